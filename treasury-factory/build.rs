@@ -11,10 +11,12 @@ fn main() {
     // Instruct Cargo to re-run this build script if specific files or env vars change.
     println!("cargo:rerun-if-changed=./public_html/index.html");
     println!("cargo:rerun-if-changed=./min_self_upgrade_contract.wat");
+    println!("cargo:rerun-if-changed=./stub_delete_contract.wat");
     println!("cargo:rerun-if-changed=../web4/treasury-web4/src/web4/index.html");
     println!("cargo:rerun-if-changed=../web4/treasury-web4/target/near/treasury_web4.wasm");
     println!("cargo:rerun-if-env-changed=POSTHOG_API_KEY");
     println!("cargo:rerun-if-env-changed=PIKESPEAK_API_KEY");
+    println!("cargo:rerun-if-env-changed=CARGO_FEATURE_TESTNET");
 
     // Set fallback API keys for test/dev runs if not already set externally.
     // These are only visible in the current build process and to the compiled crate if it reads them.
@@ -53,20 +55,8 @@ fn main() {
         .write_all(index_html_base64.as_bytes())
         .expect("Failed to write to output file");
 
-    let mut min_self_upgrade_contract_wasm = wat2wasm("./min_self_upgrade_contract.wat").unwrap();
-
-    // Remove the name section that is added by wat
-    let data_section = b"\x00\x00\x00\x00\x00\x00\x00\x00XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX";
-
-    if let Some(pos) = min_self_upgrade_contract_wasm
-        .windows(data_section.len())
-        .position(|window| window == data_section)
-    {
-        min_self_upgrade_contract_wasm =
-            min_self_upgrade_contract_wasm[..(pos + data_section.len())].to_vec();
-    } else {
-        panic!("Data section not found in the wasm file");
-    }
+    let min_self_upgrade_contract_wasm =
+        wat2wasm_with_account_placeholder("./min_self_upgrade_contract.wat");
 
     // write wasm file to use for inspection if needed
     let mut min_self_upgrade_wasm_file = fs::File::create(
@@ -92,12 +82,23 @@ fn main() {
         .write_all(min_self_upgrade_contract_wasm_base64.as_bytes())
         .expect("Failed to write to output file");
 
+    // The factory appends the beneficiary account in place of the placeholder
+    let stub_delete_contract_wasm = wat2wasm_with_account_placeholder("./stub_delete_contract.wat");
+    fs::write(
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("stub_delete_contract.wasm"),
+        &stub_delete_contract_wasm,
+    )
+    .expect("Unable to write stub delete wasm");
+
     let web4_wasm_path = "../web4/treasury-web4/target/near/treasury_web4.wasm";
+    // A testnet factory always rebuilds the web4 contract with the testnet account layout
+    let testnet = env::var("CARGO_FEATURE_TESTNET").is_ok();
     let _web4_wasm = match fs::exists(web4_wasm_path) {
-        Ok(true) => fs::read(web4_wasm_path).unwrap(),
-        Ok(false) => {
+        Ok(true) if !testnet => fs::read(web4_wasm_path).unwrap(),
+        Ok(_) => {
             let build_opts = BuildOpts::builder()
                 .manifest_path("../web4/treasury-web4/Cargo.toml".into())
+                .maybe_features(testnet.then(|| "testnet".to_string()))
                 .build();
             let build_script_opts = BuildScriptOpts::builder().build();
             let build_opts_extended = BuildOptsExtended::builder()
@@ -112,3 +113,21 @@ fn main() {
         Err(err) => panic!("Not able to build {}. Error: {}", web4_wasm_path, err),
     };
 }
+
+/// Compiles a WAT contract that ends with a data section reserving space for an
+/// account id, and strips everything after it so the account can be appended.
+fn wat2wasm_with_account_placeholder(wat_path: &str) -> Vec<u8> {
+    let wasm = wat2wasm(wat_path).unwrap();
+
+    // Remove the name section that is added by wat
+    let data_section = b"\x00\x00\x00\x00\x00\x00\x00\x00XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX";
+
+    if let Some(pos) = wasm
+        .windows(data_section.len())
+        .position(|window| window == data_section)
+    {
+        wasm[..(pos + data_section.len())].to_vec()
+    } else {
+        panic!("Data section not found in {}", wat_path);
+    }
+}
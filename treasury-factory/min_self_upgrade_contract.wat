@@ -6,10 +6,12 @@
   (import "env" "register_len" (func $register_len (param i64) (result i64)))
   (import "env" "promise_batch_create" (func $promise_batch_create (param i64 i64) (result i64)))
   (import "env" "promise_batch_action_deploy_contract" (func $promise_batch_action_deploy_contract (param i64 i64 i64)))
+  (import "env" "promise_batch_action_use_global_contract" (func $promise_batch_action_use_global_contract (param i64 i64 i64)))
+  (import "env" "storage_write" (func $storage_write (param i64 i64 i64 i64 i64) (result i64)))
   (import "env" "panic_utf8" (func $panic (param i64 i64))) ;; Import panic function to abort execution
 
-  (func (export "upgrade")
-    (local $promise_id i64)
+  ;; Aborts unless the predecessor is the account embedded at address 0
+  (func $assert_allowed_predecessor
     (local $predecessor_len i64)
     (local $allowed_len i64)
     (local $allowed_addr i32)
@@ -55,8 +57,30 @@
         )
       )
     )
+  )
 
+  ;; Stores the account embedded at address 0 as the factory of the instance, read by the web4 contract
+  (func $store_factory_account_id
+    (drop (call $storage_write
+      (i64.const 18) (i64.const 128) ;; Key at addr 128
+      (i64.load (i32.const 0)) (i64.const 8) ;; Allowed account id
+      (i64.const 0)
+    ))
+  )
+
+  ;; Creates a batch promise for the current account and returns its id
+  (func $promise_batch_create_for_self (result i64)
     ;; Read current account id into addr 1024
+    (call $current_account_id (i64.const 0))
+    (call $read_register (i64.const 0) (i64.const 1024))
+    (call $promise_batch_create (call $register_len (i64.const 0)) (i64.const 1024))
+  )
+
+  (func (export "upgrade")
+    (local $promise_id i64)
+    (call $assert_allowed_predecessor)
+    (call $store_factory_account_id)
+
     (call $current_account_id (i64.const 0))
     (call $read_register (i64.const 0) (i64.const 1024))
 
@@ -74,10 +98,30 @@
       (call $register_len (i64.const 0))
       (i64.const 2048) 
     )
+  )
+
+  ;; Switches the contract to a global contract, identified by the code hash given as raw input
+  (func (export "use_global_contract")
+    (local $promise_id i64)
+    (call $assert_allowed_predecessor)
+    (call $store_factory_account_id)
+
+    (local.set $promise_id (call $promise_batch_create_for_self))
+
+    ;; Read code hash from input into addr 2048
+    (call $input (i64.const 0))
+    (call $read_register (i64.const 0) (i64.const 2048))
+
+    (call $promise_batch_action_use_global_contract
+      (local.get $promise_id)
+      (call $register_len (i64.const 0))
+      (i64.const 2048)
+    )
     nop ;; padding to align base64
     nop ;; padding to align base64
   )
   (memory 32)
+  (data (i32.const 128) "factory_account_id")
   ;; Reserve 64 bytes for the account ID (pre-allocated empty space)
   (data (i32.const 0) "\00\00\00\00\00\00\00\00XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX")
 )
@@ -0,0 +1,87 @@
+use near_sdk::{
+    env, near,
+    serde_json::{json, Value},
+    NearToken, PublicKey,
+};
+
+use crate::{AdminPermission, Contract, ContractExt};
+
+/// Gas allowance of function-call keys added to new instances.
+const FUNCTION_CALL_KEY_ALLOWANCE: NearToken = NearToken::from_millinear(250);
+/// The instance methods a function-call key may call.
+const FUNCTION_CALL_KEY_METHOD_NAMES: &str = "update_widgets,self_upgrade";
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum InstanceKeyAccess {
+    #[default]
+    FullAccess,
+    /// Can only call `update_widgets` and `self_upgrade` on the instance
+    FunctionCall,
+    None,
+}
+
+/// The access keys added to a new instance account for the creator's public key
+/// and for the admin public key of the factory.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct InstanceAccessKeys {
+    #[serde(default)]
+    pub creator_key: InstanceKeyAccess,
+    #[serde(default)]
+    pub admin_key: InstanceKeyAccess,
+}
+
+impl Contract {
+    /// The `full_access_keys` and `limited_access_keys` options of
+    /// `create_account_advanced` for the instance account of `name`.
+    pub(crate) fn internal_instance_access_key_options(&self, name: &str) -> Value {
+        let creation = self
+            .creations
+            .get(name)
+            .unwrap_or_else(|| env::panic_str(&format!("No creation found for {}", name)));
+
+        let mut keys = vec![(
+            creation.creator_public_key.clone(),
+            creation.access_keys.creator_key,
+        )];
+        // Uses the admin key at the time the account is created, so that a rotated
+        // key is never added by resumed creations
+        if let Some(admin_public_key) = &self.admin_public_key {
+            keys.push((admin_public_key.clone(), creation.access_keys.admin_key));
+        }
+
+        let mut full_access_keys: Vec<PublicKey> = vec![];
+        let mut limited_access_keys: Vec<Value> = vec![];
+        for (public_key, access) in keys {
+            match access {
+                InstanceKeyAccess::FullAccess => full_access_keys.push(public_key),
+                InstanceKeyAccess::FunctionCall => limited_access_keys.push(json!({
+                    "public_key": public_key,
+                    "allowance": FUNCTION_CALL_KEY_ALLOWANCE,
+                    "receiver_id": creation.instance_account_id,
+                    "method_names": FUNCTION_CALL_KEY_METHOD_NAMES
+                })),
+                InstanceKeyAccess::None => {}
+            }
+        }
+        json!({
+            "full_access_keys": full_access_keys,
+            "limited_access_keys": limited_access_keys
+        })
+    }
+}
+
+#[near]
+impl Contract {
+    pub fn get_admin_public_key(&self) -> Option<PublicKey> {
+        self.admin_public_key.clone()
+    }
+
+    /// Rotates the admin key added to new instances, or stops adding one if `None`.
+    /// Existing instances keep the keys they were created with.
+    pub fn set_admin_public_key(&mut self, admin_public_key: Option<PublicKey>) {
+        self.assert_admin_action(AdminPermission::AccessKeys, "set_admin_public_key");
+        self.admin_public_key = admin_public_key;
+    }
+}
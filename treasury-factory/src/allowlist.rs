@@ -0,0 +1,139 @@
+use near_sdk::{env, near, AccountId};
+
+use crate::{AdminPermission, Contract, ContractExt};
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrustedAccountKind {
+    SputnikDaoFactory,
+    SocialDb,
+    WidgetReference,
+}
+
+impl TrustedAccountKind {
+    fn parameter_name(&self) -> &'static str {
+        match self {
+            TrustedAccountKind::SputnikDaoFactory => "sputnik_dao_factory_account_id",
+            TrustedAccountKind::SocialDb => "social_db_account_id",
+            TrustedAccountKind::WidgetReference => "widget_reference_account_id",
+        }
+    }
+}
+
+/// Accounts that may be passed to `create_instance`, so that the creation
+/// deposits can only be sent to contracts approved by the factory owner.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug)]
+pub struct TrustedAccounts {
+    pub sputnik_dao_factory_account_ids: Vec<AccountId>,
+    pub social_db_account_ids: Vec<AccountId>,
+    pub widget_reference_account_ids: Vec<AccountId>,
+}
+
+impl Default for TrustedAccounts {
+    #[cfg(not(feature = "testnet"))]
+    fn default() -> Self {
+        Self {
+            sputnik_dao_factory_account_ids: vec!["sputnik-dao.near".parse().unwrap()],
+            social_db_account_ids: vec!["social.near".parse().unwrap()],
+            widget_reference_account_ids: vec!["bootstrap.treasury-factory.near".parse().unwrap()],
+        }
+    }
+
+    #[cfg(feature = "testnet")]
+    fn default() -> Self {
+        Self {
+            sputnik_dao_factory_account_ids: vec!["sputnikv2.testnet".parse().unwrap()],
+            social_db_account_ids: vec!["v1.social08.testnet".parse().unwrap()],
+            widget_reference_account_ids: vec!["bootstrap.treasury-factory.testnet"
+                .parse()
+                .unwrap()],
+        }
+    }
+}
+
+impl TrustedAccounts {
+    fn accounts_mut(&mut self, kind: TrustedAccountKind) -> &mut Vec<AccountId> {
+        match kind {
+            TrustedAccountKind::SputnikDaoFactory => &mut self.sputnik_dao_factory_account_ids,
+            TrustedAccountKind::SocialDb => &mut self.social_db_account_ids,
+            TrustedAccountKind::WidgetReference => &mut self.widget_reference_account_ids,
+        }
+    }
+
+    fn accounts(&self, kind: TrustedAccountKind) -> &Vec<AccountId> {
+        match kind {
+            TrustedAccountKind::SputnikDaoFactory => &self.sputnik_dao_factory_account_ids,
+            TrustedAccountKind::SocialDb => &self.social_db_account_ids,
+            TrustedAccountKind::WidgetReference => &self.widget_reference_account_ids,
+        }
+    }
+}
+
+impl Contract {
+    /// Parses `account_id` and panics unless it is trusted for the given kind.
+    pub(crate) fn internal_assert_trusted_account(
+        &self,
+        kind: TrustedAccountKind,
+        account_id: &str,
+    ) -> AccountId {
+        let parsed_account_id: AccountId = account_id.parse().unwrap_or_else(|_| {
+            env::panic_str(&format!(
+                "{} is not a valid account id: {}",
+                kind.parameter_name(),
+                account_id
+            ))
+        });
+        if !self
+            .trusted_accounts
+            .accounts(kind)
+            .contains(&parsed_account_id)
+        {
+            env::panic_str(&format!(
+                "{} {} is not accepted by the factory",
+                kind.parameter_name(),
+                account_id
+            ));
+        }
+        parsed_account_id
+    }
+
+    pub(crate) fn internal_assert_trusted_creation_accounts(
+        &self,
+        sputnik_dao_factory_account_id: &str,
+        social_db_account_id: &str,
+        widget_reference_account_id: &str,
+    ) {
+        self.internal_assert_trusted_account(
+            TrustedAccountKind::SputnikDaoFactory,
+            sputnik_dao_factory_account_id,
+        );
+        self.internal_assert_trusted_account(TrustedAccountKind::SocialDb, social_db_account_id);
+        self.internal_assert_trusted_account(
+            TrustedAccountKind::WidgetReference,
+            widget_reference_account_id,
+        );
+    }
+}
+
+#[near]
+impl Contract {
+    pub fn get_trusted_accounts(&self) -> TrustedAccounts {
+        self.trusted_accounts.clone()
+    }
+
+    pub fn add_trusted_account(&mut self, kind: TrustedAccountKind, account_id: AccountId) {
+        self.assert_admin_action(AdminPermission::TrustedAccounts, "add_trusted_account");
+        let accounts = self.trusted_accounts.accounts_mut(kind);
+        if !accounts.contains(&account_id) {
+            accounts.push(account_id);
+        }
+    }
+
+    pub fn remove_trusted_account(&mut self, kind: TrustedAccountKind, account_id: AccountId) {
+        self.assert_admin_action(AdminPermission::TrustedAccounts, "remove_trusted_account");
+        self.trusted_accounts
+            .accounts_mut(kind)
+            .retain(|trusted_account_id| trusted_account_id != &account_id);
+    }
+}
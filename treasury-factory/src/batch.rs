@@ -0,0 +1,183 @@
+use near_sdk::{env, near, require, AccountId, Gas, Promise, PromiseOrValue};
+
+use crate::{
+    Contract, ContractExt, CreateInstanceArgs, CreationPayment, FactoryEvent, InstanceOutcome,
+};
+
+const MAX_BATCH_SIZE: usize = 10;
+/// Gas for every step of one creation, of which the sputnik DAO creation alone takes
+/// 100 Tgas, so a transaction can only run one complete creation.
+const INSTANCE_CREATION_GAS: Gas = Gas::from_tgas(250);
+const CREATE_INSTANCES_CALLBACK_GAS: Gas = Gas::from_tgas(10);
+
+/// One instance of a `create_instances` batch, with the arguments of `create_instance`.
+pub type InstanceSpec = CreateInstanceArgs;
+
+#[near(serializers = [json])]
+#[derive(Debug)]
+pub struct InstanceCreationReport {
+    pub name: String,
+    pub instance_account_id: AccountId,
+    pub outcome: InstanceOutcome,
+    /// The creation has not started yet, see `start_instance_creation`. Its outcome
+    /// stays `Pending` until it is started, or it can be cancelled with
+    /// `cancel_queued_creation`.
+    pub queued: bool,
+    /// Whether every step of the creation succeeded
+    pub complete: bool,
+}
+
+impl Contract {
+    fn internal_creation_report(&self, name: &str) -> InstanceCreationReport {
+        let creation = self
+            .creations
+            .get(name)
+            .unwrap_or_else(|| env::panic_str(&format!("No creation found for {}", name)));
+        InstanceCreationReport {
+            name: name.to_string(),
+            instance_account_id: creation.instance_account_id.clone(),
+            outcome: self
+                .instances
+                .get(name)
+                .map(|instance| instance.outcome.clone())
+                .unwrap_or(InstanceOutcome::Pending),
+            queued: creation.queued,
+            complete: creation.is_complete(),
+        }
+    }
+}
+
+#[near]
+impl Contract {
+    /// Creates several instances for the caller, who attaches the combined creation
+    /// cost and fee of the instances that are not paid with a voucher. The batch starts as many
+    /// creations as its gas covers, and queues the others for `start_instance_creation`.
+    /// Each creation refunds its own failed steps. Returns a report for each instance
+    /// once the started creations are done.
+    #[payable]
+    pub fn create_instances(
+        &mut self,
+        instances: Vec<InstanceSpec>,
+    ) -> PromiseOrValue<Vec<InstanceCreationReport>> {
+        require!(
+            !instances.is_empty() && instances.len() <= MAX_BATCH_SIZE,
+            format!(
+                "A batch must create between 1 and {} instances",
+                MAX_BATCH_SIZE
+            )
+        );
+        let creator_id = env::predecessor_account_id();
+        let paid_instances = instances
+            .iter()
+            .filter(|instance| instance.options.voucher.is_none())
+            .count();
+        self.internal_take_deposit(
+            self.creation_cost
+                .total()
+                .saturating_add(self.fee_config.creation_fee)
+                .saturating_mul(paid_instances as u128),
+            "create treasury instances",
+        );
+        let names: Vec<String> = instances
+            .into_iter()
+            .map(|instance| {
+                self.internal_register_creation(
+                    creator_id.clone(),
+                    instance,
+                    CreationPayment::Batch,
+                )
+            })
+            .collect();
+
+        let mut remaining_gas = env::prepaid_gas()
+            .saturating_sub(env::used_gas())
+            .saturating_sub(CREATE_INSTANCES_CALLBACK_GAS);
+        let mut creations: Option<Promise> = None;
+        for name in &names {
+            if remaining_gas < INSTANCE_CREATION_GAS {
+                self.internal_get_creation_mut(name).queued = true;
+                continue;
+            }
+            remaining_gas = remaining_gas.saturating_sub(INSTANCE_CREATION_GAS);
+            let creation = self.internal_check_dao_step(name);
+            creations = Some(match creations {
+                Some(creations) => creations.and(creation),
+                None => creation,
+            });
+        }
+
+        match creations {
+            Some(creations) => creations
+                .then(
+                    Self::ext(env::current_account_id())
+                        .with_static_gas(CREATE_INSTANCES_CALLBACK_GAS)
+                        .with_unused_gas_weight(0)
+                        .create_instances_callback(names),
+                )
+                .into(),
+            None => PromiseOrValue::Value(
+                names
+                    .iter()
+                    .map(|name| self.internal_creation_report(name))
+                    .collect(),
+            ),
+        }
+    }
+
+    #[private]
+    pub fn create_instances_callback(&self, names: Vec<String>) -> Vec<InstanceCreationReport> {
+        names
+            .iter()
+            .map(|name| self.internal_creation_report(name))
+            .collect()
+    }
+
+    /// Starts a creation that was queued by `create_instances`. It is already paid for,
+    /// so anyone can start it by attaching enough gas.
+    pub fn start_instance_creation(&mut self, name: String) -> PromiseOrValue<()> {
+        require!(
+            env::prepaid_gas() >= INSTANCE_CREATION_GAS,
+            format!(
+                "Starting an instance creation requires {} of gas",
+                INSTANCE_CREATION_GAS
+            )
+        );
+        let creation = self.internal_get_creation_mut(&name);
+        require!(creation.queued, "Instance creation is not queued");
+        creation.queued = false;
+        self.internal_run_creation_steps(&name)
+    }
+
+    /// Cancels a creation that was queued by `create_instances`, which only its creator
+    /// can do. The creation cost and fee are refunded, or the voucher that paid for it
+    /// restored, and the name is deregistered.
+    pub fn cancel_queued_creation(&mut self, name: String) -> PromiseOrValue<()> {
+        let creator_id = self
+            .instances
+            .get(&name)
+            .map(|record| record.creator_id.clone())
+            .unwrap_or_else(|| env::panic_str(&format!("No creation found for {}", name)));
+        require!(
+            creator_id == env::predecessor_account_id(),
+            "Only the creator can cancel a queued creation"
+        );
+        let creation = self.internal_get_creation_mut(&name);
+        require!(creation.queued, "Instance creation is not queued");
+        let instance_account_id = creation.instance_account_id.clone();
+        let refund_amount = creation
+            .required_deposit(&creation.creation_cost)
+            .saturating_add(self.internal_release_fee(&name));
+
+        let refund = self.internal_refund(&name, refund_amount);
+        self.internal_restore_voucher(&name);
+        self.creations.remove(&name);
+        self.instances.remove(&name);
+        self.internal_remove_from_creator(&creator_id, &name);
+        FactoryEvent::CreationCancelled {
+            name,
+            instance_account_id,
+        }
+        .emit();
+        refund
+    }
+}
@@ -0,0 +1,69 @@
+use near_sdk::near;
+
+const MAX_NAME_LENGTH: usize = 64;
+const MAX_DESCRIPTION_LENGTH: usize = 1000;
+const MAX_IPFS_CID_LENGTH: usize = 128;
+const MAX_TAGS: usize = 10;
+const MAX_TAG_LENGTH: usize = 32;
+
+/// Social metadata of a new instance in place of the "NEAR Treasury" defaults, passed
+/// to the `update_widgets` call of the instance, so the web4 page is branded from the
+/// first load.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InstanceBranding {
+    /// Display name of the app
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// IPFS CID of the logo
+    pub ipfs_cid: Option<String>,
+    /// Added to the `app` and `neartreasury` tags
+    pub tags: Option<Vec<String>>,
+}
+
+impl InstanceBranding {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(name) = &self.name {
+            if name.trim().is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+                return Err(format!(
+                    "Name must have between 1 and {} characters",
+                    MAX_NAME_LENGTH
+                ));
+            }
+        }
+        if let Some(description) = &self.description {
+            if description.chars().count() > MAX_DESCRIPTION_LENGTH {
+                return Err(format!(
+                    "Description cannot be longer than {} characters",
+                    MAX_DESCRIPTION_LENGTH
+                ));
+            }
+        }
+        if let Some(ipfs_cid) = &self.ipfs_cid {
+            if ipfs_cid.is_empty()
+                || ipfs_cid.len() > MAX_IPFS_CID_LENGTH
+                || !ipfs_cid.chars().all(|c| c.is_ascii_alphanumeric())
+            {
+                return Err(format!("{} is not a valid IPFS CID", ipfs_cid));
+            }
+        }
+        let tags = self.tags.as_deref().unwrap_or_default();
+        if tags.len() > MAX_TAGS {
+            return Err(format!("At most {} tags are allowed", MAX_TAGS));
+        }
+        for tag in tags {
+            if tag.is_empty()
+                || tag.len() > MAX_TAG_LENGTH
+                || !tag
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+            {
+                return Err(format!(
+                    "Tag {} must have up to {} lowercase letters, digits or -",
+                    tag, MAX_TAG_LENGTH
+                ));
+            }
+        }
+        Ok(())
+    }
+}
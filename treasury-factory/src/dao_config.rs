@@ -0,0 +1,248 @@
+use near_sdk::{
+    base64::{engine::general_purpose, Engine},
+    env,
+    json_types::{U128, U64},
+    near,
+    serde_json::{json, Map, Value},
+    AccountId, NearToken,
+};
+
+use crate::{Contract, PolicyTemplateArgs};
+
+const PROPOSAL_KIND_LABELS: [&str; 17] = [
+    "config",
+    "policy",
+    "add_member_to_role",
+    "remove_member_from_role",
+    "call",
+    "upgrade_self",
+    "upgrade_remote",
+    "transfer",
+    "set_vote_token",
+    "add_bounty",
+    "bounty_done",
+    "vote",
+    "factory_info_update",
+    "policy_add_or_update_role",
+    "policy_remove_role",
+    "policy_update_default_vote_policy",
+    "policy_update_parameters",
+];
+
+const PROPOSAL_ACTIONS: [&str; 7] = [
+    "AddProposal",
+    "RemoveProposal",
+    "VoteApprove",
+    "VoteReject",
+    "VoteRemove",
+    "Finalize",
+    "MoveToHub",
+];
+
+/// The share of votes needed to approve a proposal, as in sputnik's `WeightOrRatio`.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug)]
+pub enum VoteThreshold {
+    Weight(U128),
+    Ratio(u64, u64),
+}
+
+impl VoteThreshold {
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            VoteThreshold::Weight(weight) if weight.0 == 0 => {
+                Err("Vote threshold weight must be at least 1".to_string())
+            }
+            VoteThreshold::Ratio(numerator, denominator)
+                if *numerator == 0 || numerator > denominator =>
+            {
+                Err(format!(
+                    "Vote threshold ratio {}/{} must be greater than 0 and at most 1",
+                    numerator, denominator
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn to_vote_policy(&self) -> Value {
+        json!({
+            "weight_kind": "RoleWeight",
+            "quorum": "0",
+            "threshold": match self {
+                VoteThreshold::Weight(weight) => json!(weight),
+                VoteThreshold::Ratio(numerator, denominator) => json!([numerator, denominator]),
+            }
+        })
+    }
+}
+
+#[near(serializers = [json])]
+#[derive(Clone, Debug)]
+pub struct DaoRole {
+    pub name: String,
+    pub members: Vec<AccountId>,
+    /// Sputnik permissions such as `transfer:AddProposal` or `*:VoteApprove`
+    pub permissions: Vec<String>,
+    /// Applies to every proposal kind this role has a permission for. The
+    /// default vote threshold of the DAO is used if not set.
+    pub vote_threshold: Option<VoteThreshold>,
+}
+
+/// Typed alternative to passing base64 encoded sputnik `create` arguments. The DAO
+/// is always named after the instance, so its name is not part of the config.
+#[near(serializers = [json])]
+#[derive(Clone, Debug)]
+pub struct DaoConfig {
+    pub purpose: String,
+    pub roles: Vec<DaoRole>,
+    pub default_vote_threshold: VoteThreshold,
+    pub proposal_bond: NearToken,
+    /// How long proposals are open for voting, in nanoseconds
+    pub voting_period: U64,
+}
+
+fn validate_permission(permission: &str) -> Result<(), String> {
+    let Some((label, action)) = permission.split_once(':') else {
+        return Err(format!(
+            "Permission {} must have the format <proposal kind>:<action>",
+            permission
+        ));
+    };
+    if label != "*" && !PROPOSAL_KIND_LABELS.contains(&label) {
+        return Err(format!(
+            "Unknown proposal kind {} in permission {}",
+            label, permission
+        ));
+    }
+    if action != "*" && !PROPOSAL_ACTIONS.contains(&action) {
+        return Err(format!(
+            "Unknown action {} in permission {}",
+            action, permission
+        ));
+    }
+    Ok(())
+}
+
+impl DaoConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.roles.is_empty() {
+            return Err("DAO config must have at least one role".to_string());
+        }
+        for (index, role) in self.roles.iter().enumerate() {
+            if role.name.is_empty() {
+                return Err("Role names cannot be empty".to_string());
+            }
+            if self.roles[..index]
+                .iter()
+                .any(|other_role| other_role.name == role.name)
+            {
+                return Err(format!("Role {} is defined more than once", role.name));
+            }
+            for permission in role.permissions.iter() {
+                validate_permission(permission)?;
+            }
+            if let Some(vote_threshold) = &role.vote_threshold {
+                vote_threshold.validate()?;
+            }
+        }
+        if !self.roles.iter().any(|role| {
+            !role.members.is_empty()
+                && role.permissions.iter().any(|permission| {
+                    permission.ends_with(":VoteApprove") || permission.ends_with(":*")
+                })
+        }) {
+            return Err("At least one member must be able to approve proposals".to_string());
+        }
+        self.default_vote_threshold.validate()?;
+        if self.voting_period.0 == 0 {
+            return Err("Voting period must be greater than zero".to_string());
+        }
+        Ok(())
+    }
+
+    /// Builds the base64 encoded arguments of the sputnik DAO factory `create` method.
+    pub fn to_create_dao_args(&self, name: &str) -> String {
+        let roles: Vec<Value> = self
+            .roles
+            .iter()
+            .map(|role| {
+                let mut vote_policy = Map::new();
+                if let Some(vote_threshold) = &role.vote_threshold {
+                    for permission in role.permissions.iter() {
+                        let label = permission.split(':').next().unwrap_or_default();
+                        if label != "*" {
+                            vote_policy.insert(label.to_string(), vote_threshold.to_vote_policy());
+                        }
+                    }
+                }
+                json!({
+                    "name": role.name,
+                    "kind": { "Group": role.members },
+                    "permissions": role.permissions,
+                    "vote_policy": vote_policy
+                })
+            })
+            .collect();
+
+        let args = json!({
+            "config": {
+                "name": name,
+                "purpose": self.purpose,
+                "metadata": ""
+            },
+            "policy": {
+                "roles": roles,
+                "default_vote_policy": self.default_vote_threshold.to_vote_policy(),
+                "proposal_bond": self.proposal_bond,
+                "proposal_period": self.voting_period,
+                "bounty_bond": self.proposal_bond,
+                "bounty_forgiveness_period": self.voting_period
+            }
+        });
+        general_purpose::STANDARD.encode(args.to_string())
+    }
+}
+
+impl Contract {
+    /// Returns the sputnik `create` arguments from the raw base64 string, the typed
+    /// config or a policy template, panicking before any promise is created if they
+    /// are invalid.
+    pub(crate) fn internal_resolve_create_dao_args(
+        &self,
+        name: &str,
+        create_dao_args: Option<String>,
+        dao_config: Option<DaoConfig>,
+        policy_template: Option<PolicyTemplateArgs>,
+    ) -> String {
+        match (create_dao_args, dao_config, policy_template) {
+            (Some(create_dao_args), None, None) => {
+                let is_json = general_purpose::STANDARD
+                    .decode(&create_dao_args)
+                    .ok()
+                    .and_then(|decoded| near_sdk::serde_json::from_slice::<Value>(&decoded).ok())
+                    .is_some_and(|args| args.is_object());
+                if !is_json {
+                    env::panic_str("create_dao_args must be base64 encoded JSON");
+                }
+                create_dao_args
+            }
+            (None, Some(dao_config), None) => {
+                if let Err(error) = dao_config.validate() {
+                    env::panic_str(&format!("Invalid dao_config: {}", error));
+                }
+                dao_config.to_create_dao_args(name)
+            }
+            (None, None, Some(policy_template)) => {
+                let dao_config = self.internal_expand_policy_template(name, policy_template);
+                if let Err(error) = dao_config.validate() {
+                    env::panic_str(&format!("Invalid policy_template: {}", error));
+                }
+                dao_config.to_create_dao_args(name)
+            }
+            _ => env::panic_str(
+                "Exactly one of create_dao_args, dao_config or policy_template must be provided",
+            ),
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use near_sdk::{json_types::Base58CryptoHash, near, AccountId, NearToken};
+
+use crate::{AdminPermission, TokenPayment};
+
+/// NEP-297 events emitted by the factory, logged as `EVENT_JSON:{...}`.
+#[near(event_json(standard = "treasury-factory"))]
+pub enum FactoryEvent {
+    /// The DAO of an instance exists and the instance is registered as created
+    #[event_version("1.0.0")]
+    InstanceCreated {
+        name: String,
+        instance_account_id: AccountId,
+        dao_account_id: AccountId,
+        creator_id: AccountId,
+    },
+    #[event_version("1.0.0")]
+    AccountCreationFailed {
+        name: String,
+        instance_account_id: AccountId,
+    },
+    #[event_version("1.0.0")]
+    DaoCreationFailed {
+        name: String,
+        instance_account_id: AccountId,
+        dao_account_id: AccountId,
+    },
+    /// A queued creation was cancelled by its creator before it started, and its
+    /// name deregistered
+    #[event_version("1.0.0")]
+    CreationCancelled {
+        name: String,
+        instance_account_id: AccountId,
+    },
+    #[event_version("1.0.0")]
+    WidgetsUpdated {
+        name: String,
+        instance_account_id: AccountId,
+    },
+    /// `amount` of the creation deposits is returned, or `token_refund` if the
+    /// creation was paid with tokens
+    #[event_version("1.0.0")]
+    RefundIssued {
+        name: String,
+        account_id: AccountId,
+        amount: NearToken,
+        token_refund: Option<TokenPayment>,
+    },
+    /// An instance was decommissioned by its DAO, and its account deleted in favour
+    /// of `beneficiary_id`
+    #[event_version("1.0.0")]
+    InstanceRetired {
+        name: String,
+        instance_account_id: AccountId,
+        beneficiary_id: AccountId,
+    },
+    /// An instance switched to the web4 contract of `version`, which is `None` for
+    /// the web4 contract compiled into the factory
+    #[event_version("1.0.0")]
+    InstanceUpgraded {
+        name: String,
+        instance_account_id: AccountId,
+        version: Option<String>,
+    },
+    #[event_version("1.0.0")]
+    OwnershipTransferStarted {
+        owner_id: AccountId,
+        pending_owner_id: AccountId,
+    },
+    #[event_version("1.0.0")]
+    OwnershipTransferCancelled {
+        owner_id: AccountId,
+        pending_owner_id: AccountId,
+    },
+    #[event_version("1.0.0")]
+    OwnershipTransferred {
+        previous_owner_id: AccountId,
+        owner_id: AccountId,
+    },
+    #[event_version("1.0.0")]
+    AdminSet {
+        account_id: AccountId,
+        permissions: Vec<AdminPermission>,
+    },
+    #[event_version("1.0.0")]
+    AdminRemoved { account_id: AccountId },
+    /// A method that requires an admin permission was called by the owner or an admin
+    #[event_version("1.0.0")]
+    AdminAction {
+        caller_id: AccountId,
+        permission: AdminPermission,
+        method: String,
+    },
+    #[event_version("1.0.0")]
+    SponsorshipCreated {
+        sponsorship_id: u64,
+        sponsor_id: AccountId,
+        creations: u32,
+    },
+    #[event_version("1.0.0")]
+    VoucherRedeemed {
+        sponsorship_id: u64,
+        name: String,
+        beneficiary_id: AccountId,
+    },
+    /// A refund of the sponsored creation `name` was kept for resuming it, see
+    /// `Sponsorship::credit`
+    #[event_version("1.0.0")]
+    SponsorshipCredited {
+        sponsorship_id: u64,
+        name: String,
+        amount: NearToken,
+    },
+    /// The sponsored creation `name` failed before its account was created, so its
+    /// voucher or beneficiary can be used again
+    #[event_version("1.0.0")]
+    VoucherRestored {
+        sponsorship_id: u64,
+        name: String,
+        beneficiary_id: AccountId,
+    },
+    #[event_version("1.0.0")]
+    SponsorshipClosed {
+        sponsorship_id: u64,
+        refund: NearToken,
+    },
+    /// The fee of a created instance was collected, of which `referral_share` is
+    /// accrued to `referrer_id`
+    #[event_version("1.0.0")]
+    FeeCollected {
+        name: String,
+        fee: NearToken,
+        referrer_id: Option<AccountId>,
+        referral_share: NearToken,
+    },
+    #[event_version("1.0.0")]
+    ReferralClaimed {
+        referrer_id: AccountId,
+        amount: NearToken,
+    },
+    #[event_version("1.0.0")]
+    FeesWithdrawn {
+        receiver_id: AccountId,
+        amount: NearToken,
+    },
+    #[event_version("1.0.0")]
+    ParentAccountRegistered {
+        parent_account_id: AccountId,
+        creators: Vec<AccountId>,
+    },
+    #[event_version("1.0.0")]
+    ParentAccountUnregistered { parent_account_id: AccountId },
+    #[event_version("1.0.0")]
+    FactoryUpgradeStarted {
+        caller_id: AccountId,
+        code_hash: Base58CryptoHash,
+    },
+    #[event_version("1.0.0")]
+    StateMigrated { from_version: u32, to_version: u32 },
+}
@@ -0,0 +1,170 @@
+use near_sdk::{
+    env, near, require, serde_json::Value, AccountId, Gas, NearToken, Promise, PromiseOrValue,
+    PromiseResult,
+};
+
+use crate::{
+    sputnik_dao_contract, Contract, ContractExt, CreationStatus, InstanceAccessKeys,
+    InstanceOutcome, StepStatus,
+};
+
+/// Whether `account_id` is a member of a group role of a sputnik DAO policy that
+/// can approve proposals, like the council of the DAO.
+fn is_dao_approver(policy: &Value, account_id: &AccountId) -> bool {
+    policy["roles"]
+        .as_array()
+        .map(|roles| {
+            roles.iter().any(|role| {
+                let can_approve = role["permissions"]
+                    .as_array()
+                    .map(|permissions| {
+                        permissions
+                            .iter()
+                            .any(|permission| permission == "*:*" || permission == "*:VoteApprove")
+                    })
+                    .unwrap_or(false);
+                can_approve
+                    && role["kind"]["Group"]
+                        .as_array()
+                        .map(|members| members.iter().any(|member| member == account_id.as_str()))
+                        .unwrap_or(false)
+            })
+        })
+        .unwrap_or(false)
+}
+
+/// The optional settings of an instance creation for an existing DAO.
+#[near(serializers = [json])]
+#[derive(Default)]
+pub struct ExistingDaoOptions {
+    pub access_keys: Option<InstanceAccessKeys>,
+    /// Receives the referral share of the creation fee
+    pub referrer_id: Option<AccountId>,
+}
+
+impl Contract {
+    pub(crate) fn internal_verify_dao_step(&mut self, name: &str) -> Promise {
+        let creation = self.internal_get_creation_mut(name);
+        creation.create_dao = StepStatus::InProgress;
+        let dao_account_id: AccountId =
+            format!("{}.{}", name, creation.sputnik_dao_factory_account_id)
+                .parse()
+                .unwrap();
+
+        sputnik_dao_contract::ext(dao_account_id)
+            .with_static_gas(Gas::from_tgas(10))
+            .get_policy()
+            .then(Self::ext(env::current_account_id()).verify_dao_callback(name.to_string()))
+    }
+}
+
+#[near]
+impl Contract {
+    /// Creates a web4 instance for a sputnik DAO that already exists, instead of
+    /// creating a new DAO. The caller must be a member of a DAO group that can
+    /// approve proposals.
+    #[payable]
+    pub fn create_instance_for_existing_dao(
+        &mut self,
+        name: String,
+        sputnik_dao_factory_account_id: String,
+        social_db_account_id: String,
+        widget_reference_account_id: String,
+        options: Option<ExistingDaoOptions>,
+    ) -> Promise {
+        let ExistingDaoOptions {
+            access_keys,
+            referrer_id,
+        } = options.unwrap_or_default();
+        self.internal_assert_trusted_creation_accounts(
+            &sputnik_dao_factory_account_id,
+            &social_db_account_id,
+            &widget_reference_account_id,
+        );
+        self.internal_assert_valid_name(&name, None, &sputnik_dao_factory_account_id);
+        require!(
+            referrer_id.as_ref() != Some(&env::predecessor_account_id()),
+            "The creator cannot be their own referrer"
+        );
+        let creation_cost = self.creation_cost.clone();
+        let fee = self.fee_config.creation_fee;
+        self.internal_take_deposit(
+            creation_cost.total_for_existing_dao().saturating_add(fee),
+            "create treasury instance for existing DAO",
+        );
+        self.internal_hold_fee(fee);
+
+        let new_instance_contract_id = self.network_config.instance_account_id(&name);
+        let sputnik_dao_contract_id: AccountId =
+            format!("{}.{}", name, sputnik_dao_factory_account_id)
+                .parse()
+                .unwrap();
+        self.internal_start_creation(
+            CreationStatus {
+                name: name.clone(),
+                instance_account_id: new_instance_contract_id,
+                refund_account_id: env::predecessor_account_id(),
+                creator_public_key: env::signer_account_pk(),
+                sputnik_dao_factory_account_id,
+                social_db_account_id,
+                widget_reference_account_id,
+                create_dao_args: String::new(),
+                existing_dao: true,
+                // The DAO is verified before the account is created, so no stub is left to delete
+                delete_stub_on_failure: false,
+                access_keys: access_keys.unwrap_or_default(),
+                branding: None,
+                token_payment: None,
+                sponsorship_id: None,
+                creation_cost,
+                fee,
+                released_fee: NearToken::from_near(0),
+                referrer_id,
+                refunded: NearToken::from_near(0),
+                queued: false,
+                create_account: StepStatus::NotStarted,
+                upgrade_instance: StepStatus::NotStarted,
+                create_dao: StepStatus::NotStarted,
+                update_widgets: StepStatus::NotStarted,
+            },
+            env::predecessor_account_id(),
+            sputnik_dao_contract_id,
+        );
+
+        self.internal_verify_dao_step(&name)
+    }
+
+    #[private]
+    pub fn verify_dao_callback(&mut self, name: String) -> PromiseOrValue<()> {
+        let creator_id = self
+            .get_instance(name.clone())
+            .map(|instance| instance.creator_id)
+            .unwrap_or_else(|| env::panic_str(&format!("No instance found for {}", name)));
+        let is_member = match env::promise_result(0) {
+            PromiseResult::Successful(result) => near_sdk::serde_json::from_slice::<Value>(&result)
+                .map(|policy| is_dao_approver(&policy, &creator_id))
+                .unwrap_or(false),
+            _ => false,
+        };
+
+        let creation = self.internal_get_creation_mut(&name);
+        if is_member {
+            creation.create_dao = StepStatus::Succeeded;
+            return self.internal_create_account_step(&name).into();
+        }
+
+        creation.create_dao = StepStatus::Failed;
+        let refund_amount = creation.required_deposit(&creation.creation_cost);
+        env::log_str(
+            format!(
+                "{} is not an approver of treasury account {}.{}",
+                creator_id, name, creation.sputnik_dao_factory_account_id
+            )
+            .as_str(),
+        );
+        let refund_amount = refund_amount.saturating_add(self.internal_release_fee(&name));
+        self.internal_set_instance_outcome(&name, InstanceOutcome::DaoVerificationFailed);
+        self.internal_update_stats(|counters| counters.failed.verify_dao += 1);
+        self.internal_refund(&name, refund_amount)
+    }
+}
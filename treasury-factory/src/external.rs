@@ -1,20 +1,42 @@
 // Find all our documentation at https://docs.near.org
-use near_sdk::{ext_contract, Promise};
+use near_sdk::{ext_contract, json_types::U128, serde_json::Value, AccountId, Promise};
+
+use crate::InstanceBranding;
 pub const NO_DEPOSIT: u128 = 0;
 pub const XCC_SUCCESS: u64 = 1;
 
 // Validator interface, for cross-contract calls
 #[ext_contract(sputnik_dao)]
+#[allow(dead_code)]
 trait SputnikDao {
     fn create(&self, name: String, args: String);
 }
 
+#[ext_contract(sputnik_dao_contract)]
+#[allow(dead_code)]
+trait SputnikDaoContract {
+    fn get_policy(&self) -> Value;
+
+    fn get_config(&self) -> Value;
+}
+
 #[ext_contract(instance_contract)]
+#[allow(dead_code)]
 trait InstanceContract {
     fn update_widgets(
         &self,
         widget_reference_account_id: String,
         social_db_account_id: String,
         set_social_metadata_defaults: bool,
+        social_metadata: Option<InstanceBranding>,
+        sputnik_dao_factory_account_id: String,
     ) -> Promise;
+
+    fn factory_upgrade(&mut self, version: Option<String>) -> bool;
+}
+
+#[ext_contract(fungible_token)]
+#[allow(dead_code)]
+trait FungibleToken {
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
 }
@@ -0,0 +1,205 @@
+use near_sdk::{env, near, require, AccountId, NearToken, Promise};
+
+use crate::{AdminPermission, Contract, ContractExt, FactoryEvent};
+
+const BASIS_POINTS: u16 = 10_000;
+
+/// The fee charged by the factory for a creation, on top of the creation deposits.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FeeConfig {
+    pub creation_fee: NearToken,
+    /// Share of the fee accrued to the referrer of a creation, in basis points
+    pub referral_share_bps: u16,
+}
+
+impl FeeConfig {
+    /// The part of `fee` that goes to the referrer.
+    pub fn referral_share(&self, fee: NearToken) -> NearToken {
+        NearToken::from_yoctonear(
+            fee.as_yoctonear() / u128::from(BASIS_POINTS) * u128::from(self.referral_share_bps),
+        )
+    }
+}
+
+/// Totals of every fee movement since the factory started charging fees.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FeeAccounting {
+    /// Fees of instances that are not created yet, which are returned with the
+    /// refunds if their creation fails for good
+    pub pending: NearToken,
+    /// Fees of created instances, including the referral shares
+    pub collected: NearToken,
+    pub referral_accrued: NearToken,
+    pub referral_claimed: NearToken,
+    pub withdrawn: NearToken,
+}
+
+impl FeeAccounting {
+    /// The collected fees that belong to the factory and were not withdrawn yet.
+    pub fn withdrawable(&self) -> NearToken {
+        self.collected
+            .saturating_sub(self.referral_accrued)
+            .saturating_sub(self.withdrawn)
+    }
+}
+
+#[near(serializers = [json])]
+pub struct FeeAccountingView {
+    #[serde(flatten)]
+    pub accounting: FeeAccounting,
+    pub withdrawable: NearToken,
+}
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReferrerBalance {
+    /// Creations that credited the referrer
+    pub referrals: u32,
+    pub accrued: NearToken,
+    pub claimed: NearToken,
+}
+
+impl ReferrerBalance {
+    pub fn unclaimed(&self) -> NearToken {
+        self.accrued.saturating_sub(self.claimed)
+    }
+}
+
+impl Contract {
+    /// Holds the fee paid for a creation until its instance is created.
+    pub(crate) fn internal_hold_fee(&mut self, fee: NearToken) {
+        self.fee_accounting.pending = self.fee_accounting.pending.saturating_add(fee);
+    }
+
+    /// Collects the fee of `name` once its instance is created, and credits the
+    /// referral share to its referrer.
+    pub(crate) fn internal_collect_fee(&mut self, name: &str) {
+        let Some(creation) = self.creations.get_mut(name) else {
+            return;
+        };
+        let fee = std::mem::replace(&mut creation.fee, NearToken::from_near(0));
+        if fee.is_zero() {
+            return;
+        }
+        let referrer_id = creation.referrer_id.clone();
+        let referral_share = match &referrer_id {
+            Some(_) => self.fee_config.referral_share(fee),
+            None => NearToken::from_near(0),
+        };
+
+        let accounting = &mut self.fee_accounting;
+        accounting.pending = accounting.pending.saturating_sub(fee);
+        accounting.collected = accounting.collected.saturating_add(fee);
+        accounting.referral_accrued = accounting.referral_accrued.saturating_add(referral_share);
+        if let Some(referrer_id) = &referrer_id {
+            let mut balance = self
+                .referrer_balances
+                .get(referrer_id)
+                .cloned()
+                .unwrap_or_default();
+            balance.referrals += 1;
+            balance.accrued = balance.accrued.saturating_add(referral_share);
+            self.referrer_balances.insert(referrer_id.clone(), balance);
+        }
+
+        FactoryEvent::FeeCollected {
+            name: name.to_string(),
+            fee,
+            referrer_id,
+            referral_share,
+        }
+        .emit();
+    }
+
+    /// Releases the fee of `name` for a creation that failed, and returns it so it is
+    /// refunded with the deposits. Resuming the creation charges it again.
+    pub(crate) fn internal_release_fee(&mut self, name: &str) -> NearToken {
+        let fee = self
+            .creations
+            .get_mut(name)
+            .map(|creation| {
+                let fee = std::mem::replace(&mut creation.fee, NearToken::from_near(0));
+                creation.released_fee = creation.released_fee.saturating_add(fee);
+                fee
+            })
+            .unwrap_or(NearToken::from_near(0));
+        self.fee_accounting.pending = self.fee_accounting.pending.saturating_sub(fee);
+        fee
+    }
+}
+
+#[near]
+impl Contract {
+    pub fn get_fee_config(&self) -> FeeConfig {
+        self.fee_config.clone()
+    }
+
+    pub fn set_fee_config(&mut self, fee_config: FeeConfig) {
+        self.assert_admin_action(AdminPermission::Fees, "set_fee_config");
+        require!(
+            fee_config.referral_share_bps <= BASIS_POINTS,
+            format!("Referral share cannot exceed {} basis points", BASIS_POINTS)
+        );
+        self.fee_config = fee_config;
+    }
+
+    pub fn get_fee_accounting(&self) -> FeeAccountingView {
+        FeeAccountingView {
+            accounting: self.fee_accounting.clone(),
+            withdrawable: self.fee_accounting.withdrawable(),
+        }
+    }
+
+    pub fn get_referrer_balance(&self, referrer_id: AccountId) -> Option<ReferrerBalance> {
+        self.referrer_balances.get(&referrer_id).cloned()
+    }
+
+    /// Transfers the unclaimed referral shares of the caller.
+    pub fn claim(&mut self) -> Promise {
+        let referrer_id = env::predecessor_account_id();
+        let balance = self
+            .referrer_balances
+            .get_mut(&referrer_id)
+            .unwrap_or_else(|| env::panic_str(&format!("{} has no referrals", referrer_id)));
+        let amount = balance.unclaimed();
+        require!(!amount.is_zero(), "Nothing to claim");
+        balance.claimed = balance.claimed.saturating_add(amount);
+        self.fee_accounting.referral_claimed =
+            self.fee_accounting.referral_claimed.saturating_add(amount);
+
+        FactoryEvent::ReferralClaimed {
+            referrer_id: referrer_id.clone(),
+            amount,
+        }
+        .emit();
+        Promise::new(referrer_id).transfer(amount)
+    }
+
+    /// Transfers `amount` of the withdrawable fees, or all of them, to `receiver_id`
+    /// or the owner.
+    pub fn withdraw_fees(
+        &mut self,
+        amount: Option<NearToken>,
+        receiver_id: Option<AccountId>,
+    ) -> Promise {
+        self.assert_owner();
+        let withdrawable = self.fee_accounting.withdrawable();
+        let amount = amount.unwrap_or(withdrawable);
+        require!(!amount.is_zero(), "Nothing to withdraw");
+        require!(
+            amount <= withdrawable,
+            format!("Only {} of fees can be withdrawn", withdrawable)
+        );
+        self.fee_accounting.withdrawn = self.fee_accounting.withdrawn.saturating_add(amount);
+        let receiver_id = receiver_id.unwrap_or_else(|| self.owner_id.clone());
+
+        FactoryEvent::FeesWithdrawn {
+            receiver_id: receiver_id.clone(),
+            amount,
+        }
+        .emit();
+        Promise::new(receiver_id).transfer(amount)
+    }
+}
@@ -0,0 +1,104 @@
+use near_sdk::{
+    env, json_types::U128, near, require, serde_json, AccountId, NearToken, PromiseOrValue,
+};
+
+use crate::{
+    registry::DEFAULT_PAGE_LIMIT, AdminPermission, Contract, ContractExt, CreateInstanceArgs,
+    CreationPayment, TokenPayment,
+};
+
+/// Balance kept on the factory for the storage of the records of creations paid
+/// with tokens, on top of the deposits it funds.
+const TOKEN_CREATION_BALANCE_RESERVE: NearToken = NearToken::from_near(1);
+
+#[near(serializers = [json])]
+pub struct TokenPrice {
+    pub token_account_id: AccountId,
+    /// The amount of tokens, in the smallest unit of the token, to create an instance
+    pub price: U128,
+}
+
+impl Contract {
+    /// Panics unless the factory can pay `amount` without touching the balance
+    /// locked for its storage.
+    pub(crate) fn internal_assert_balance_covers(&self, amount: NearToken) {
+        let storage_cost = env::storage_byte_cost().saturating_mul(env::storage_usage().into());
+        let available_balance = env::account_balance().saturating_sub(storage_cost);
+        require!(
+            available_balance >= amount.saturating_add(TOKEN_CREATION_BALANCE_RESERVE),
+            "The factory balance cannot fund a creation paid with tokens"
+        );
+    }
+}
+
+#[near]
+impl Contract {
+    /// Pays for `create_instance` with the transferred tokens, where `msg` holds the
+    /// `create_instance` arguments as JSON. The transfer is refunded if the token is
+    /// not accepted or the creation cannot be started, and any amount above the
+    /// token price is returned.
+    pub fn ft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        let token_account_id = env::predecessor_account_id();
+        let price = self
+            .token_prices
+            .get(&token_account_id)
+            .copied()
+            .unwrap_or_else(|| {
+                env::panic_str(&format!("{} is not accepted for payment", token_account_id))
+            });
+        require!(
+            amount.0 >= price.0,
+            format!(
+                "Must transfer at least {} {} to create treasury instance",
+                price.0, token_account_id
+            )
+        );
+        let args: CreateInstanceArgs = serde_json::from_str(&msg).unwrap_or_else(|error| {
+            env::panic_str(&format!("Invalid create_instance arguments: {}", error))
+        });
+
+        // Not returned, since the token contract would read its result as the unused amount
+        self.internal_create_instance(
+            sender_id,
+            args,
+            CreationPayment::Tokens(TokenPayment {
+                token_account_id,
+                amount: price,
+            }),
+        );
+        PromiseOrValue::Value(U128(amount.0 - price.0))
+    }
+
+    pub fn get_token_price(&self, token_account_id: AccountId) -> Option<U128> {
+        self.token_prices.get(&token_account_id).copied()
+    }
+
+    pub fn get_token_prices(&self, from_index: Option<u32>, limit: Option<u32>) -> Vec<TokenPrice> {
+        self.token_prices
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(DEFAULT_PAGE_LIMIT) as usize)
+            .map(|(token_account_id, price)| TokenPrice {
+                token_account_id: token_account_id.clone(),
+                price: *price,
+            })
+            .collect()
+    }
+
+    /// Accepts `token_account_id` as payment for `create_instance` at `price`.
+    pub fn set_token_price(&mut self, token_account_id: AccountId, price: U128) {
+        self.assert_admin_action(AdminPermission::TokenPrices, "set_token_price");
+        require!(price.0 > 0, "Token price must be greater than zero");
+        self.token_prices.insert(token_account_id, price);
+    }
+
+    pub fn remove_token_price(&mut self, token_account_id: AccountId) {
+        self.assert_admin_action(AdminPermission::TokenPrices, "remove_token_price");
+        self.token_prices.remove(&token_account_id);
+    }
+}
@@ -2,29 +2,214 @@ mod web4;
 use near_sdk::{
     base64::{engine::general_purpose, Engine},
     env::{self},
-    near,
+    json_types::U128,
+    near, require,
     serde_json::json,
-    AccountId, Gas, NearToken, Promise, PromiseResult, PublicKey,
+    store::{IterableMap, LookupMap},
+    AccountId, BorshStorageKey, CryptoHash, Gas, NearToken, Promise, PromiseOrValue, PromiseResult,
+    PublicKey,
 };
 use web4::types::{Web4Request, Web4Response};
+pub mod access_keys;
+pub use crate::access_keys::*;
+pub mod allowlist;
+pub use crate::allowlist::*;
+pub mod batch;
+pub use crate::batch::*;
+pub mod branding;
+pub use crate::branding::*;
+pub mod dao_config;
+pub use crate::dao_config::*;
+pub mod events;
+pub use crate::events::*;
+pub mod existing_dao;
+pub use crate::existing_dao::*;
 pub mod external;
 pub use crate::external::*;
-
-static CREATE_SPUTNIK_DAO_DEPOSIT: NearToken = NearToken::from_near(6);
-static SOCIAL_DB_DEPOSIT: NearToken = NearToken::from_millinear(500);
-static NEW_INSTANCE_ACCOUNT_DEPOSIT: NearToken = NearToken::from_millinear(2500);
+pub mod fees;
+pub use crate::fees::*;
+pub mod ft_payments;
+pub use crate::ft_payments::*;
+pub mod names;
+pub use crate::names::*;
+pub mod network;
+pub use crate::network::*;
+pub mod parent_accounts;
+pub use crate::parent_accounts::*;
+pub mod pipeline;
+pub use crate::pipeline::*;
+pub mod policy_template;
+pub use crate::policy_template::*;
+pub mod pricing;
+pub use crate::pricing::*;
+pub mod push_upgrade;
+pub use crate::push_upgrade::*;
+pub mod registry;
+pub use crate::registry::*;
+pub mod roles;
+pub use crate::roles::*;
+pub mod stats;
+pub use crate::stats::*;
+pub mod upgrade;
+pub use crate::upgrade::*;
+pub mod vouchers;
+pub use crate::vouchers::*;
+pub mod web4_releases;
+pub use crate::web4_releases::*;
 
 const WEB4_CONTRACT_BYTES: &[u8] =
     include_bytes!("../../web4/treasury-web4/target/near/treasury_web4.wasm");
+const STUB_DELETE_CONTRACT_WASM: &[u8] = include_bytes!("../stub_delete_contract.wasm");
+
+#[near(serializers = [borsh])]
+#[derive(BorshStorageKey)]
+enum StorageKey {
+    Instances,
+    InstancesByCreator,
+    Creations,
+    Web4Releases,
+    Web4ReleaseCode,
+    Admins,
+    TokenPrices,
+    Sponsorships,
+    SponsorshipsBySponsor,
+    VoucherSponsorships,
+    ParentAccounts,
+    ReferrerBalances,
+    DailyStats,
+}
+
+/// The arguments of `create_instance`, which are also passed as the `msg` of an
+/// `ft_transfer_call` that pays for the creation with tokens.
+#[near(serializers = [json])]
+pub struct CreateInstanceArgs {
+    pub name: String,
+    pub sputnik_dao_factory_account_id: String,
+    pub social_db_account_id: String,
+    pub widget_reference_account_id: String,
+    pub create_dao_args: Option<String>,
+    #[serde(default)]
+    pub options: CreateInstanceOptions,
+}
+
+/// The optional settings of an instance creation.
+#[near(serializers = [json])]
+#[derive(Default)]
+pub struct CreateInstanceOptions {
+    pub dao_config: Option<DaoConfig>,
+    pub policy_template: Option<PolicyTemplateArgs>,
+    pub delete_stub_on_failure: Option<bool>,
+    pub access_keys: Option<InstanceAccessKeys>,
+    /// Pays with a sponsorship instead of the attached deposit
+    pub voucher: Option<Voucher>,
+    /// Creates the instance as a sub-account of a registered parent account instead
+    /// of a top-level account
+    pub parent_account_id: Option<AccountId>,
+    /// Social metadata of the instance in place of the defaults
+    pub branding: Option<InstanceBranding>,
+    /// Receives the referral share of the creation fee
+    pub referrer_id: Option<AccountId>,
+}
 
 // Define the contract structure
 #[near(contract_state)]
-#[derive(Default)]
-pub struct Contract {}
+pub struct Contract {
+    /// Must remain the first field, see `upgrade::STATE_VERSION`
+    state_version: u32,
+    owner_id: AccountId,
+    pending_owner_id: Option<AccountId>,
+    admins: IterableMap<AccountId, Vec<AdminPermission>>,
+    network_config: NetworkConfig,
+    creation_cost: CreationCost,
+    trusted_accounts: TrustedAccounts,
+    instances: IterableMap<String, InstanceRecord>,
+    instances_by_creator: LookupMap<AccountId, Vec<String>>,
+    creations: LookupMap<String, CreationStatus>,
+    policy_templates: Vec<PolicyTemplate>,
+    admin_public_key: Option<PublicKey>,
+    web4_releases: IterableMap<String, Web4Release>,
+    web4_release_code: LookupMap<String, Vec<u8>>,
+    latest_web4_version: Option<String>,
+    token_prices: IterableMap<AccountId, U128>,
+    sponsorships: LookupMap<u64, Sponsorship>,
+    sponsorships_by_sponsor: LookupMap<AccountId, Vec<u64>>,
+    voucher_sponsorships: LookupMap<CryptoHash, u64>,
+    next_sponsorship_id: u64,
+    /// Creators allowed by each account that delegated instance creation to the factory
+    parent_accounts: LookupMap<AccountId, Vec<AccountId>>,
+    fee_config: FeeConfig,
+    fee_accounting: FeeAccounting,
+    referrer_balances: LookupMap<AccountId, ReferrerBalance>,
+    stats: FactoryStats,
+    /// Counters of each day, keyed by the days since the unix epoch
+    daily_stats: LookupMap<u64, CreationCounters>,
+}
+
+impl Default for Contract {
+    fn default() -> Self {
+        Self {
+            state_version: STATE_VERSION,
+            owner_id: env::current_account_id(),
+            pending_owner_id: None,
+            admins: IterableMap::new(StorageKey::Admins),
+            network_config: NetworkConfig::default(),
+            creation_cost: CreationCost::default(),
+            trusted_accounts: TrustedAccounts::default(),
+            instances: IterableMap::new(StorageKey::Instances),
+            instances_by_creator: LookupMap::new(StorageKey::InstancesByCreator),
+            creations: LookupMap::new(StorageKey::Creations),
+            policy_templates: default_policy_templates(),
+            admin_public_key: Some(
+                "ed25519:DuAFUPhxv3zBDbZP8oCwC1KQPVzaUY88s5tECv8JDPMg"
+                    .parse()
+                    .unwrap(),
+            ),
+            web4_releases: IterableMap::new(StorageKey::Web4Releases),
+            web4_release_code: LookupMap::new(StorageKey::Web4ReleaseCode),
+            latest_web4_version: None,
+            token_prices: IterableMap::new(StorageKey::TokenPrices),
+            sponsorships: LookupMap::new(StorageKey::Sponsorships),
+            sponsorships_by_sponsor: LookupMap::new(StorageKey::SponsorshipsBySponsor),
+            voucher_sponsorships: LookupMap::new(StorageKey::VoucherSponsorships),
+            next_sponsorship_id: 0,
+            parent_accounts: LookupMap::new(StorageKey::ParentAccounts),
+            fee_config: FeeConfig::default(),
+            fee_accounting: FeeAccounting::default(),
+            referrer_balances: LookupMap::new(StorageKey::ReferrerBalances),
+            stats: FactoryStats::default(),
+            daily_stats: LookupMap::new(StorageKey::DailyStats),
+        }
+    }
+}
 
 // Implement the contract structure
 #[near]
 impl Contract {
+    /// Only needed for networks other than the default one of the build, since the
+    /// factory otherwise runs without initialization.
+    #[init]
+    pub fn new(
+        owner_id: Option<AccountId>,
+        network_config: Option<NetworkConfig>,
+        trusted_accounts: Option<TrustedAccounts>,
+    ) -> Self {
+        let mut contract = Self::default();
+        if let Some(owner_id) = owner_id {
+            contract.owner_id = owner_id;
+        }
+        if let Some(network_config) = network_config {
+            contract.network_config = network_config;
+        }
+        if let Some(trusted_accounts) = trusted_accounts {
+            contract.trusted_accounts = trusted_accounts;
+        }
+        contract
+    }
+
+    pub fn get_network_config(&self) -> NetworkConfig {
+        self.network_config.clone()
+    }
+
     #[allow(unused_variables)]
     pub fn web4_get(&self, request: Web4Request) -> Web4Response {
         let path = request.path.as_str();
@@ -56,152 +241,596 @@ impl Contract {
         sputnik_dao_factory_account_id: String,
         social_db_account_id: String,
         widget_reference_account_id: String,
-        create_dao_args: String,
+        create_dao_args: Option<String>,
+        options: Option<CreateInstanceOptions>,
     ) -> Promise {
-        if env::attached_deposit() != NearToken::from_near(9) {
-            env::panic_str("Must attach 9 NEAR to create treasury instance");
+        self.internal_create_instance(
+            env::predecessor_account_id(),
+            CreateInstanceArgs {
+                name,
+                sputnik_dao_factory_account_id,
+                social_db_account_id,
+                widget_reference_account_id,
+                create_dao_args,
+                options: options.unwrap_or_default(),
+            },
+            CreationPayment::AttachedDeposit,
+        )
+    }
+
+    #[private]
+    pub fn create_account_callback(&mut self, name: String) -> PromiseOrValue<()> {
+        let create_account_result = env::promise_result(0);
+        let create_account_result: bool = match create_account_result {
+            PromiseResult::Successful(result) => {
+                near_sdk::serde_json::from_slice::<bool>(&result).unwrap_or(false)
+            }
+            _ => false,
+        };
+
+        let creation = self.internal_get_creation_mut(&name);
+        if create_account_result {
+            creation.create_account = StepStatus::Succeeded;
+            self.internal_run_instance_steps(&name)
+        } else {
+            creation.create_account = StepStatus::Failed;
+            let new_instance_contract_id = creation.instance_account_id.clone();
+            let refund_amount = creation
+                .required_deposit(&creation.creation_cost)
+                .saturating_add(self.internal_release_fee(&name));
+            self.internal_set_instance_outcome(&name, InstanceOutcome::AccountCreationFailed);
+            self.internal_update_stats(|counters| counters.failed.create_account += 1);
+            FactoryEvent::AccountCreationFailed {
+                name: name.clone(),
+                instance_account_id: new_instance_contract_id,
+            }
+            .emit();
+            let refund = self.internal_refund(&name, refund_amount);
+            self.internal_restore_voucher(&name);
+            refund
+        }
+    }
+
+    /// Creates the instance account, unless the DAO of the instance already exists, in
+    /// which case the creation fails and every deposit and the fee are refunded.
+    #[private]
+    pub fn check_dao_callback(&mut self, name: String) -> PromiseOrValue<()> {
+        // Only a deployed DAO answers `get_config`
+        if !matches!(env::promise_result(0), PromiseResult::Successful(_)) {
+            return self.internal_create_account_step(&name).into();
         }
-        let new_instance_contract_id: AccountId = format!("{}.near", name).parse().unwrap();
-        let admin_full_access_public_key: PublicKey =
-            "ed25519:DuAFUPhxv3zBDbZP8oCwC1KQPVzaUY88s5tECv8JDPMg"
+
+        let creation = self.internal_get_creation_mut(&name);
+        creation.create_account = StepStatus::NotStarted;
+        creation.create_dao = StepStatus::Failed;
+        let instance_account_id = creation.instance_account_id.clone();
+        let dao_account_id: AccountId =
+            format!("{}.{}", name, creation.sputnik_dao_factory_account_id)
                 .parse()
                 .unwrap();
+        let refund_amount = creation
+            .required_deposit(&creation.creation_cost)
+            .saturating_add(self.internal_release_fee(&name));
+        env::log_str(&format!("DAO {} already exists", dao_account_id));
+        self.internal_set_instance_outcome(&name, InstanceOutcome::DaoCreationFailed);
+        self.internal_update_stats(|counters| counters.failed.create_dao += 1);
+        FactoryEvent::DaoCreationFailed {
+            name: name.clone(),
+            instance_account_id,
+            dao_account_id,
+        }
+        .emit();
+        let refund = self.internal_refund(&name, refund_amount);
+        self.internal_restore_voucher(&name);
+        refund
+    }
 
-        let minimum_self_upgrade_contract_wasm_base64 =
-            include_str!("../min_self_upgrade_contract.wasm.base64.txt");
+    #[private]
+    pub fn create_dao_callback(&mut self, name: String) -> PromiseOrValue<()> {
+        let creation = self.internal_get_creation_mut(&name);
+
+        // Results are in the order the steps were started by `internal_run_instance_steps`
+        let mut result_index = 0;
+        let mut upgrade_instance_failed = false;
+        if creation.upgrade_instance == StepStatus::InProgress {
+            creation.upgrade_instance = match env::promise_result(result_index) {
+                PromiseResult::Successful(_) => StepStatus::Succeeded,
+                _ => StepStatus::Failed,
+            };
+            upgrade_instance_failed = creation.upgrade_instance == StepStatus::Failed;
+            result_index += 1;
+        }
+        let mut create_dao_failed = false;
+        if creation.create_dao == StepStatus::InProgress {
+            creation.create_dao = match env::promise_result(result_index) {
+                PromiseResult::Successful(result) => {
+                    if near_sdk::serde_json::from_slice::<bool>(&result).unwrap_or(true) {
+                        StepStatus::Succeeded
+                    } else {
+                        StepStatus::Failed
+                    }
+                }
+                _ => StepStatus::Failed,
+            };
+            if creation.create_dao == StepStatus::Failed {
+                create_dao_failed = true;
+                FactoryEvent::DaoCreationFailed {
+                    name: name.clone(),
+                    instance_account_id: creation.instance_account_id.clone(),
+                    dao_account_id: format!("{}.{}", name, creation.sputnik_dao_factory_account_id)
+                        .parse()
+                        .unwrap(),
+                }
+                .emit();
+            }
+        }
+
+        let create_dao_status = creation.create_dao;
+        let upgrade_instance_status = creation.upgrade_instance;
+        let update_widgets_status = creation.update_widgets;
+        let delete_stub_on_failure = creation.delete_stub_on_failure;
+        let creation_cost = creation.creation_cost.clone();
+
+        if upgrade_instance_failed {
+            self.internal_update_stats(|counters| counters.failed.upgrade_instance += 1);
+        }
+        // The sputnik factory returns the deposit of a failed DAO creation to us, and
+        // the fee is returned with it until the creation is resumed
+        if create_dao_failed {
+            self.internal_update_stats(|counters| counters.failed.create_dao += 1);
+            let refund_amount = creation_cost
+                .sputnik_dao_deposit
+                .saturating_add(self.internal_release_fee(&name));
+            self.internal_refund(&name, refund_amount);
+        }
+        match create_dao_status {
+            StepStatus::Succeeded => {
+                self.internal_set_instance_outcome(&name, InstanceOutcome::Created)
+            }
+            _ => self.internal_set_instance_outcome(&name, InstanceOutcome::DaoCreationFailed),
+        }
+
+        // A stub account without a DAO is of no use, so its balance is returned along
+        // with the deposit of the widgets it will not get
+        if delete_stub_on_failure && create_dao_status != StepStatus::Succeeded {
+            if update_widgets_status != StepStatus::Succeeded {
+                self.internal_refund(&name, creation_cost.social_db_deposit);
+            }
+            return self.internal_delete_stub(&name).into();
+        }
+        // The upgrade of a stub that was kept for deletion starts once its DAO exists
+        if upgrade_instance_status == StepStatus::NotStarted {
+            return self.internal_run_instance_steps(&name);
+        }
+
+        // Widgets can only be deployed once the instance runs the web4 contract
+        if upgrade_instance_status == StepStatus::Succeeded {
+            if let Some(instance) = self.instances.get_mut(&name) {
+                instance.web4_version = self.latest_web4_version.clone();
+                FactoryEvent::InstanceUpgraded {
+                    name: name.clone(),
+                    instance_account_id: instance.instance_account_id.clone(),
+                    version: instance.web4_version.clone(),
+                }
+                .emit();
+            }
+            return self.internal_run_widgets_step(&name);
+        }
+
+        if update_widgets_status != StepStatus::Succeeded {
+            self.internal_refund(&name, creation_cost.social_db_deposit);
+        }
+        PromiseOrValue::Value(())
+    }
+
+    #[private]
+    pub fn update_widgets_callback(&mut self, name: String) {
+        let creation = self.internal_get_creation_mut(&name);
+        creation.update_widgets = match env::promise_result(0) {
+            PromiseResult::Successful(_) => {
+                FactoryEvent::WidgetsUpdated {
+                    name: name.clone(),
+                    instance_account_id: creation.instance_account_id.clone(),
+                }
+                .emit();
+                StepStatus::Succeeded
+            }
+            _ => StepStatus::Failed,
+        };
+        // The deposit is charged again when the step is resumed
+        if creation.update_widgets == StepStatus::Failed {
+            let social_db_deposit = creation.creation_cost.social_db_deposit;
+            self.internal_update_stats(|counters| counters.failed.update_widgets += 1);
+            self.internal_refund(&name, social_db_deposit);
+        }
+    }
+
+    #[private]
+    pub fn delete_stub_callback(&mut self, name: String) {
+        let creation = self.internal_get_creation_mut(&name);
+        match env::promise_result(0) {
+            PromiseResult::Successful(_) => {
+                creation.create_account = StepStatus::NotStarted;
+                creation.upgrade_instance = StepStatus::NotStarted;
+                creation.update_widgets = StepStatus::NotStarted;
+                let deleted_in_favour_of_factory =
+                    creation.sponsorship_id.is_some() || creation.token_payment.is_some();
+                let instance_account_deposit = creation.creation_cost.instance_account_deposit;
+                self.internal_set_instance_outcome(&name, InstanceOutcome::StubDeleted);
+                // The balance of a sponsored or token-paid stub went to the factory, see
+                // `internal_delete_stub`
+                if deleted_in_favour_of_factory {
+                    self.internal_refund(&name, instance_account_deposit);
+                }
+                let fee = self.internal_release_fee(&name);
+                if !fee.is_zero() {
+                    self.internal_refund(&name, fee);
+                }
+                self.internal_restore_voucher(&name);
+            }
+            _ => env::log_str(
+                format!(
+                    "Failed deleting treasury web4 account {}",
+                    creation.instance_account_id
+                )
+                .as_str(),
+            ),
+        }
+    }
+}
+
+/// How the deposits of a creation are paid.
+pub(crate) enum CreationPayment {
+    /// With the attached deposit, or the voucher of the creation
+    AttachedDeposit,
+    /// With tokens, in which case the factory funds the deposits from its own balance
+    Tokens(TokenPayment),
+    /// With the combined deposit of a `create_instances` batch, already taken
+    Batch,
+}
+
+impl Contract {
+    /// Starts creating an instance for `creator_id`.
+    pub(crate) fn internal_create_instance(
+        &mut self,
+        creator_id: AccountId,
+        args: CreateInstanceArgs,
+        payment: CreationPayment,
+    ) -> Promise {
+        let name = self.internal_register_creation(creator_id, args, payment);
+        self.internal_check_dao_step(&name)
+    }
+
+    /// Validates and pays for the creation of an instance, and registers it without
+    /// starting any step. Returns the name the instance is registered under.
+    pub(crate) fn internal_register_creation(
+        &mut self,
+        creator_id: AccountId,
+        args: CreateInstanceArgs,
+        payment: CreationPayment,
+    ) -> String {
+        let CreateInstanceArgs {
+            name,
+            sputnik_dao_factory_account_id,
+            social_db_account_id,
+            widget_reference_account_id,
+            create_dao_args,
+            options:
+                CreateInstanceOptions {
+                    dao_config,
+                    policy_template,
+                    delete_stub_on_failure,
+                    access_keys,
+                    voucher,
+                    parent_account_id,
+                    branding,
+                    referrer_id,
+                },
+        } = args;
+        self.internal_assert_trusted_creation_accounts(
+            &sputnik_dao_factory_account_id,
+            &social_db_account_id,
+            &widget_reference_account_id,
+        );
+        let name_check = self.internal_assert_valid_name(
+            &name,
+            parent_account_id.as_ref(),
+            &sputnik_dao_factory_account_id,
+        );
+        if let Some(parent_account_id) = &parent_account_id {
+            self.internal_assert_parent_account_creator(parent_account_id, &creator_id);
+        }
+        require!(
+            referrer_id.as_ref() != Some(&creator_id),
+            "The creator cannot be their own referrer"
+        );
+        // Instances under a parent account are registered under the name of their DAO
+        let name = name_check.instance_name;
+        if let Some(Err(error)) = branding.as_ref().map(InstanceBranding::validate) {
+            env::panic_str(&format!("Invalid branding: {}", error));
+        }
+        let create_dao_args = self.internal_resolve_create_dao_args(
+            &name,
+            create_dao_args,
+            dao_config,
+            policy_template,
+        );
+        let creation_cost = self.creation_cost.clone();
+        let creation_fee = self.fee_config.creation_fee;
+        let mut token_payment = None;
+        let mut sponsorship_id = None;
+        let (refund_account_id, fee) = match (voucher, payment) {
+            (Some(_), CreationPayment::Tokens(_)) => {
+                env::panic_str("Cannot pay with both a voucher and tokens")
+            }
+            (Some(voucher), payment) => {
+                // The sponsor funded the deposits, so an attached deposit is returned
+                if let CreationPayment::AttachedDeposit = payment {
+                    self.internal_take_deposit(NearToken::from_near(0), "create treasury instance");
+                }
+                let (voucher_sponsorship_id, fee) =
+                    self.internal_redeem_voucher(voucher, &creator_id, &name);
+                sponsorship_id = Some(voucher_sponsorship_id);
+                (
+                    self.sponsorships[&voucher_sponsorship_id]
+                        .sponsor_id
+                        .clone(),
+                    fee,
+                )
+            }
+            // The token price is the revenue of the factory, so no fee is charged in NEAR
+            (None, CreationPayment::Tokens(payment)) => {
+                self.internal_assert_balance_covers(creation_cost.total());
+                token_payment = Some(payment);
+                (creator_id.clone(), NearToken::from_near(0))
+            }
+            (None, CreationPayment::AttachedDeposit) => {
+                self.internal_take_deposit(
+                    creation_cost.total().saturating_add(creation_fee),
+                    "create treasury instance",
+                );
+                (creator_id.clone(), creation_fee)
+            }
+            (None, CreationPayment::Batch) => (creator_id.clone(), creation_fee),
+        };
+        self.internal_hold_fee(fee);
+
+        self.internal_start_creation(
+            CreationStatus {
+                name: name.clone(),
+                instance_account_id: name_check.instance_account_id.unwrap(),
+                refund_account_id,
+                creator_public_key: env::signer_account_pk(),
+                sputnik_dao_factory_account_id,
+                social_db_account_id,
+                widget_reference_account_id,
+                create_dao_args,
+                existing_dao: false,
+                delete_stub_on_failure: delete_stub_on_failure.unwrap_or(false),
+                access_keys: access_keys.unwrap_or_default(),
+                branding,
+                token_payment,
+                sponsorship_id,
+                creation_cost,
+                fee,
+                released_fee: NearToken::from_near(0),
+                referrer_id,
+                refunded: NearToken::from_near(0),
+                queued: false,
+                create_account: StepStatus::NotStarted,
+                upgrade_instance: StepStatus::NotStarted,
+                create_dao: StepStatus::NotStarted,
+                update_widgets: StepStatus::NotStarted,
+            },
+            creator_id,
+            name_check.dao_account_id.unwrap(),
+        );
+        name
+    }
+
+    /// Registers a new instance and stores its creation steps, before any of them is started.
+    pub(crate) fn internal_start_creation(
+        &mut self,
+        creation: CreationStatus,
+        creator_id: AccountId,
+        dao_account_id: AccountId,
+    ) {
+        self.internal_register_instance(InstanceRecord {
+            name: creation.name.clone(),
+            instance_account_id: creation.instance_account_id.clone(),
+            dao_account_id,
+            creator_id,
+            created_at_block: env::block_height(),
+            outcome: InstanceOutcome::Pending,
+            web4_version: None,
+            last_push_upgrade: None,
+        });
+        self.creations.insert(creation.name.clone(), creation);
+        self.internal_update_stats(|counters| counters.started += 1);
+    }
+
+    /// Starts every step of the creation of `name` that has not succeeded yet.
+    pub(crate) fn internal_run_creation_steps(&mut self, name: &str) -> PromiseOrValue<()> {
+        let creation = self.internal_get_creation_mut(name);
+        if creation.existing_dao && creation.create_dao != StepStatus::Succeeded {
+            self.internal_verify_dao_step(name).into()
+        } else if creation.create_account == StepStatus::Succeeded {
+            self.internal_run_instance_steps(name)
+        } else if creation.existing_dao || creation.create_dao == StepStatus::Succeeded {
+            self.internal_create_account_step(name).into()
+        } else {
+            self.internal_check_dao_step(name).into()
+        }
+    }
 
-        let allowed_self_upgrade_account_id = env::current_account_id();
-        let allowed_self_upgrade_account_bytes = allowed_self_upgrade_account_id.as_bytes();
+    /// Looks up the DAO of `name` before the instance account is paid for, since the
+    /// sputnik factory cannot create a DAO that already exists. The lookup is part of
+    /// the `create_account` step, which is in progress until the account is created.
+    pub(crate) fn internal_check_dao_step(&mut self, name: &str) -> Promise {
+        let creation = self.internal_get_creation_mut(name);
+        creation.create_account = StepStatus::InProgress;
+        let dao_account_id: AccountId =
+            format!("{}.{}", name, creation.sputnik_dao_factory_account_id)
+                .parse()
+                .unwrap();
+
+        sputnik_dao_contract::ext(dao_account_id)
+            .with_static_gas(Gas::from_tgas(5))
+            .get_config()
+            .then(Self::ext(env::current_account_id()).check_dao_callback(name.to_string()))
+    }
 
-        // Encode length (8 bytes) + account ID (padded to 64 bytes)
-        let mut encoded_data = vec![0u8; 8 + 64];
-        encoded_data[..8]
-            .copy_from_slice(&(allowed_self_upgrade_account_bytes.len() as u64).to_le_bytes()); // Store length (8 bytes)
-        encoded_data[8..8 + allowed_self_upgrade_account_bytes.len()]
-            .copy_from_slice(allowed_self_upgrade_account_bytes); // Store account ID
+    pub(crate) fn internal_create_account_step(&mut self, name: &str) -> Promise {
+        let mut options = self.internal_instance_access_key_options(name);
+        let creation = self.internal_get_creation_mut(name);
+        creation.create_account = StepStatus::InProgress;
+        // The registrar, or the parent account that delegated the creation to the factory
+        let parent_account_id = parent_account_of(&creation.instance_account_id);
 
-        let encoded_account_base64 = general_purpose::STANDARD.encode(&encoded_data);
+        let minimum_self_upgrade_contract_wasm_base64 =
+            include_str!("../min_self_upgrade_contract.wasm.base64.txt");
+
+        let encoded_account_base64 =
+            general_purpose::STANDARD.encode(encode_account_id_data(&env::current_account_id()));
 
         // Final Base64 string
         let final_wasm_base64 = format!(
             "{}{}",
             minimum_self_upgrade_contract_wasm_base64, encoded_account_base64
         );
+        options["contract_bytes_base64"] = json!(final_wasm_base64);
 
-        Promise::new("near".parse().unwrap())
+        Promise::new(parent_account_id)
             .function_call(
                 "create_account_advanced".to_string(),
                 json!({
-                    "new_account_id": new_instance_contract_id.clone(),
-                    "options": {
-                        "full_access_keys": [env::signer_account_pk(),admin_full_access_public_key],
-                        "contract_bytes_base64": final_wasm_base64
-                    }
+                    "new_account_id": creation.instance_account_id,
+                    "options": options
                 })
                 .to_string()
                 .as_bytes()
                 .to_vec(),
-                NEW_INSTANCE_ACCOUNT_DEPOSIT,
+                creation.creation_cost.instance_account_deposit,
                 Gas::from_tgas(30),
             )
-            .then(
-                Self::ext(env::current_account_id()).create_account_callback(
-                    env::predecessor_account_id(),
-                    name,
-                    new_instance_contract_id,
-                    sputnik_dao_factory_account_id,
-                    social_db_account_id,
-                    widget_reference_account_id,
-                    create_dao_args,
-                ),
-            )
+            .then(Self::ext(env::current_account_id()).create_account_callback(name.to_string()))
     }
 
-    #[private]
-    pub fn create_account_callback(
-        &self,
-        refund_on_failure_account: AccountId,
-        name: String,
-        new_instance_contract_id: AccountId,
-        sputnik_dao_factory_account_id: String,
-        social_db_account_id: String,
-        widget_reference_account_id: String,
-        create_dao_args: String,
-    ) -> Promise {
-        let create_account_result = env::promise_result(0);
-        let create_account_result: bool = match create_account_result {
-            PromiseResult::Successful(result) => {
-                near_sdk::serde_json::from_slice::<bool>(&result).unwrap_or(false)
-            }
-            _ => false,
-        };
+    fn internal_run_instance_steps(&mut self, name: &str) -> PromiseOrValue<()> {
+        let (upgrade_method_name, upgrade_args) = self.internal_instance_upgrade_call(None);
+        let creation = self.internal_get_creation_mut(name);
+        let mut steps: Option<Promise> = None;
 
-        if create_account_result {
-            Promise::new(new_instance_contract_id.clone())
-                .function_call(
-                    String::from("upgrade"),
-                    WEB4_CONTRACT_BYTES.to_vec(),
+        // A stub that may have to be deleted keeps its contract until the DAO exists,
+        // since the web4 contract cannot replace itself with the one deleting the stub
+        let wait_for_dao = creation.delete_stub_on_failure
+            && !creation.existing_dao
+            && creation.create_dao != StepStatus::Succeeded;
+        if creation.upgrade_instance != StepStatus::Succeeded && !wait_for_dao {
+            creation.upgrade_instance = StepStatus::InProgress;
+            steps = Some(
+                Promise::new(creation.instance_account_id.clone()).function_call(
+                    upgrade_method_name,
+                    upgrade_args,
                     NearToken::from_near(0),
                     Gas::from_tgas(30),
-                )
-                .then(
-                    sputnik_dao::ext(sputnik_dao_factory_account_id.parse().unwrap())
-                        .with_static_gas(Gas::from_tgas(100))
-                        .with_attached_deposit(CREATE_SPUTNIK_DAO_DEPOSIT)
-                        .create(name.to_string(), create_dao_args),
-                )
-                .then(Self::ext(env::current_account_id()).create_dao_callback(
-                    refund_on_failure_account,
-                    new_instance_contract_id,
-                    format!("{}.{}", name, sputnik_dao_factory_account_id),
-                    widget_reference_account_id,
-                    social_db_account_id,
-                ))
-        } else {
-            env::log_str(
-                format!(
-                    "Failed creating treasury web4 account {}",
-                    new_instance_contract_id
-                )
-                .as_str(),
+                ),
             );
-            Promise::new(refund_on_failure_account).transfer(
-                CREATE_SPUTNIK_DAO_DEPOSIT
-                    .saturating_add(SOCIAL_DB_DEPOSIT)
-                    .saturating_add(NEW_INSTANCE_ACCOUNT_DEPOSIT),
-            )
+        }
+
+        if creation.create_dao != StepStatus::Succeeded && !creation.existing_dao {
+            creation.create_dao = StepStatus::InProgress;
+            let create_dao =
+                sputnik_dao::ext(creation.sputnik_dao_factory_account_id.parse().unwrap())
+                    .with_static_gas(Gas::from_tgas(100))
+                    .with_attached_deposit(creation.creation_cost.sputnik_dao_deposit)
+                    .create(name.to_string(), creation.create_dao_args.clone());
+            steps = Some(match steps {
+                Some(steps) => steps.and(create_dao),
+                None => create_dao,
+            });
+        }
+
+        match steps {
+            Some(steps) => steps
+                .then(Self::ext(env::current_account_id()).create_dao_callback(name.to_string()))
+                .into(),
+            None => self.internal_run_widgets_step(name),
         }
     }
 
-    #[private]
-    pub fn create_dao_callback(
-        &self,
-        refund_on_failure_account: AccountId,
-        new_instance_contract_id: AccountId,
-        sputnik_dao_contract_id: String,
-        widget_reference_account_id: String,
-        social_db_account_id: String,
-    ) -> Promise {
-        let create_dao_result = env::promise_result(0);
+    /// Replaces the contract of a stub instance account, which was not upgraded to
+    /// web4, with one that deletes the account in favour of the account that paid for it.
+    /// A sponsored or token-paid stub is deleted in favour of the factory, which credits
+    /// its deposit to the sponsorship or refunds its share of the tokens.
+    fn internal_delete_stub(&mut self, name: &str) -> Promise {
+        let current_account_id = env::current_account_id();
+        let creation = self.internal_get_creation_mut(name);
+        let beneficiary_id = match (&creation.sponsorship_id, &creation.token_payment) {
+            (None, None) => creation.refund_account_id.clone(),
+            _ => current_account_id,
+        };
+        let mut stub_delete_contract_wasm =
+            STUB_DELETE_CONTRACT_WASM[..STUB_DELETE_CONTRACT_WASM.len() - 72].to_vec();
+        stub_delete_contract_wasm.extend(encode_account_id_data(&beneficiary_id));
+
+        Promise::new(creation.instance_account_id.clone())
+            .function_call(
+                String::from("upgrade"),
+                stub_delete_contract_wasm,
+                NearToken::from_near(0),
+                Gas::from_tgas(30),
+            )
+            .then(
+                Promise::new(creation.instance_account_id.clone()).function_call(
+                    String::from("delete"),
+                    vec![],
+                    NearToken::from_near(0),
+                    Gas::from_tgas(10),
+                ),
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(Gas::from_tgas(10))
+                    .with_unused_gas_weight(0)
+                    .delete_stub_callback(name.to_string()),
+            )
+    }
+
+    fn internal_run_widgets_step(&mut self, name: &str) -> PromiseOrValue<()> {
+        let creation = self.internal_get_creation_mut(name);
+        if creation.update_widgets == StepStatus::Succeeded {
+            return PromiseOrValue::Value(());
+        }
+        creation.update_widgets = StepStatus::InProgress;
 
-        let mut promise = instance_contract::ext(new_instance_contract_id.clone())
-            .with_attached_deposit(SOCIAL_DB_DEPOSIT)
+        instance_contract::ext(creation.instance_account_id.clone())
+            .with_attached_deposit(creation.creation_cost.social_db_deposit)
             .update_widgets(
-                widget_reference_account_id.clone(),
-                social_db_account_id.clone(),
+                creation.widget_reference_account_id.clone(),
+                creation.social_db_account_id.clone(),
                 true,
-            );
-        if create_dao_result == PromiseResult::Failed {
-            env::log_str(format!("Succeeded creating and funding web4 account {}, but failed creating treasury account {}",new_instance_contract_id, sputnik_dao_contract_id).as_str());
-            promise = promise
-                .then(Promise::new(refund_on_failure_account).transfer(CREATE_SPUTNIK_DAO_DEPOSIT))
-        }
-        promise
+                creation.branding.clone(),
+                creation.sputnik_dao_factory_account_id.clone(),
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(Gas::from_tgas(20))
+                    .with_unused_gas_weight(0)
+                    .update_widgets_callback(name.to_string()),
+            )
+            .into()
     }
+}
 
-    pub fn get_web4_contract_bytes(&self) {
-        env::value_return(WEB4_CONTRACT_BYTES);
-    }
+/// Encodes an account id the way the WAT contracts expect it in their data
+/// section: the length (8 bytes) followed by the account id padded to 64 bytes.
+fn encode_account_id_data(account_id: &AccountId) -> Vec<u8> {
+    let account_id_bytes = account_id.as_bytes();
+    let mut encoded_data = vec![0u8; 8 + 64];
+    encoded_data[..8].copy_from_slice(&(account_id_bytes.len() as u64).to_le_bytes());
+    encoded_data[8..8 + account_id_bytes.len()].copy_from_slice(account_id_bytes);
+    encoded_data
 }
 
 /*
@@ -213,6 +842,7 @@ mod tests {
     use super::*;
 
     use near_sdk::base64::{engine::general_purpose, Engine as _};
+    use near_sdk::{test_utils::VMContextBuilder, testing_env};
 
     #[test]
     fn web4_get() {
@@ -237,4 +867,1666 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn get_creation_cost_defaults_to_nine_near() {
+        let contract = Contract::default();
+        assert_eq!(contract.get_creation_cost().total, NearToken::from_near(9));
+    }
+
+    #[test]
+    #[should_panic(expected = "Requires the CreationCost admin permission")]
+    fn set_creation_cost_requires_owner() {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id("treasury-factory.near".parse().unwrap())
+            .predecessor_account_id("someone.near".parse().unwrap())
+            .build());
+        let mut contract = Contract::default();
+        contract.set_creation_cost(CreationCost::default());
+    }
+
+    fn create_test_instance_with_deposit(name: &str, deposit: NearToken) -> Contract {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id("treasury-factory.near".parse().unwrap())
+            .predecessor_account_id("alice.near".parse().unwrap())
+            .attached_deposit(deposit)
+            .build());
+        let mut contract = Contract::default();
+        call_create_instance(&mut contract, name);
+        contract
+    }
+
+    fn call_create_instance(contract: &mut Contract, name: &str) {
+        let args: CreateInstanceArgs =
+            serde_json::from_str(&test_create_instance_msg(name)).unwrap();
+        contract.create_instance(
+            args.name,
+            args.sputnik_dao_factory_account_id,
+            args.social_db_account_id,
+            args.widget_reference_account_id,
+            args.create_dao_args,
+            Some(args.options),
+        );
+    }
+
+    fn transfers_to(account_id: &str) -> Vec<NearToken> {
+        near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .filter(|receipt| receipt.receiver_id.as_str() == account_id)
+            .flat_map(|receipt| receipt.actions)
+            .filter_map(|action| match action {
+                near_sdk::mock::MockAction::Transfer { deposit, .. } => Some(deposit),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn create_instance_refunds_the_overpayment() {
+        create_test_instance_with_deposit("overpaid", NearToken::from_near(12));
+        assert_eq!(transfers_to("alice.near"), vec![NearToken::from_near(3)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Must attach at least 9.00 NEAR to create treasury instance")]
+    fn create_instance_requires_the_creation_cost() {
+        create_test_instance_with_deposit("underpaid", NearToken::from_millinear(8_999));
+    }
+
+    #[test]
+    #[should_panic(expected = "Must attach at least 6.00 NEAR to create treasury instance")]
+    fn create_instance_requires_the_creation_cost_set_by_the_owner() {
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id("treasury-factory.near".parse().unwrap())
+            .predecessor_account_id("treasury-factory.near".parse().unwrap());
+        testing_env!(context.build());
+        let mut contract = Contract::default();
+        let creation_cost = CreationCost {
+            sputnik_dao_deposit: NearToken::from_near(4),
+            social_db_deposit: NearToken::from_millinear(500),
+            instance_account_deposit: NearToken::from_millinear(1_500),
+        };
+        contract.set_creation_cost(creation_cost.clone());
+        assert_eq!(contract.get_creation_cost().total, NearToken::from_near(6));
+
+        testing_env!(context
+            .predecessor_account_id("alice.near".parse().unwrap())
+            .attached_deposit(NearToken::from_near(6))
+            .build());
+        call_create_instance(&mut contract, "paid");
+        assert!(transfers_to("alice.near").is_empty());
+        assert_eq!(
+            contract
+                .get_creation_status("paid".to_string())
+                .unwrap()
+                .creation_cost,
+            creation_cost
+        );
+
+        testing_env!(context.attached_deposit(NearToken::from_near(5)).build());
+        call_create_instance(&mut contract, "underpaid");
+    }
+
+    #[test]
+    fn ownership_transfer_requires_acceptance() {
+        let factory_account_id: AccountId = "treasury-factory.near".parse().unwrap();
+        let dao_account_id: AccountId = "factory-owners.sputnik-dao.near".parse().unwrap();
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id(factory_account_id.clone())
+            .predecessor_account_id(factory_account_id.clone());
+        testing_env!(context.build());
+        let mut contract = Contract::default();
+        contract.propose_owner(dao_account_id.clone());
+        assert_eq!(contract.get_owner(), factory_account_id);
+        assert_eq!(contract.get_pending_owner(), Some(dao_account_id.clone()));
+
+        testing_env!(context
+            .predecessor_account_id(dao_account_id.clone())
+            .build());
+        contract.accept_ownership();
+        assert_eq!(contract.get_owner(), dao_account_id);
+        assert_eq!(contract.get_pending_owner(), None);
+        assert!(near_sdk::test_utils::get_logs()[0].starts_with(
+            r#"EVENT_JSON:{"standard":"treasury-factory","version":"1.0.0","event":"ownership_transferred""#
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the pending owner can accept the ownership")]
+    fn ownership_cannot_be_accepted_by_others() {
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id("treasury-factory.near".parse().unwrap())
+            .predecessor_account_id("treasury-factory.near".parse().unwrap());
+        testing_env!(context.build());
+        let mut contract = Contract::default();
+        contract.propose_owner("new-owner.near".parse().unwrap());
+
+        testing_env!(context
+            .predecessor_account_id("someone.near".parse().unwrap())
+            .build());
+        contract.accept_ownership();
+    }
+
+    #[test]
+    fn admins_only_hold_their_permissions() {
+        let admin_account_id: AccountId = "release-manager.near".parse().unwrap();
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id("treasury-factory.near".parse().unwrap())
+            .predecessor_account_id("treasury-factory.near".parse().unwrap());
+        testing_env!(context.build());
+        let mut contract = Contract::default();
+        contract.set_admin(
+            admin_account_id.clone(),
+            vec![
+                AdminPermission::Web4Releases,
+                AdminPermission::InstanceUpgrades,
+                AdminPermission::Web4Releases,
+            ],
+        );
+        assert_eq!(
+            contract.get_admin_permissions(admin_account_id.clone()),
+            vec![
+                AdminPermission::Web4Releases,
+                AdminPermission::InstanceUpgrades
+            ]
+        );
+
+        testing_env!(context
+            .predecessor_account_id(admin_account_id.clone())
+            .build());
+        contract.upload_web4_release(
+            "1.0.0".to_string(),
+            b"code".to_vec().into(),
+            String::new(),
+            true,
+        );
+        assert_eq!(
+            contract.get_latest_web4_version(),
+            Some("1.0.0".to_string())
+        );
+        assert!(near_sdk::test_utils::get_logs()[0].contains(
+            r#""event":"admin_action","data":{"caller_id":"release-manager.near","permission":"Web4Releases","method":"upload_web4_release"}"#
+        ));
+        assert!(!contract.internal_has_permission(&admin_account_id, AdminPermission::CreationCost));
+
+        testing_env!(context
+            .predecessor_account_id("treasury-factory.near".parse().unwrap())
+            .build());
+        contract.remove_admin(admin_account_id.clone());
+        assert!(contract.get_admins(None, None).is_empty());
+    }
+
+    #[test]
+    fn migrate_from_legacy_state() {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id("treasury-factory.near".parse().unwrap())
+            .predecessor_account_id("treasury-factory.near".parse().unwrap())
+            .build());
+        // The legacy factory stored an empty `Contract {}`
+        env::storage_write(b"STATE", &[]);
+        let contract = Contract::migrate();
+        assert_eq!(contract.get_state_version(), STATE_VERSION);
+        assert_eq!(contract.get_owner().as_str(), "treasury-factory.near");
+        assert_eq!(contract.get_creation_cost().total, NearToken::from_near(9));
+    }
+
+    #[test]
+    fn migrate_keeps_current_state() {
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id("treasury-factory.near".parse().unwrap())
+            .predecessor_account_id("treasury-factory.near".parse().unwrap());
+        testing_env!(context.build());
+        let mut contract = Contract::default();
+        contract.upload_web4_release(
+            "1.0.0".to_string(),
+            b"code".to_vec().into(),
+            String::new(),
+            true,
+        );
+        env::state_write(&contract);
+
+        let contract = Contract::migrate();
+        assert_eq!(
+            contract.get_latest_web4_version(),
+            Some("1.0.0".to_string())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot migrate from factory state version 7")]
+    fn migrate_rejects_unknown_state_versions() {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id("treasury-factory.near".parse().unwrap())
+            .predecessor_account_id("treasury-factory.near".parse().unwrap())
+            .build());
+        env::storage_write(b"STATE", &7u32.to_le_bytes());
+        Contract::migrate();
+    }
+
+    fn test_creation_status() -> CreationStatus {
+        CreationStatus {
+            name: "test".to_string(),
+            instance_account_id: "test.near".parse().unwrap(),
+            refund_account_id: "creator.near".parse().unwrap(),
+            creator_public_key: "ed25519:DuAFUPhxv3zBDbZP8oCwC1KQPVzaUY88s5tECv8JDPMg"
+                .parse()
+                .unwrap(),
+            sputnik_dao_factory_account_id: "sputnik-dao.near".to_string(),
+            social_db_account_id: "social.near".to_string(),
+            widget_reference_account_id: "bootstrap.treasury-factory.near".to_string(),
+            create_dao_args: String::new(),
+            existing_dao: false,
+            delete_stub_on_failure: false,
+            access_keys: InstanceAccessKeys::default(),
+            branding: None,
+            token_payment: None,
+            sponsorship_id: None,
+            creation_cost: CreationCost::default(),
+            fee: NearToken::from_near(0),
+            released_fee: NearToken::from_near(0),
+            referrer_id: None,
+            refunded: NearToken::from_near(0),
+            queued: false,
+            create_account: StepStatus::NotStarted,
+            upgrade_instance: StepStatus::NotStarted,
+            create_dao: StepStatus::NotStarted,
+            update_widgets: StepStatus::NotStarted,
+        }
+    }
+
+    #[test]
+    fn required_deposit_only_covers_unfinished_steps() {
+        let creation_cost = CreationCost::default();
+        let mut creation = test_creation_status();
+        creation.create_account = StepStatus::Failed;
+        assert_eq!(
+            creation.required_deposit(&creation_cost),
+            creation_cost.total()
+        );
+
+        creation.create_account = StepStatus::Succeeded;
+        creation.upgrade_instance = StepStatus::Succeeded;
+        creation.create_dao = StepStatus::Failed;
+        creation.update_widgets = StepStatus::Succeeded;
+        assert_eq!(
+            creation.required_deposit(&creation_cost),
+            creation_cost.sputnik_dao_deposit
+        );
+        assert!(!creation.is_in_progress());
+        assert!(!creation.is_complete());
+    }
+
+    #[test]
+    fn resuming_widgets_keeps_instance_created() {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id("treasury-factory.near".parse().unwrap())
+            .predecessor_account_id("creator.near".parse().unwrap())
+            .attached_deposit(CreationCost::default().social_db_deposit)
+            .build());
+        let mut contract = Contract::default();
+        contract.internal_register_instance(InstanceRecord {
+            name: "test".to_string(),
+            instance_account_id: "test.near".parse().unwrap(),
+            dao_account_id: "test.sputnik-dao.near".parse().unwrap(),
+            creator_id: "creator.near".parse().unwrap(),
+            created_at_block: 0,
+            outcome: InstanceOutcome::Created,
+            web4_version: None,
+            last_push_upgrade: None,
+        });
+        let mut creation = test_creation_status();
+        creation.create_account = StepStatus::Succeeded;
+        creation.upgrade_instance = StepStatus::Succeeded;
+        creation.create_dao = StepStatus::Succeeded;
+        creation.update_widgets = StepStatus::Failed;
+        contract.creations.insert("test".to_string(), creation);
+
+        contract.resume_instance_creation("test".to_string());
+
+        let instance = contract.get_instance("test".to_string()).unwrap();
+        assert_eq!(instance.outcome, InstanceOutcome::Created);
+        let creation = contract.get_creation_status("test".to_string()).unwrap();
+        assert_eq!(creation.update_widgets, StepStatus::InProgress);
+    }
+
+    fn test_dao_config() -> DaoConfig {
+        DaoConfig {
+            purpose: "Treasury".to_string(),
+            roles: vec![DaoRole {
+                name: "Approver".to_string(),
+                members: vec!["alice.near".parse().unwrap()],
+                permissions: vec![
+                    "transfer:AddProposal".to_string(),
+                    "*:VoteApprove".to_string(),
+                ],
+                vote_threshold: Some(VoteThreshold::Ratio(1, 2)),
+            }],
+            default_vote_threshold: VoteThreshold::Weight(1.into()),
+            proposal_bond: NearToken::from_near(0),
+            voting_period: 604800000000000.into(),
+        }
+    }
+
+    #[test]
+    fn dao_config_builds_sputnik_create_args() {
+        let dao_config = test_dao_config();
+        assert!(dao_config.validate().is_ok());
+
+        let args: serde_json::Value = serde_json::from_slice(
+            &general_purpose::STANDARD
+                .decode(dao_config.to_create_dao_args("test"))
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(args["config"]["name"], "test");
+        let role = &args["policy"]["roles"][0];
+        assert_eq!(role["kind"]["Group"][0], "alice.near");
+        assert_eq!(
+            role["vote_policy"]["transfer"]["threshold"],
+            serde_json::json!([1, 2])
+        );
+        assert!(role["vote_policy"].get("*").is_none());
+        assert_eq!(args["policy"]["default_vote_policy"]["threshold"], "1");
+        assert_eq!(args["policy"]["proposal_period"], "604800000000000");
+    }
+
+    #[test]
+    fn dao_config_validation_rejects_invalid_configs() {
+        let mut dao_config = test_dao_config();
+        dao_config.roles[0].vote_threshold = Some(VoteThreshold::Ratio(3, 2));
+        assert!(dao_config.validate().is_err());
+
+        let mut dao_config = test_dao_config();
+        dao_config.roles[0].permissions = vec!["transfers:AddProposal".to_string()];
+        assert!(dao_config.validate().is_err());
+
+        let mut dao_config = test_dao_config();
+        dao_config.roles[0].members.clear();
+        assert!(dao_config.validate().is_err());
+
+        let mut dao_config = test_dao_config();
+        dao_config.voting_period = 0.into();
+        assert!(dao_config.validate().is_err());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Exactly one of create_dao_args, dao_config or policy_template must be provided"
+    )]
+    fn create_instance_requires_exactly_one_dao_args_source() {
+        Contract::default().internal_resolve_create_dao_args(
+            "test",
+            Some(test_dao_config().to_create_dao_args("test")),
+            Some(test_dao_config()),
+            None,
+        );
+    }
+
+    #[test]
+    fn check_name_accepts_valid_names() {
+        let name_check = Contract::default().check_name("my-treasury_2".to_string(), None, None);
+        assert_eq!(
+            name_check,
+            NameCheck {
+                name: "my-treasury_2".to_string(),
+                instance_name: "my-treasury_2".to_string(),
+                instance_account_id: Some("my-treasury_2.near".parse().unwrap()),
+                dao_account_id: Some("my-treasury_2.sputnik-dao.near".parse().unwrap()),
+                available: true,
+                reasons: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn check_name_reports_all_reasons() {
+        let contract = Contract::default();
+        assert_eq!(
+            contract
+                .check_name("-My.Treasury".to_string(), None, None)
+                .reasons,
+            vec![
+                "Name can only contain lowercase letters, digits, - and _",
+                "Name cannot start or end with - or _",
+            ]
+        );
+        assert_eq!(
+            contract.check_name("a".to_string(), None, None).reasons,
+            vec!["Name must be at least 2 characters"]
+        );
+        assert_eq!(
+            contract
+                .check_name("my--treasury".to_string(), None, None)
+                .reasons,
+            vec!["Name cannot contain consecutive - or _"]
+        );
+        assert_eq!(
+            contract
+                .check_name("treasury".to_string(), None, None)
+                .reasons,
+            vec!["treasury is a reserved name"]
+        );
+
+        let long_name = "a".repeat(50);
+        let name_check = contract.check_name(long_name.clone(), None, None);
+        assert!(!name_check.available);
+        assert!(name_check.instance_account_id.is_some());
+        assert_eq!(name_check.dao_account_id, None);
+        assert_eq!(
+            name_check.reasons,
+            vec![format!(
+                "{}.sputnik-dao.near is longer than 64 characters",
+                long_name
+            )]
+        );
+    }
+
+    #[test]
+    fn check_name_reports_registered_names() {
+        let mut contract = Contract::default();
+        contract.internal_start_creation(
+            test_creation_status(),
+            "creator.near".parse().unwrap(),
+            "test.sputnik-dao.near".parse().unwrap(),
+        );
+        assert_eq!(
+            contract.check_name("test".to_string(), None, None).reasons,
+            vec!["Instance test is already registered"]
+        );
+
+        contract.internal_set_instance_outcome("test", InstanceOutcome::AccountCreationFailed);
+        assert!(
+            contract
+                .check_name("test".to_string(), None, None)
+                .available
+        );
+    }
+
+    #[test]
+    fn decommissioned_instances_are_retired_for_good() {
+        let mut context = VMContextBuilder::new();
+        let mut contract = Contract::default();
+        contract.internal_start_creation(
+            test_creation_status(),
+            "creator.near".parse().unwrap(),
+            "test.sputnik-dao.near".parse().unwrap(),
+        );
+        contract.internal_set_instance_outcome("test", InstanceOutcome::Created);
+
+        testing_env!(context
+            .predecessor_account_id("test.near".parse().unwrap())
+            .build());
+        contract.on_instance_decommissioned("test.sputnik-dao.near".parse().unwrap());
+        assert!(near_sdk::test_utils::get_logs().last().unwrap().contains(
+            r#""event":"instance_retired","data":{"name":"test","instance_account_id":"test.near","beneficiary_id":"test.sputnik-dao.near"}"#
+        ));
+        assert_eq!(
+            contract.get_instance("test".to_string()).unwrap().outcome,
+            InstanceOutcome::Retired
+        );
+        assert!(contract.get_creation_status("test".to_string()).is_none());
+        assert_eq!(
+            contract.check_name("test".to_string(), None, None).reasons,
+            vec!["Instance test was retired and its name cannot be reused"]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "other.near is not an instance of the factory")]
+    fn only_instances_can_report_their_decommissioning() {
+        let mut context = VMContextBuilder::new();
+        let mut contract = Contract::default();
+        testing_env!(context
+            .predecessor_account_id("other.near".parse().unwrap())
+            .build());
+        contract.on_instance_decommissioned("other.near".parse().unwrap());
+    }
+
+    #[test]
+    fn push_upgrade_failures_are_paged_over_the_registry() {
+        let mut contract = Contract::default();
+        for name in ["refused", "upgraded", "failed"] {
+            let mut creation = test_creation_status();
+            creation.name = name.to_string();
+            creation.instance_account_id = format!("{}.near", name).parse().unwrap();
+            contract.internal_start_creation(
+                creation,
+                "creator.near".parse().unwrap(),
+                format!("{}.sputnik-dao.near", name).parse().unwrap(),
+            );
+        }
+        let version = Some("2.0.0".to_string());
+        for (name, status) in [
+            (
+                "refused",
+                PushUpgradeStatus::Refused {
+                    version: version.clone(),
+                },
+            ),
+            (
+                "upgraded",
+                PushUpgradeStatus::Upgraded {
+                    version: version.clone(),
+                },
+            ),
+            ("failed", PushUpgradeStatus::Failed { version }),
+        ] {
+            contract.instances.get_mut(name).unwrap().last_push_upgrade = Some(status);
+        }
+
+        let failure_names = |from_index, limit| {
+            contract
+                .get_push_upgrade_failures(Some(from_index), Some(limit))
+                .into_iter()
+                .map(|instance| instance.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(failure_names(0, 2), vec!["refused"]);
+        assert_eq!(failure_names(2, 2), vec!["failed"]);
+        assert_eq!(failure_names(0, 3), vec!["refused", "failed"]);
+    }
+
+    #[test]
+    fn instances_report_the_version_they_upgraded_to() {
+        let mut context = VMContextBuilder::new();
+        let mut contract = Contract::default();
+        contract.internal_start_creation(
+            test_creation_status(),
+            "creator.near".parse().unwrap(),
+            "test.sputnik-dao.near".parse().unwrap(),
+        );
+        contract.internal_set_instance_outcome("test", InstanceOutcome::Created);
+        contract.latest_web4_version = Some("2.0.0".to_string());
+
+        testing_env!(context
+            .predecessor_account_id("test.near".parse().unwrap())
+            .build());
+        contract.on_instance_upgraded(Some("1.0.0".to_string()));
+        assert_eq!(
+            contract
+                .get_instance("test".to_string())
+                .unwrap()
+                .web4_version,
+            Some("1.0.0".to_string())
+        );
+        assert!(near_sdk::test_utils::get_logs().last().unwrap().contains(
+            r#""event":"instance_upgraded","data":{"name":"test","instance_account_id":"test.near","version":"1.0.0"}"#
+        ));
+
+        contract.on_instance_upgraded(None);
+        assert_eq!(
+            contract
+                .get_instance("test".to_string())
+                .unwrap()
+                .web4_version,
+            Some("2.0.0".to_string())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "other.near is not an instance of the factory")]
+    fn only_instances_can_report_their_upgrades() {
+        let mut context = VMContextBuilder::new();
+        let mut contract = Contract::default();
+        testing_env!(context
+            .predecessor_account_id("other.near".parse().unwrap())
+            .build());
+        contract.on_instance_upgraded(None);
+    }
+
+    fn create_test_instance_with_fee(context: &mut VMContextBuilder, contract: &mut Contract) {
+        contract.fee_config = FeeConfig {
+            creation_fee: NearToken::from_near(1),
+            referral_share_bps: 2_000,
+        };
+        testing_env!(context
+            .predecessor_account_id("alice.near".parse().unwrap())
+            .attached_deposit(NearToken::from_near(10))
+            .build());
+        let mut args: CreateInstanceArgs =
+            serde_json::from_str(&test_create_instance_msg("referred")).unwrap();
+        args.options.referrer_id = Some("referrer.near".parse().unwrap());
+        contract.internal_create_instance(
+            "alice.near".parse().unwrap(),
+            args,
+            CreationPayment::AttachedDeposit,
+        );
+    }
+
+    #[test]
+    fn creation_fees_are_collected_once_the_instance_is_created() {
+        let mut context = VMContextBuilder::new();
+        let mut contract = Contract::default();
+        create_test_instance_with_fee(&mut context, &mut contract);
+        assert_eq!(contract.get_creation_cost().total, NearToken::from_near(10));
+        assert_eq!(
+            contract.get_fee_accounting().accounting,
+            FeeAccounting {
+                pending: NearToken::from_near(1),
+                ..Default::default()
+            }
+        );
+
+        contract.internal_set_instance_outcome("referred", InstanceOutcome::Created);
+        let fee_accounting = contract.get_fee_accounting();
+        assert_eq!(
+            fee_accounting.accounting,
+            FeeAccounting {
+                collected: NearToken::from_near(1),
+                referral_accrued: NearToken::from_millinear(200),
+                ..Default::default()
+            }
+        );
+        assert_eq!(fee_accounting.withdrawable, NearToken::from_millinear(800));
+        assert!(near_sdk::test_utils::get_logs().iter().any(|log| log.contains(
+            r#""event":"fee_collected","data":{"name":"referred","fee":"1000000000000000000000000","referrer_id":"referrer.near","referral_share":"200000000000000000000000"}"#
+        )));
+
+        // Being created again after a resumed step does not collect the fee twice
+        contract.internal_set_instance_outcome("referred", InstanceOutcome::Pending);
+        contract.internal_set_instance_outcome("referred", InstanceOutcome::Created);
+        assert_eq!(
+            contract.get_fee_accounting().accounting.collected,
+            NearToken::from_near(1)
+        );
+    }
+
+    #[test]
+    fn referrers_claim_their_accrued_shares() {
+        let mut context = VMContextBuilder::new();
+        let mut contract = Contract::default();
+        create_test_instance_with_fee(&mut context, &mut contract);
+        contract.internal_set_instance_outcome("referred", InstanceOutcome::Created);
+
+        testing_env!(context
+            .predecessor_account_id("referrer.near".parse().unwrap())
+            .attached_deposit(NearToken::from_near(0))
+            .build());
+        contract.claim();
+        assert_eq!(
+            contract.get_referrer_balance("referrer.near".parse().unwrap()),
+            Some(ReferrerBalance {
+                referrals: 1,
+                accrued: NearToken::from_millinear(200),
+                claimed: NearToken::from_millinear(200),
+            })
+        );
+        assert_eq!(
+            contract.get_fee_accounting().accounting.referral_claimed,
+            NearToken::from_millinear(200)
+        );
+    }
+
+    #[test]
+    fn failed_account_creations_release_their_fee() {
+        let mut context = VMContextBuilder::new();
+        let mut contract = Contract::default();
+        create_test_instance_with_fee(&mut context, &mut contract);
+
+        assert_eq!(
+            contract.internal_release_fee("referred"),
+            NearToken::from_near(1)
+        );
+        contract.internal_set_instance_outcome("referred", InstanceOutcome::Created);
+        assert_eq!(
+            contract.get_fee_accounting().accounting,
+            FeeAccounting::default()
+        );
+    }
+
+    #[test]
+    fn failed_dao_creations_refund_the_fee_until_resumed() {
+        let mut context = VMContextBuilder::new();
+        context.current_account_id("treasury-factory.near".parse().unwrap());
+        let mut contract = Contract::default();
+        create_test_instance_with_fee(&mut context, &mut contract);
+        let creation = contract.internal_get_creation_mut("referred");
+        creation.create_account = StepStatus::Succeeded;
+        creation.upgrade_instance = StepStatus::Failed;
+        creation.create_dao = StepStatus::InProgress;
+
+        testing_env!(
+            context.attached_deposit(NearToken::from_near(0)).build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        contract.create_dao_callback("referred".to_string());
+        let creation_cost = CreationCost::default();
+        let creation = contract
+            .get_creation_status("referred".to_string())
+            .unwrap();
+        assert_eq!(
+            creation.refunded,
+            creation_cost
+                .sputnik_dao_deposit
+                .saturating_add(creation_cost.social_db_deposit)
+                .saturating_add(NearToken::from_near(1))
+        );
+        assert_eq!(creation.fee, NearToken::from_near(0));
+        assert_eq!(creation.released_fee, NearToken::from_near(1));
+        assert_eq!(
+            contract.get_fee_accounting().accounting,
+            FeeAccounting::default()
+        );
+
+        testing_env!(context
+            .attached_deposit(
+                creation_cost
+                    .sputnik_dao_deposit
+                    .saturating_add(creation_cost.social_db_deposit)
+                    .saturating_add(NearToken::from_near(1))
+            )
+            .build());
+        contract.resume_instance_creation("referred".to_string());
+        let creation = contract
+            .get_creation_status("referred".to_string())
+            .unwrap();
+        assert_eq!(creation.fee, NearToken::from_near(1));
+        assert_eq!(creation.released_fee, NearToken::from_near(0));
+        assert_eq!(
+            contract.get_fee_accounting().accounting.pending,
+            NearToken::from_near(1)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "of fees can be withdrawn")]
+    fn fee_withdrawals_exclude_referral_shares() {
+        let mut context = VMContextBuilder::new();
+        let mut contract = Contract::default();
+        create_test_instance_with_fee(&mut context, &mut contract);
+        contract.internal_set_instance_outcome("referred", InstanceOutcome::Created);
+
+        testing_env!(context
+            .predecessor_account_id(contract.get_owner())
+            .attached_deposit(NearToken::from_near(0))
+            .build());
+        contract.withdraw_fees(Some(NearToken::from_near(1)), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "The creator cannot be their own referrer")]
+    fn creators_cannot_refer_themselves() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.attached_deposit(NearToken::from_near(9)).build());
+        let mut args: CreateInstanceArgs =
+            serde_json::from_str(&test_create_instance_msg("referred")).unwrap();
+        args.options.referrer_id = Some("alice.near".parse().unwrap());
+        Contract::default().internal_create_instance(
+            "alice.near".parse().unwrap(),
+            args,
+            CreationPayment::AttachedDeposit,
+        );
+    }
+
+    #[test]
+    fn stats_count_creations_by_day() {
+        const DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+        let mut context = VMContextBuilder::new();
+        testing_env!(context
+            .block_timestamp(20_000 * DAY)
+            .attached_deposit(NearToken::from_near(9))
+            .build());
+        let mut contract = Contract::default();
+        for name in ["firsttreasury", "secondtreasury"] {
+            contract.internal_create_instance(
+                "alice.near".parse().unwrap(),
+                serde_json::from_str(&test_create_instance_msg(name)).unwrap(),
+                CreationPayment::AttachedDeposit,
+            );
+        }
+        contract.internal_set_instance_outcome("firsttreasury", InstanceOutcome::Created);
+
+        testing_env!(context.block_timestamp(20_001 * DAY).build());
+        contract.internal_refund("secondtreasury", NearToken::from_near(6));
+
+        let stats = contract.get_stats(Some(3));
+        assert_eq!(
+            stats.totals.counters,
+            CreationCounters {
+                started: 2,
+                created: 1,
+                refunds: 1,
+                refunded: NearToken::from_near(6),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            stats.totals.instances_by_sputnik_factory,
+            [("sputnik-dao.near".to_string(), 1)].into()
+        );
+        assert_eq!(
+            stats.totals.instances_by_widget_reference,
+            [("bootstrap.treasury-factory.near".to_string(), 1)].into()
+        );
+        assert_eq!(
+            stats
+                .daily
+                .iter()
+                .map(|daily| daily.day)
+                .collect::<Vec<_>>(),
+            vec![19_999, 20_000, 20_001]
+        );
+        assert_eq!(stats.daily[0].counters, CreationCounters::default());
+        assert_eq!(stats.daily[1].counters.started, 2);
+        assert_eq!(stats.daily[1].counters.created, 1);
+        assert_eq!(stats.daily[2].counters.refunds, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid name www: www is a reserved name")]
+    fn create_instance_rejects_invalid_names() {
+        Contract::default().create_instance(
+            "www".to_string(),
+            "sputnik-dao.near".to_string(),
+            "social.near".to_string(),
+            "bootstrap.treasury-factory.near".to_string(),
+            None,
+            Some(CreateInstanceOptions {
+                dao_config: Some(test_dao_config()),
+                ..Default::default()
+            }),
+        );
+    }
+
+    #[test]
+    fn create_instance_keeps_branding_for_the_widgets_step() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.attached_deposit(NearToken::from_near(9)).build());
+        let mut contract = Contract::default();
+        let branding = InstanceBranding {
+            name: Some("Org Treasury".to_string()),
+            description: Some("Funds of the org".to_string()),
+            ipfs_cid: Some(
+                "bafkreib3c4mlrkeyfjvg4fspb5k3w6eeo2f2wp6qtfadfz3i2qqbqkyxeq".to_string(),
+            ),
+            tags: Some(vec!["grants".to_string()]),
+        };
+        let mut args: CreateInstanceArgs =
+            serde_json::from_str(&test_create_instance_msg("branded")).unwrap();
+        args.options.branding = Some(branding.clone());
+        contract.internal_create_instance(
+            "alice.near".parse().unwrap(),
+            args,
+            CreationPayment::AttachedDeposit,
+        );
+
+        assert_eq!(
+            contract
+                .get_creation_status("branded".to_string())
+                .unwrap()
+                .branding,
+            Some(branding)
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invalid branding: Tag Grants must have up to 32 lowercase letters, digits or -"
+    )]
+    fn create_instance_rejects_invalid_branding() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.attached_deposit(NearToken::from_near(9)).build());
+        let mut args: CreateInstanceArgs =
+            serde_json::from_str(&test_create_instance_msg("branded")).unwrap();
+        args.options.branding = Some(InstanceBranding {
+            tags: Some(vec!["Grants".to_string()]),
+            ..Default::default()
+        });
+        Contract::default().internal_create_instance(
+            "alice.near".parse().unwrap(),
+            args,
+            CreationPayment::AttachedDeposit,
+        );
+    }
+
+    fn register_test_parent_account(context: &mut VMContextBuilder, contract: &mut Contract) {
+        testing_env!(context
+            .predecessor_account_id("myorg.near".parse().unwrap())
+            .attached_deposit(NearToken::from_near(1))
+            .build());
+        contract.register_parent_account(vec!["alice.near".parse().unwrap()]);
+    }
+
+    #[test]
+    fn check_name_under_parent_account() {
+        let mut context = VMContextBuilder::new();
+        let mut contract = Contract::default();
+        let parent_account_id: AccountId = "myorg.near".parse().unwrap();
+        assert_eq!(
+            contract
+                .check_name("payroll".to_string(), None, Some(parent_account_id.clone()))
+                .reasons,
+            vec!["myorg.near is not registered as a parent account"]
+        );
+
+        register_test_parent_account(&mut context, &mut contract);
+        // Reserved names only apply to top-level instances
+        assert_eq!(
+            contract.check_name("treasury".to_string(), None, Some(parent_account_id)),
+            NameCheck {
+                name: "treasury".to_string(),
+                instance_name: "treasury-myorg".to_string(),
+                instance_account_id: Some("treasury.myorg.near".parse().unwrap()),
+                dao_account_id: Some("treasury-myorg.sputnik-dao.near".parse().unwrap()),
+                available: true,
+                reasons: vec![],
+            }
+        );
+        assert_eq!(
+            instance_name("ops", Some(&"team.myorg.near".parse().unwrap())),
+            "ops-team-myorg"
+        );
+    }
+
+    #[test]
+    fn create_instance_under_parent_account() {
+        let mut context = VMContextBuilder::new();
+        let mut contract = Contract::default();
+        register_test_parent_account(&mut context, &mut contract);
+        assert_eq!(
+            contract.get_parent_account_creators("myorg.near".parse().unwrap()),
+            Some(vec!["alice.near".parse().unwrap()])
+        );
+
+        testing_env!(context
+            .predecessor_account_id("alice.near".parse().unwrap())
+            .attached_deposit(NearToken::from_near(9))
+            .build());
+        let mut args: CreateInstanceArgs =
+            serde_json::from_str(&test_create_instance_msg("treasury")).unwrap();
+        args.options.parent_account_id = Some("myorg.near".parse().unwrap());
+        contract.internal_create_instance(
+            "alice.near".parse().unwrap(),
+            args,
+            CreationPayment::AttachedDeposit,
+        );
+
+        let instance = contract.get_instance("treasury-myorg".to_string()).unwrap();
+        assert_eq!(instance.instance_account_id.as_str(), "treasury.myorg.near");
+        assert_eq!(
+            instance.dao_account_id.as_str(),
+            "treasury-myorg.sputnik-dao.near"
+        );
+        assert!(contract.get_instance("treasury".to_string()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "bob.near cannot create instances under myorg.near")]
+    fn parent_accounts_only_allow_their_creators() {
+        let mut context = VMContextBuilder::new();
+        let mut contract = Contract::default();
+        register_test_parent_account(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id("bob.near".parse().unwrap())
+            .attached_deposit(NearToken::from_near(9))
+            .build());
+        let mut args: CreateInstanceArgs =
+            serde_json::from_str(&test_create_instance_msg("treasury")).unwrap();
+        args.options.parent_account_id = Some("myorg.near".parse().unwrap());
+        contract.internal_create_instance(
+            "bob.near".parse().unwrap(),
+            args,
+            CreationPayment::AttachedDeposit,
+        );
+    }
+
+    fn test_create_instance_msg(name: &str) -> String {
+        serde_json::json!({
+            "name": name,
+            "sputnik_dao_factory_account_id": "sputnik-dao.near",
+            "social_db_account_id": "social.near",
+            "widget_reference_account_id": "bootstrap.treasury-factory.near",
+            "options": { "dao_config": test_dao_config() },
+        })
+        .to_string()
+    }
+
+    fn token_payment_context() -> VMContextBuilder {
+        let mut context = VMContextBuilder::new();
+        context
+            .current_account_id("treasury-factory.near".parse().unwrap())
+            .predecessor_account_id("treasury-factory.near".parse().unwrap())
+            .account_balance(NearToken::from_near(100));
+        context
+    }
+
+    #[test]
+    fn ft_on_transfer_pays_for_creation_and_refunds_in_tokens() {
+        let usdc_account_id: AccountId = "usdc.near".parse().unwrap();
+        let mut context = token_payment_context();
+        testing_env!(context.build());
+        let mut contract = Contract::default();
+        contract.set_token_price(usdc_account_id.clone(), U128(25_000_000));
+
+        testing_env!(context
+            .predecessor_account_id(usdc_account_id.clone())
+            .build());
+        let unused_amount = contract.ft_on_transfer(
+            "alice.near".parse().unwrap(),
+            U128(30_000_000),
+            test_create_instance_msg("paidwithusdc"),
+        );
+        assert!(matches!(
+            unused_amount,
+            PromiseOrValue::Value(U128(5_000_000))
+        ));
+
+        let creation = contract
+            .get_creation_status("paidwithusdc".to_string())
+            .unwrap();
+        assert_eq!(creation.refund_account_id.as_str(), "alice.near");
+        assert_eq!(
+            creation.token_payment,
+            Some(TokenPayment {
+                token_account_id: usdc_account_id.clone(),
+                amount: U128(25_000_000),
+            })
+        );
+        assert_eq!(
+            contract
+                .get_instance("paidwithusdc".to_string())
+                .unwrap()
+                .creator_id
+                .as_str(),
+            "alice.near"
+        );
+
+        // Failing to create the DAO refunds its share of the price
+        contract.internal_refund("paidwithusdc", NearToken::from_near(6));
+        assert!(near_sdk::test_utils::get_logs()
+            .last()
+            .unwrap()
+            .contains(r#""token_refund":{"token_account_id":"usdc.near","amount":"16666666"}"#));
+    }
+
+    #[test]
+    #[should_panic(expected = "usdt.near is not accepted for payment")]
+    fn ft_on_transfer_rejects_unknown_tokens() {
+        let mut context = token_payment_context();
+        testing_env!(context
+            .predecessor_account_id("usdt.near".parse().unwrap())
+            .build());
+        Contract::default().ft_on_transfer(
+            "alice.near".parse().unwrap(),
+            U128(30_000_000),
+            test_create_instance_msg("paidwithusdt"),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The factory balance cannot fund a creation paid with tokens")]
+    fn ft_on_transfer_requires_factory_balance() {
+        let usdc_account_id: AccountId = "usdc.near".parse().unwrap();
+        let mut context = token_payment_context();
+        testing_env!(context.build());
+        let mut contract = Contract::default();
+        contract.set_token_price(usdc_account_id.clone(), U128(25_000_000));
+
+        testing_env!(context
+            .predecessor_account_id(usdc_account_id)
+            .account_balance(NearToken::from_near(5))
+            .build());
+        contract.ft_on_transfer(
+            "alice.near".parse().unwrap(),
+            U128(25_000_000),
+            test_create_instance_msg("paidwithusdc"),
+        );
+    }
+
+    #[test]
+    fn create_instances_queues_creations_beyond_the_batch_gas() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context
+            .predecessor_account_id("alice.near".parse().unwrap())
+            .attached_deposit(NearToken::from_near(27))
+            .prepaid_gas(Gas::from_tgas(300))
+            .build());
+        let mut contract = Contract::default();
+        let instances = ["grants", "ops", "marketing"]
+            .iter()
+            .map(|name| serde_json::from_str(&test_create_instance_msg(name)).unwrap())
+            .collect();
+        assert!(matches!(
+            contract.create_instances(instances),
+            PromiseOrValue::Promise(_)
+        ));
+
+        let grants = contract.get_creation_status("grants".to_string()).unwrap();
+        assert!(!grants.queued);
+        assert_eq!(grants.create_account, StepStatus::InProgress);
+        for name in ["ops", "marketing"] {
+            let creation = contract.get_creation_status(name.to_string()).unwrap();
+            assert!(creation.queued);
+            assert_eq!(creation.create_account, StepStatus::NotStarted);
+            assert_eq!(
+                contract
+                    .get_instance(name.to_string())
+                    .unwrap()
+                    .creator_id
+                    .as_str(),
+                "alice.near"
+            );
+        }
+
+        testing_env!(context
+            .predecessor_account_id("anyone.near".parse().unwrap())
+            .attached_deposit(NearToken::from_near(0))
+            .build());
+        contract.start_instance_creation("ops".to_string());
+        let ops = contract.get_creation_status("ops".to_string()).unwrap();
+        assert!(!ops.queued);
+        assert_eq!(ops.create_account, StepStatus::InProgress);
+    }
+
+    #[test]
+    #[should_panic(expected = "Instance creation is queued, see start_instance_creation")]
+    fn queued_creations_cannot_be_resumed() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context
+            .predecessor_account_id("alice.near".parse().unwrap())
+            .attached_deposit(NearToken::from_near(18))
+            .prepaid_gas(Gas::from_tgas(100))
+            .build());
+        let mut contract = Contract::default();
+        let instances = ["grants", "ops"]
+            .iter()
+            .map(|name| serde_json::from_str(&test_create_instance_msg(name)).unwrap())
+            .collect();
+        match contract.create_instances(instances) {
+            PromiseOrValue::Value(reports) => {
+                assert!(reports.iter().all(|report| report.queued));
+                assert_eq!(reports[1].instance_account_id.as_str(), "ops.near");
+            }
+            PromiseOrValue::Promise(_) => panic!("No creation should have been started"),
+        }
+
+        contract.resume_instance_creation("grants".to_string());
+    }
+
+    fn queue_test_creations(context: &mut VMContextBuilder, contract: &mut Contract) {
+        contract.fee_config = FeeConfig {
+            creation_fee: NearToken::from_near(1),
+            referral_share_bps: 0,
+        };
+        testing_env!(context
+            .predecessor_account_id("alice.near".parse().unwrap())
+            .attached_deposit(NearToken::from_near(20))
+            .prepaid_gas(Gas::from_tgas(100))
+            .build());
+        let instances = ["grants", "ops"]
+            .iter()
+            .map(|name| serde_json::from_str(&test_create_instance_msg(name)).unwrap())
+            .collect();
+        contract.create_instances(instances);
+    }
+
+    #[test]
+    fn queued_creations_can_be_cancelled_by_their_creator() {
+        let mut context = VMContextBuilder::new();
+        let mut contract = Contract::default();
+        queue_test_creations(&mut context, &mut contract);
+        assert_eq!(contract.fee_accounting.pending, NearToken::from_near(2));
+
+        testing_env!(context.attached_deposit(NearToken::from_near(0)).build());
+        assert!(matches!(
+            contract.cancel_queued_creation("ops".to_string()),
+            PromiseOrValue::Promise(_)
+        ));
+        assert!(
+            contract
+                .get_creation_status("grants".to_string())
+                .unwrap()
+                .queued
+        );
+        assert!(contract.get_creation_status("ops".to_string()).is_none());
+        assert!(contract.get_instance("ops".to_string()).is_none());
+        assert_eq!(
+            contract
+                .get_instances_by_creator("alice.near".parse().unwrap(), None, None)
+                .iter()
+                .map(|instance| instance.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["grants"]
+        );
+        assert_eq!(contract.fee_accounting.pending, NearToken::from_near(1));
+        assert_eq!(
+            contract.get_stats(None).totals.counters.refunded,
+            NearToken::from_near(10)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the creator can cancel a queued creation")]
+    fn only_creators_can_cancel_queued_creations() {
+        let mut context = VMContextBuilder::new();
+        let mut contract = Contract::default();
+        queue_test_creations(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id("anyone.near".parse().unwrap())
+            .build());
+        contract.cancel_queued_creation("ops".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Instance creation is not queued")]
+    fn started_creations_cannot_be_cancelled() {
+        let mut context = VMContextBuilder::new();
+        let mut contract = Contract::default();
+        queue_test_creations(&mut context, &mut contract);
+
+        testing_env!(context
+            .attached_deposit(NearToken::from_near(0))
+            .prepaid_gas(Gas::from_tgas(300))
+            .build());
+        contract.start_instance_creation("ops".to_string());
+        contract.cancel_queued_creation("ops".to_string());
+    }
+
+    fn create_test_sponsorship(context: &mut VMContextBuilder, contract: &mut Contract) -> u64 {
+        testing_env!(context
+            .predecessor_account_id("sponsor.near".parse().unwrap())
+            .attached_deposit(NearToken::from_near(18))
+            .build());
+        contract.create_sponsorship(
+            Some(vec!["grantee.near".parse().unwrap()]),
+            Some(vec![env::sha256_array(b"secret-code").into()]),
+        )
+    }
+
+    #[test]
+    fn vouchers_pay_for_creations() {
+        let mut context = VMContextBuilder::new();
+        context.current_account_id("treasury-factory.near".parse().unwrap());
+        let mut contract = Contract::default();
+        let sponsorship_id = create_test_sponsorship(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id("holder.near".parse().unwrap())
+            .attached_deposit(NearToken::from_near(0))
+            .build());
+        let mut args: CreateInstanceArgs =
+            serde_json::from_str(&test_create_instance_msg("voucherholder")).unwrap();
+        args.options.voucher = Some(Voucher::Code("secret-code".to_string()));
+        contract.internal_create_instance(
+            "holder.near".parse().unwrap(),
+            args,
+            CreationPayment::AttachedDeposit,
+        );
+
+        testing_env!(context
+            .predecessor_account_id("grantee.near".parse().unwrap())
+            .build());
+        let mut args: CreateInstanceArgs =
+            serde_json::from_str(&test_create_instance_msg("grantee")).unwrap();
+        args.options.voucher = Some(Voucher::Beneficiary { sponsorship_id });
+        contract.internal_create_instance(
+            "grantee.near".parse().unwrap(),
+            args,
+            CreationPayment::AttachedDeposit,
+        );
+
+        let sponsorship = contract.get_sponsorship(sponsorship_id).unwrap();
+        assert_eq!(sponsorship.remaining_creations(), 0);
+        let redemptions = contract.get_sponsorship_redemptions(sponsorship_id, None, None);
+        assert_eq!(redemptions.len(), 2);
+        assert_eq!(redemptions[0].redemption.name, "voucherholder");
+        assert_eq!(
+            redemptions[0].redemption.beneficiary_id.as_str(),
+            "holder.near"
+        );
+        assert_eq!(redemptions[1].redemption.voucher_hash, None);
+        assert_eq!(redemptions[1].outcome, Some(InstanceOutcome::Pending));
+
+        // Refunds of sponsored creations are credited to the sponsorship
+        let creation = contract.get_creation_status("grantee".to_string()).unwrap();
+        assert_eq!(creation.refund_account_id.as_str(), "sponsor.near");
+        assert_eq!(creation.sponsorship_id, Some(sponsorship_id));
+        assert_eq!(
+            contract
+                .get_instance("grantee".to_string())
+                .unwrap()
+                .creator_id
+                .as_str(),
+            "grantee.near"
+        );
+    }
+
+    #[test]
+    fn vouchers_of_creations_failing_before_the_account_exists_are_restored() {
+        let mut context = VMContextBuilder::new();
+        context.current_account_id("treasury-factory.near".parse().unwrap());
+        let mut contract = Contract::default();
+        let sponsorship_id = create_test_sponsorship(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id("holder.near".parse().unwrap())
+            .attached_deposit(NearToken::from_near(0))
+            .build());
+        let mut args: CreateInstanceArgs =
+            serde_json::from_str(&test_create_instance_msg("taken")).unwrap();
+        args.options.voucher = Some(Voucher::Code("secret-code".to_string()));
+        contract.internal_create_instance(
+            "holder.near".parse().unwrap(),
+            args,
+            CreationPayment::AttachedDeposit,
+        );
+
+        testing_env!(
+            context.build(),
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        contract.create_account_callback("taken".to_string());
+        assert!(near_sdk::test_utils::get_logs().last().unwrap().contains(
+            r#""event":"voucher_restored","data":{"sponsorship_id":0,"name":"taken","beneficiary_id":"holder.near"}"#
+        ));
+        let sponsorship = contract.get_sponsorship(sponsorship_id).unwrap();
+        assert_eq!(sponsorship.remaining_creations(), 2);
+        assert_eq!(sponsorship.credit, NearToken::from_near(0));
+        assert_eq!(
+            contract
+                .get_creation_status("taken".to_string())
+                .unwrap()
+                .sponsorship_id,
+            None
+        );
+
+        let (redeemed_sponsorship_id, _) = contract.internal_redeem_voucher(
+            Voucher::Code("secret-code".to_string()),
+            &"holder.near".parse().unwrap(),
+            "available",
+        );
+        assert_eq!(redeemed_sponsorship_id, sponsorship_id);
+    }
+
+    #[test]
+    fn sponsored_creations_are_resumed_with_the_credit_of_their_refunds() {
+        let mut context = VMContextBuilder::new();
+        context.current_account_id("treasury-factory.near".parse().unwrap());
+        let mut contract = Contract::default();
+        let sponsorship_id = create_test_sponsorship(&mut context, &mut contract);
+
+        testing_env!(context
+            .predecessor_account_id("grantee.near".parse().unwrap())
+            .attached_deposit(NearToken::from_near(0))
+            .build());
+        let mut args: CreateInstanceArgs =
+            serde_json::from_str(&test_create_instance_msg("grantee")).unwrap();
+        args.options.voucher = Some(Voucher::Beneficiary { sponsorship_id });
+        contract.internal_create_instance(
+            "grantee.near".parse().unwrap(),
+            args,
+            CreationPayment::AttachedDeposit,
+        );
+        let creation = contract.creations.get_mut("grantee").unwrap();
+        creation.create_account = StepStatus::Succeeded;
+        creation.upgrade_instance = StepStatus::Succeeded;
+        creation.create_dao = StepStatus::Failed;
+        creation.update_widgets = StepStatus::Failed;
+        let sputnik_dao_deposit = creation.creation_cost.sputnik_dao_deposit;
+        let social_db_deposit = creation.creation_cost.social_db_deposit;
+        contract.internal_refund("grantee", sputnik_dao_deposit);
+        contract.internal_refund("grantee", social_db_deposit);
+        assert_eq!(
+            contract.get_sponsorship(sponsorship_id).unwrap().credit,
+            sputnik_dao_deposit.saturating_add(social_db_deposit)
+        );
+
+        contract.resume_instance_creation("grantee".to_string());
+        assert_eq!(
+            contract.get_sponsorship(sponsorship_id).unwrap().credit,
+            NearToken::from_near(0)
+        );
+        let creation = contract.get_creation_status("grantee".to_string()).unwrap();
+        assert_eq!(creation.sponsorship_id, Some(sponsorship_id));
+        assert_eq!(creation.create_dao, StepStatus::InProgress);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid or already redeemed voucher")]
+    fn vouchers_can_only_be_redeemed_once() {
+        let mut context = VMContextBuilder::new();
+        context.current_account_id("treasury-factory.near".parse().unwrap());
+        let mut contract = Contract::default();
+        create_test_sponsorship(&mut context, &mut contract);
+
+        for name in ["firsttreasury", "secondtreasury"] {
+            contract.internal_redeem_voucher(
+                Voucher::Code("secret-code".to_string()),
+                &"holder.near".parse().unwrap(),
+                name,
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "someone.near is not a beneficiary of sponsorship 0")]
+    fn sponsorships_are_limited_to_their_beneficiaries() {
+        let mut context = VMContextBuilder::new();
+        context.current_account_id("treasury-factory.near".parse().unwrap());
+        let mut contract = Contract::default();
+        let sponsorship_id = create_test_sponsorship(&mut context, &mut contract);
+
+        contract.internal_redeem_voucher(
+            Voucher::Beneficiary { sponsorship_id },
+            &"someone.near".parse().unwrap(),
+            "sometreasury",
+        );
+    }
+
+    #[test]
+    fn closing_a_sponsorship_invalidates_unused_vouchers() {
+        let mut context = VMContextBuilder::new();
+        context.current_account_id("treasury-factory.near".parse().unwrap());
+        let mut contract = Contract::default();
+        let sponsorship_id = create_test_sponsorship(&mut context, &mut contract);
+
+        contract.close_sponsorship(sponsorship_id);
+        assert!(near_sdk::test_utils::get_logs().last().unwrap().contains(
+            r#""event":"sponsorship_closed","data":{"sponsorship_id":0,"refund":"18000000000000000000000000"}"#
+        ));
+        let sponsorship = contract.get_sponsorship(sponsorship_id).unwrap();
+        assert!(sponsorship.closed);
+        assert_eq!(sponsorship.remaining_creations(), 0);
+        assert_eq!(
+            contract.get_sponsorships_by_sponsor("sponsor.near".parse().unwrap(), None, None)[0].id,
+            sponsorship_id
+        );
+    }
+
+    #[test]
+    fn default_policy_templates_expand_to_valid_dao_configs() {
+        let contract = Contract::default();
+        for template in contract.get_policy_templates() {
+            let members = template
+                .roles
+                .iter()
+                .map(|role| (role.name.clone(), vec!["alice.near".parse().unwrap()]))
+                .collect();
+            let dao_config = template
+                .to_dao_config("Treasury".to_string(), members)
+                .unwrap();
+            assert!(dao_config.validate().is_ok(), "{}", template.id);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Policy template small-multisig has no role Council")]
+    fn policy_template_rejects_unknown_roles() {
+        Contract::default().internal_resolve_create_dao_args(
+            "test",
+            None,
+            None,
+            Some(PolicyTemplateArgs {
+                template_id: "small-multisig".to_string(),
+                purpose: None,
+                members: [("Council".to_string(), vec!["alice.near".parse().unwrap()])].into(),
+            }),
+        );
+    }
+
+    #[test]
+    fn instance_access_keys_follow_creator_choices() {
+        testing_env!(VMContextBuilder::new()
+            .current_account_id("treasury-factory.near".parse().unwrap())
+            .build());
+        let mut contract = Contract::default();
+        let mut creation = test_creation_status();
+        creation.creator_public_key = "ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp"
+            .parse()
+            .unwrap();
+        creation.access_keys = InstanceAccessKeys {
+            creator_key: InstanceKeyAccess::FunctionCall,
+            admin_key: InstanceKeyAccess::None,
+        };
+        contract.creations.insert("test".to_string(), creation);
+
+        let options = contract.internal_instance_access_key_options("test");
+        assert_eq!(options["full_access_keys"], serde_json::json!([]));
+        assert_eq!(
+            options["limited_access_keys"][0]["public_key"],
+            "ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp"
+        );
+        assert_eq!(
+            options["limited_access_keys"][0]["method_names"],
+            "update_widgets,self_upgrade"
+        );
+        assert_eq!(
+            options["limited_access_keys"][0]["receiver_id"],
+            "test.near"
+        );
+
+        contract.creations.get_mut("test").unwrap().access_keys = InstanceAccessKeys::default();
+        contract.admin_public_key = None;
+        let options = contract.internal_instance_access_key_options("test");
+        assert_eq!(
+            options["full_access_keys"],
+            serde_json::json!(["ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp"])
+        );
+    }
+
+    fn verify_existing_dao(policy: serde_json::Value) -> CreationStatus {
+        let factory_context = VMContextBuilder::new()
+            .current_account_id("treasury-factory.near".parse().unwrap())
+            .predecessor_account_id("treasury-factory.near".parse().unwrap())
+            .build();
+        testing_env!(factory_context.clone());
+        let mut contract = Contract::default();
+        let mut creation = test_creation_status();
+        creation.existing_dao = true;
+        creation.create_dao = StepStatus::InProgress;
+        contract.internal_start_creation(
+            creation,
+            "creator.near".parse().unwrap(),
+            "test.sputnik-dao.near".parse().unwrap(),
+        );
+
+        testing_env!(
+            factory_context,
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(policy.to_string().into_bytes())],
+        );
+        contract.verify_dao_callback("test".to_string());
+        contract.get_creation_status("test".to_string()).unwrap()
+    }
+
+    #[test]
+    fn existing_dao_creator_must_be_able_to_approve_proposals() {
+        let creation = verify_existing_dao(serde_json::json!({
+            "roles": [
+                {
+                    "kind": { "Group": ["creator.near"] },
+                    "name": "Requestor",
+                    "permissions": ["transfer:AddProposal", "call:AddProposal"]
+                },
+                {
+                    "kind": { "Group": ["council.near"] },
+                    "name": "Council",
+                    "permissions": ["*:VoteApprove", "*:VoteReject"]
+                }
+            ]
+        }));
+        assert_eq!(creation.create_dao, StepStatus::Failed);
+
+        let creation = verify_existing_dao(serde_json::json!({
+            "roles": [
+                {
+                    "kind": { "Group": ["council.near", "creator.near"] },
+                    "name": "Council",
+                    "permissions": ["*:VoteApprove", "*:VoteReject"]
+                }
+            ]
+        }));
+        assert_eq!(creation.create_dao, StepStatus::Succeeded);
+    }
+
+    fn check_dao(dao_lookup: PromiseResult) -> (Contract, CreationStatus) {
+        let factory_context = VMContextBuilder::new()
+            .current_account_id("treasury-factory.near".parse().unwrap())
+            .predecessor_account_id("treasury-factory.near".parse().unwrap())
+            .build();
+        testing_env!(factory_context.clone());
+        let mut contract = Contract::default();
+        let mut creation = test_creation_status();
+        creation.create_account = StepStatus::InProgress;
+        creation.fee = NearToken::from_near(1);
+        contract.internal_start_creation(
+            creation,
+            "creator.near".parse().unwrap(),
+            "test.sputnik-dao.near".parse().unwrap(),
+        );
+
+        testing_env!(
+            factory_context,
+            near_sdk::test_vm_config(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![dao_lookup],
+        );
+        contract.check_dao_callback("test".to_string());
+        let creation = contract.get_creation_status("test".to_string()).unwrap();
+        (contract, creation)
+    }
+
+    #[test]
+    fn existing_dao_fails_creation_before_the_account_is_created() {
+        let (contract, creation) = check_dao(PromiseResult::Successful(b"{}".to_vec()));
+        assert_eq!(creation.create_account, StepStatus::NotStarted);
+        assert_eq!(creation.create_dao, StepStatus::Failed);
+        assert_eq!(
+            creation.refunded,
+            CreationCost::default()
+                .total()
+                .saturating_add(NearToken::from_near(1))
+        );
+        assert_eq!(creation.fee, NearToken::from_near(0));
+        assert_eq!(
+            contract.get_instance("test".to_string()).unwrap().outcome,
+            InstanceOutcome::DaoCreationFailed
+        );
+        assert!(near_sdk::test_utils::get_logs()
+            .contains(&"DAO test.sputnik-dao.near already exists".to_string()));
+    }
+
+    #[test]
+    fn missing_dao_lets_the_account_be_created() {
+        let (_, creation) = check_dao(PromiseResult::Failed);
+        assert_eq!(creation.create_account, StepStatus::InProgress);
+        assert_eq!(creation.create_dao, StepStatus::NotStarted);
+        assert_eq!(creation.refunded, NearToken::from_near(0));
+    }
 }
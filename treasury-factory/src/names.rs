@@ -0,0 +1,218 @@
+use near_sdk::{env, near, AccountId};
+
+use crate::{Contract, ContractExt, InstanceOutcome};
+
+const MIN_NAME_LENGTH: usize = 2;
+const MAX_ACCOUNT_ID_LENGTH: usize = 64;
+
+/// Names that could be mistaken for accounts of the treasury service itself.
+pub const RESERVED_NAMES: &[&str] = &[
+    "admin",
+    "app",
+    "bootstrap",
+    "dao",
+    "factory",
+    "near",
+    "neartreasury",
+    "social",
+    "sputnik-dao",
+    "treasury",
+    "treasury-factory",
+    "www",
+];
+
+/// Whether a name can be used to create an instance, see `check_name`.
+#[near(serializers = [json])]
+#[derive(Debug, PartialEq)]
+pub struct NameCheck {
+    pub name: String,
+    /// The name the instance is registered under, which is also the name of its DAO.
+    /// Differs from `name` for instances under a parent account, see `instance_name`.
+    pub instance_name: String,
+    /// `None` if the name does not make a valid instance account id
+    pub instance_account_id: Option<AccountId>,
+    /// `None` if the name does not make a valid DAO account id
+    pub dao_account_id: Option<AccountId>,
+    pub available: bool,
+    /// Why the name is not available, empty if it is
+    pub reasons: Vec<String>,
+}
+
+/// Checks that `name` is a single lowercase account id label.
+fn name_format_errors(name: &str) -> Vec<String> {
+    let mut errors = vec![];
+    if name.len() < MIN_NAME_LENGTH {
+        errors.push(format!(
+            "Name must be at least {} characters",
+            MIN_NAME_LENGTH
+        ));
+    }
+    let is_separator = |c: char| c == '-' || c == '_';
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || is_separator(c))
+    {
+        errors.push("Name can only contain lowercase letters, digits, - and _".to_string());
+    }
+    if name.starts_with(is_separator) || name.ends_with(is_separator) {
+        errors.push("Name cannot start or end with - or _".to_string());
+    }
+    if name
+        .as_bytes()
+        .windows(2)
+        .any(|pair| is_separator(pair[0] as char) && is_separator(pair[1] as char))
+    {
+        errors.push("Name cannot contain consecutive - or _".to_string());
+    }
+    errors
+}
+
+/// The name an instance is registered under, and of its DAO: its account id without the
+/// top-level account, with `-` in place of the dots. `treasury` under `myorg.near` is
+/// registered as `treasury-myorg` and managed by `treasury-myorg.<sputnik factory>`.
+pub fn instance_name(name: &str, parent_account_id: Option<&AccountId>) -> String {
+    match parent_account_id
+        .and_then(|parent_account_id| parent_account_id.as_str().rsplit_once('.'))
+    {
+        Some((parent_name, _)) => format!("{}-{}", name, parent_name.replace('.', "-")),
+        None => name.to_string(),
+    }
+}
+
+/// The name the instance `instance_account_id` is registered under, see `instance_name`.
+pub fn instance_name_of_account(instance_account_id: &AccountId) -> String {
+    let account_id = instance_account_id.as_str();
+    account_id
+        .rsplit_once('.')
+        .map_or(account_id, |(name, _)| name)
+        .replace('.', "-")
+}
+
+/// `<name>.<parent_account_id>` if it is a valid account id, or the reason it is not.
+fn sub_account_id(name: &str, parent_account_id: &str) -> Result<AccountId, String> {
+    let account_id = format!("{}.{}", name, parent_account_id);
+    if account_id.len() > MAX_ACCOUNT_ID_LENGTH {
+        return Err(format!(
+            "{} is longer than {} characters",
+            account_id, MAX_ACCOUNT_ID_LENGTH
+        ));
+    }
+    account_id
+        .parse()
+        .map_err(|_| format!("{} is not a valid account id", account_id))
+}
+
+impl Contract {
+    /// Checks `name` for an instance under `parent_account_id`, or a top-level instance if
+    /// `None`, with a DAO created by `sputnik_dao_factory_account_id`.
+    pub(crate) fn internal_check_name(
+        &self,
+        name: &str,
+        parent_account_id: Option<&AccountId>,
+        sputnik_dao_factory_account_id: &str,
+    ) -> NameCheck {
+        let mut reasons = name_format_errors(name);
+        match parent_account_id {
+            Some(parent_account_id) => {
+                if !self.parent_accounts.contains_key(parent_account_id) {
+                    reasons.push(format!(
+                        "{} is not registered as a parent account",
+                        parent_account_id
+                    ));
+                }
+            }
+            // Only top-level names can be mistaken for the treasury service
+            None => {
+                if RESERVED_NAMES.contains(&name) {
+                    reasons.push(format!("{} is a reserved name", name));
+                }
+            }
+        }
+        let instance_name = instance_name(name, parent_account_id);
+        let instance_account_id = sub_account_id(
+            name,
+            parent_account_id
+                .unwrap_or(&self.network_config.registrar_account_id)
+                .as_str(),
+        );
+        let dao_account_id = sub_account_id(&instance_name, sputnik_dao_factory_account_id);
+        // Account id errors only add information when the name itself is well-formed
+        if reasons.is_empty() {
+            for result in [&instance_account_id, &dao_account_id] {
+                if let Err(reason) = result {
+                    reasons.push(reason.clone());
+                }
+            }
+        }
+        let is_retired = self
+            .instances
+            .get(&instance_name)
+            .is_some_and(|instance| instance.outcome == InstanceOutcome::Retired);
+        if is_retired {
+            reasons.push(format!(
+                "Instance {} was retired and its name cannot be reused",
+                instance_name
+            ));
+        } else if self.internal_is_name_registered(&instance_name) {
+            reasons.push(format!("Instance {} is already registered", instance_name));
+        }
+
+        NameCheck {
+            name: name.to_string(),
+            instance_name,
+            instance_account_id: instance_account_id.ok(),
+            dao_account_id: dao_account_id.ok(),
+            available: reasons.is_empty(),
+            reasons,
+        }
+    }
+
+    /// Panics unless `name` is available, and returns its check with the account ids.
+    pub(crate) fn internal_assert_valid_name(
+        &self,
+        name: &str,
+        parent_account_id: Option<&AccountId>,
+        sputnik_dao_factory_account_id: &str,
+    ) -> NameCheck {
+        let name_check =
+            self.internal_check_name(name, parent_account_id, sputnik_dao_factory_account_id);
+        if !name_check.available {
+            env::panic_str(&format!(
+                "Invalid name {}: {}",
+                name,
+                name_check.reasons.join(", ")
+            ));
+        }
+        name_check
+    }
+}
+
+#[near]
+impl Contract {
+    /// Reports whether `name.<registrar>` and `name.<sputnik factory>` can be used for
+    /// a new instance, where the sputnik factory defaults to the first trusted one.
+    /// With `parent_account_id`, checks `name.<parent account>` and its DAO instead.
+    /// Only names registered in this factory are known to be taken, so accounts that
+    /// were created elsewhere have to be looked up on chain. `create_instance` looks up
+    /// the DAO itself and refunds the creation in full if it already exists.
+    pub fn check_name(
+        &self,
+        name: String,
+        sputnik_dao_factory_account_id: Option<AccountId>,
+        parent_account_id: Option<AccountId>,
+    ) -> NameCheck {
+        let sputnik_dao_factory_account_id = sputnik_dao_factory_account_id
+            .or_else(|| {
+                self.trusted_accounts
+                    .sputnik_dao_factory_account_ids
+                    .first()
+                    .cloned()
+            })
+            .unwrap_or_else(|| env::panic_str("No trusted sputnik DAO factory"));
+        self.internal_check_name(
+            &name,
+            parent_account_id.as_ref(),
+            sputnik_dao_factory_account_id.as_str(),
+        )
+    }
+}
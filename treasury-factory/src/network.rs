@@ -0,0 +1,32 @@
+use near_sdk::{env, near, AccountId};
+
+/// The account layout of the network the factory is deployed to. Defaults to
+/// mainnet, or testnet when built with the `testnet` feature. Sandbox deployments
+/// that import mainnet accounts use the mainnet layout.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetworkConfig {
+    /// Top-level account whose `create_account_advanced` method creates the
+    /// instance accounts, which are named `<name>.<registrar_account_id>`
+    pub registrar_account_id: AccountId,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        #[cfg(not(feature = "testnet"))]
+        let registrar_account_id = "near";
+        #[cfg(feature = "testnet")]
+        let registrar_account_id = "testnet";
+        Self {
+            registrar_account_id: registrar_account_id.parse().unwrap(),
+        }
+    }
+}
+
+impl NetworkConfig {
+    pub fn instance_account_id(&self, name: &str) -> AccountId {
+        format!("{}.{}", name, self.registrar_account_id)
+            .parse()
+            .unwrap_or_else(|_| env::panic_str(&format!("Invalid instance name {}", name)))
+    }
+}
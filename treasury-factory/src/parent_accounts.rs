@@ -0,0 +1,103 @@
+use near_sdk::{env, near, require, AccountId, Promise};
+
+use crate::{Contract, ContractExt, FactoryEvent};
+
+/// The account that creates `instance_account_id`, which is the top-level registrar or
+/// a registered parent account.
+pub fn parent_account_of(instance_account_id: &AccountId) -> AccountId {
+    instance_account_id
+        .as_str()
+        .split_once('.')
+        .map(|(_, parent_account_id)| parent_account_id.parse().unwrap())
+        .unwrap()
+}
+
+impl Contract {
+    /// Panics unless `creator_id` may create instances under `parent_account_id`.
+    pub(crate) fn internal_assert_parent_account_creator(
+        &self,
+        parent_account_id: &AccountId,
+        creator_id: &AccountId,
+    ) {
+        let creators = self
+            .parent_accounts
+            .get(parent_account_id)
+            .unwrap_or_else(|| {
+                env::panic_str(&format!(
+                    "{} is not registered as a parent account",
+                    parent_account_id
+                ))
+            });
+        require!(
+            creator_id == parent_account_id || creators.contains(creator_id),
+            format!(
+                "{} cannot create instances under {}",
+                creator_id, parent_account_id
+            )
+        );
+    }
+}
+
+#[near]
+impl Contract {
+    /// Lets the factory create instances as sub-accounts of the caller, for the caller
+    /// and for `creators`, replacing the creators of a previous registration. The
+    /// caller must run a contract with the `create_account_advanced` method of the
+    /// registrar, such as the linkdrop contract of `near`, that accepts calls from the
+    /// factory. The attached deposit pays for the storage of the registration.
+    #[payable]
+    pub fn register_parent_account(&mut self, creators: Vec<AccountId>) {
+        let parent_account_id = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+        self.parent_accounts
+            .insert(parent_account_id.clone(), creators.clone());
+        self.parent_accounts.flush();
+        let storage_cost = env::storage_byte_cost().saturating_mul(
+            env::storage_usage()
+                .saturating_sub(initial_storage_usage)
+                .into(),
+        );
+        self.internal_take_deposit(storage_cost, "register the parent account");
+
+        FactoryEvent::ParentAccountRegistered {
+            parent_account_id,
+            creators,
+        }
+        .emit();
+    }
+
+    /// Stops the creation of instances under the caller, and returns the storage
+    /// deposit of the registration. Existing instances are not affected.
+    pub fn unregister_parent_account(&mut self) -> Promise {
+        let parent_account_id = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+        require!(
+            self.parent_accounts.remove(&parent_account_id).is_some(),
+            format!(
+                "{} is not registered as a parent account",
+                parent_account_id
+            )
+        );
+        self.parent_accounts.flush();
+        let storage_refund = env::storage_byte_cost().saturating_mul(
+            initial_storage_usage
+                .saturating_sub(env::storage_usage())
+                .into(),
+        );
+
+        FactoryEvent::ParentAccountUnregistered {
+            parent_account_id: parent_account_id.clone(),
+        }
+        .emit();
+        Promise::new(parent_account_id).transfer(storage_refund)
+    }
+
+    /// The accounts besides the parent account itself that may create instances under
+    /// it, or `None` if it is not registered.
+    pub fn get_parent_account_creators(
+        &self,
+        parent_account_id: AccountId,
+    ) -> Option<Vec<AccountId>> {
+        self.parent_accounts.get(&parent_account_id).cloned()
+    }
+}
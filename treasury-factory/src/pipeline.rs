@@ -0,0 +1,239 @@
+use near_sdk::{
+    env, json_types::U128, near, require, AccountId, Gas, NearToken, Promise, PromiseOrValue,
+    PublicKey,
+};
+
+use crate::{
+    fungible_token, AdminPermission, Contract, ContractExt, CreationCost, FactoryEvent,
+    InstanceAccessKeys, InstanceBranding, InstanceOutcome,
+};
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StepStatus {
+    NotStarted,
+    InProgress,
+    Succeeded,
+    Failed,
+}
+
+/// Fungible tokens that paid for a creation in place of the NEAR deposits.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct TokenPayment {
+    pub token_account_id: AccountId,
+    pub amount: U128,
+}
+
+/// A creation attempt and the status of each of its steps. The parameters are
+/// kept so that failed steps can be retried with `resume_instance_creation`.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug)]
+pub struct CreationStatus {
+    pub name: String,
+    pub instance_account_id: AccountId,
+    pub refund_account_id: AccountId,
+    pub creator_public_key: PublicKey,
+    pub sputnik_dao_factory_account_id: String,
+    pub social_db_account_id: String,
+    pub widget_reference_account_id: String,
+    pub create_dao_args: String,
+    /// The instance is attached to a DAO that already exists, whose membership
+    /// check takes the place of the `create_dao` step.
+    pub existing_dao: bool,
+    pub delete_stub_on_failure: bool,
+    pub access_keys: InstanceAccessKeys,
+    /// Written to the social metadata of the instance by the `update_widgets` step
+    pub branding: Option<InstanceBranding>,
+    /// Set if the creation was paid with tokens, which are then refunded in place
+    /// of the NEAR deposits of failed steps
+    pub token_payment: Option<TokenPayment>,
+    /// Set if the creation was paid with a voucher, whose sponsorship is then credited
+    /// with the refunds of failed steps
+    pub sponsorship_id: Option<u64>,
+    pub creation_cost: CreationCost,
+    /// Creation fee held until the instance is created, see `FeeAccounting`
+    pub fee: NearToken,
+    /// Fee refunded after a failed step, which is charged again if the creation is resumed
+    pub released_fee: NearToken,
+    pub referrer_id: Option<AccountId>,
+    pub refunded: NearToken,
+    /// Paid for by a `create_instances` batch that had no gas left to start it, see
+    /// `start_instance_creation`
+    pub queued: bool,
+    pub create_account: StepStatus,
+    pub upgrade_instance: StepStatus,
+    pub create_dao: StepStatus,
+    pub update_widgets: StepStatus,
+}
+
+impl CreationStatus {
+    fn steps(&self) -> [StepStatus; 4] {
+        [
+            self.create_account,
+            self.upgrade_instance,
+            self.create_dao,
+            self.update_widgets,
+        ]
+    }
+
+    pub fn is_in_progress(&self) -> bool {
+        self.steps().contains(&StepStatus::InProgress)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.steps()
+            .iter()
+            .all(|step| *step == StepStatus::Succeeded)
+    }
+
+    /// The deposit needed to run every step that has not succeeded yet. Deposits
+    /// of failed steps have already been refunded, so they are charged again.
+    pub fn required_deposit(&self, creation_cost: &CreationCost) -> NearToken {
+        let mut required_deposit = NearToken::from_yoctonear(0);
+        if self.create_account != StepStatus::Succeeded {
+            required_deposit =
+                required_deposit.saturating_add(creation_cost.instance_account_deposit);
+        }
+        if self.create_dao != StepStatus::Succeeded && !self.existing_dao {
+            required_deposit = required_deposit.saturating_add(creation_cost.sputnik_dao_deposit);
+        }
+        if self.update_widgets != StepStatus::Succeeded {
+            required_deposit = required_deposit.saturating_add(creation_cost.social_db_deposit);
+        }
+        required_deposit
+    }
+}
+
+/// The part of `token_amount` that pays for `amount` out of `total`. Computed in
+/// milliNEAR, which is precise enough for the creation deposits and cannot overflow
+/// for token amounts below 10^34.
+fn token_share(token_amount: U128, amount: NearToken, total: NearToken) -> U128 {
+    if total.as_millinear() == 0 {
+        return U128(0);
+    }
+    U128(token_amount.0 * amount.as_millinear().min(total.as_millinear()) / total.as_millinear())
+}
+
+impl Contract {
+    pub(crate) fn internal_get_creation_mut(&mut self, name: &str) -> &mut CreationStatus {
+        self.creations
+            .get_mut(name)
+            .unwrap_or_else(|| env::panic_str(&format!("No creation found for {}", name)))
+    }
+
+    /// Returns `amount` to the account paying for the creation of `name`, or the
+    /// matching share of its tokens if the creation was paid with tokens. Refunds of
+    /// sponsored creations are credited to their sponsorship instead.
+    pub(crate) fn internal_refund(&mut self, name: &str, amount: NearToken) -> PromiseOrValue<()> {
+        self.internal_update_stats(|counters| {
+            counters.refunds += 1;
+            counters.refunded = counters.refunded.saturating_add(amount);
+        });
+        let creation = self.internal_get_creation_mut(name);
+        creation.refunded = creation.refunded.saturating_add(amount);
+        if self.internal_credit_sponsorship(name, amount) {
+            return PromiseOrValue::Value(());
+        }
+        let creation = self.internal_get_creation_mut(name);
+        let token_refund = creation.token_payment.as_ref().map(|payment| TokenPayment {
+            token_account_id: payment.token_account_id.clone(),
+            amount: token_share(payment.amount, amount, creation.creation_cost.total()),
+        });
+        FactoryEvent::RefundIssued {
+            name: name.to_string(),
+            account_id: creation.refund_account_id.clone(),
+            amount,
+            token_refund: token_refund.clone(),
+        }
+        .emit();
+        match token_refund {
+            Some(token_refund) => fungible_token::ext(token_refund.token_account_id)
+                .with_attached_deposit(NearToken::from_yoctonear(1))
+                .with_static_gas(Gas::from_tgas(10))
+                .ft_transfer(
+                    creation.refund_account_id.clone(),
+                    token_refund.amount,
+                    None,
+                )
+                .into(),
+            None => Promise::new(creation.refund_account_id.clone())
+                .transfer(amount)
+                .into(),
+        }
+    }
+}
+
+#[near]
+impl Contract {
+    pub fn get_creation_status(&self, name: String) -> Option<CreationStatus> {
+        self.creations.get(&name).cloned()
+    }
+
+    /// Retries the steps of a creation that failed. Can be called by the creator, the
+    /// account that paid for it or an admin with the `InstanceCreations` permission,
+    /// who must attach the deposits of the retried steps and the fee refunded with a
+    /// failed step, unless the creation is sponsored and the credit of its sponsorship
+    /// covers them.
+    #[payable]
+    pub fn resume_instance_creation(&mut self, name: String) -> PromiseOrValue<()> {
+        let caller = env::predecessor_account_id();
+        let is_admin = self.internal_has_permission(&caller, AdminPermission::InstanceCreations);
+        let is_creator = self
+            .instances
+            .get(&name)
+            .is_some_and(|instance| instance.creator_id == caller);
+        let creation_cost = self.creation_cost.clone();
+        let creation = self.internal_get_creation_mut(&name);
+        require!(
+            caller == creation.refund_account_id || is_creator || is_admin,
+            "Only the creator or a factory admin can resume an instance creation"
+        );
+        require!(
+            !creation.queued,
+            "Instance creation is queued, see start_instance_creation"
+        );
+        require!(
+            !creation.is_in_progress(),
+            "Instance creation is still in progress"
+        );
+        require!(
+            !creation.is_complete(),
+            "Instance creation is already complete"
+        );
+
+        let released_fee = creation.released_fee;
+        let required_deposit = creation
+            .required_deposit(&creation_cost)
+            .saturating_add(released_fee);
+        creation.creation_cost = creation_cost;
+
+        if self.internal_spend_sponsorship_credit(&name, required_deposit) {
+            self.internal_take_deposit(
+                NearToken::from_near(0),
+                "resume treasury instance creation",
+            );
+        } else {
+            let creation = self.internal_get_creation_mut(&name);
+            creation.refund_account_id = caller;
+            // The retried steps are paid with the attached NEAR, so they are refunded in NEAR
+            // to the caller, even if the creation was paid with tokens or a voucher
+            creation.token_payment = None;
+            creation.sponsorship_id = None;
+            self.internal_take_deposit(required_deposit, "resume treasury instance creation");
+        }
+        let creation = self.internal_get_creation_mut(&name);
+        creation.fee = creation.fee.saturating_add(released_fee);
+        creation.released_fee = NearToken::from_near(0);
+        self.internal_hold_fee(released_fee);
+        // An instance whose DAO exists stays created while its other steps are retried
+        if self
+            .instances
+            .get(&name)
+            .is_some_and(|instance| instance.outcome != InstanceOutcome::Created)
+        {
+            self.internal_set_instance_outcome(&name, InstanceOutcome::Pending);
+        }
+        self.internal_run_creation_steps(&name)
+    }
+}
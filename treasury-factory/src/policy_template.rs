@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+
+use near_sdk::{env, json_types::U64, near, AccountId, NearToken};
+
+use crate::{AdminPermission, Contract, ContractExt, DaoConfig, DaoRole, VoteThreshold};
+
+const ONE_WEEK_NS: u64 = 604_800_000_000_000;
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug)]
+pub struct PolicyTemplateRole {
+    pub name: String,
+    pub permissions: Vec<String>,
+    pub vote_threshold: Option<VoteThreshold>,
+}
+
+/// A named DAO policy without members, expanded into a `DaoConfig` when
+/// `create_instance` is called with the members of each role.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug)]
+pub struct PolicyTemplate {
+    pub id: String,
+    pub description: String,
+    pub roles: Vec<PolicyTemplateRole>,
+    pub default_vote_threshold: VoteThreshold,
+    pub proposal_bond: NearToken,
+    /// How long proposals are open for voting, in nanoseconds
+    pub voting_period: U64,
+}
+
+/// The template to create the DAO from, and the members of each of its roles.
+#[near(serializers = [json])]
+#[derive(Clone, Debug)]
+pub struct PolicyTemplateArgs {
+    pub template_id: String,
+    pub purpose: Option<String>,
+    pub members: HashMap<String, Vec<AccountId>>,
+}
+
+impl PolicyTemplate {
+    pub fn to_dao_config(
+        &self,
+        purpose: String,
+        mut members: HashMap<String, Vec<AccountId>>,
+    ) -> Result<DaoConfig, String> {
+        if let Some(role_name) = members
+            .keys()
+            .find(|role_name| !self.roles.iter().any(|role| &role.name == *role_name))
+        {
+            return Err(format!(
+                "Policy template {} has no role {}",
+                self.id, role_name
+            ));
+        }
+        Ok(DaoConfig {
+            purpose,
+            roles: self
+                .roles
+                .iter()
+                .map(|role| DaoRole {
+                    name: role.name.clone(),
+                    members: members.remove(&role.name).unwrap_or_default(),
+                    permissions: role.permissions.clone(),
+                    vote_threshold: role.vote_threshold.clone(),
+                })
+                .collect(),
+            default_vote_threshold: self.default_vote_threshold.clone(),
+            proposal_bond: self.proposal_bond,
+            voting_period: self.voting_period,
+        })
+    }
+}
+
+fn template_role(
+    name: &str,
+    permissions: &[&str],
+    vote_threshold: Option<VoteThreshold>,
+) -> PolicyTemplateRole {
+    PolicyTemplateRole {
+        name: name.to_string(),
+        permissions: permissions
+            .iter()
+            .map(|permission| permission.to_string())
+            .collect(),
+        vote_threshold,
+    }
+}
+
+pub fn default_policy_templates() -> Vec<PolicyTemplate> {
+    let majority = VoteThreshold::Ratio(1, 2);
+    let one_vote = VoteThreshold::Weight(1.into());
+    let requestor_permissions = [
+        "call:AddProposal",
+        "transfer:AddProposal",
+        "call:VoteRemove",
+        "transfer:VoteRemove",
+    ];
+    vec![
+        PolicyTemplate {
+            id: "small-multisig".to_string(),
+            description: "A single group of signers that can do everything by majority vote"
+                .to_string(),
+            roles: vec![template_role("Signer", &["*:*"], Some(majority.clone()))],
+            default_vote_threshold: majority.clone(),
+            proposal_bond: NearToken::from_millinear(100),
+            voting_period: ONE_WEEK_NS.into(),
+        },
+        PolicyTemplate {
+            id: "council-requestor".to_string(),
+            description: "Requestors propose payments, the council approves by majority vote"
+                .to_string(),
+            roles: vec![
+                template_role("Requestor", &requestor_permissions, None),
+                template_role("Council", &["*:*"], Some(majority.clone())),
+            ],
+            default_vote_threshold: majority.clone(),
+            proposal_bond: NearToken::from_millinear(100),
+            voting_period: ONE_WEEK_NS.into(),
+        },
+        PolicyTemplate {
+            id: "foundation".to_string(),
+            description: "Separate requestor, admin and approver roles, as in the create wizard"
+                .to_string(),
+            roles: vec![
+                template_role("Requestor", &requestor_permissions, Some(one_vote.clone())),
+                template_role(
+                    "Admin",
+                    &[
+                        "config:*",
+                        "policy:*",
+                        "add_member_to_role:*",
+                        "remove_member_from_role:*",
+                        "upgrade_self:*",
+                        "upgrade_remote:*",
+                        "set_vote_token:*",
+                        "add_bounty:*",
+                        "bounty_done:*",
+                        "factory_info_update:*",
+                        "policy_add_or_update_role:*",
+                        "policy_remove_role:*",
+                        "policy_update_default_vote_policy:*",
+                        "policy_update_parameters:*",
+                    ],
+                    Some(one_vote.clone()),
+                ),
+                template_role(
+                    "Approver",
+                    &[
+                        "call:VoteReject",
+                        "call:VoteApprove",
+                        "call:RemoveProposal",
+                        "call:Finalize",
+                        "transfer:VoteReject",
+                        "transfer:VoteApprove",
+                        "transfer:RemoveProposal",
+                        "transfer:Finalize",
+                    ],
+                    Some(one_vote),
+                ),
+            ],
+            default_vote_threshold: majority,
+            proposal_bond: NearToken::from_millinear(100),
+            voting_period: ONE_WEEK_NS.into(),
+        },
+    ]
+}
+
+impl Contract {
+    pub(crate) fn internal_expand_policy_template(
+        &self,
+        name: &str,
+        policy_template: PolicyTemplateArgs,
+    ) -> DaoConfig {
+        let template = self
+            .policy_templates
+            .iter()
+            .find(|template| template.id == policy_template.template_id)
+            .unwrap_or_else(|| {
+                env::panic_str(&format!(
+                    "Policy template {} not found",
+                    policy_template.template_id
+                ))
+            });
+        let purpose = policy_template
+            .purpose
+            .unwrap_or_else(|| format!("creating {} treasury", name));
+        template
+            .to_dao_config(purpose, policy_template.members)
+            .unwrap_or_else(|error| env::panic_str(&error))
+    }
+}
+
+#[near]
+impl Contract {
+    pub fn get_policy_templates(&self) -> Vec<PolicyTemplate> {
+        self.policy_templates.clone()
+    }
+
+    pub fn get_policy_template(&self, template_id: String) -> Option<PolicyTemplate> {
+        self.policy_templates
+            .iter()
+            .find(|template| template.id == template_id)
+            .cloned()
+    }
+
+    /// Adds a policy template, or replaces the one with the same id.
+    pub fn set_policy_template(&mut self, template: PolicyTemplate) {
+        self.assert_admin_action(AdminPermission::PolicyTemplates, "set_policy_template");
+        // Validate the template as if the owner was a member of every role
+        let members = template
+            .roles
+            .iter()
+            .map(|role| (role.name.clone(), vec![self.owner_id.clone()]))
+            .collect();
+        if let Err(error) = template
+            .to_dao_config(template.description.clone(), members)
+            .and_then(|dao_config| dao_config.validate())
+        {
+            env::panic_str(&format!("Invalid policy template: {}", error));
+        }
+
+        self.policy_templates
+            .retain(|existing_template| existing_template.id != template.id);
+        self.policy_templates.push(template);
+    }
+
+    pub fn remove_policy_template(&mut self, template_id: String) {
+        self.assert_admin_action(AdminPermission::PolicyTemplates, "remove_policy_template");
+        self.policy_templates
+            .retain(|template| template.id != template_id);
+    }
+}
@@ -0,0 +1,93 @@
+use near_sdk::{env, near, require, NearToken, Promise};
+
+use crate::{AdminPermission, Contract, ContractExt};
+
+/// Deposits forwarded to the accounts and contracts involved in creating an instance.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CreationCost {
+    pub sputnik_dao_deposit: NearToken,
+    pub social_db_deposit: NearToken,
+    pub instance_account_deposit: NearToken,
+}
+
+impl Default for CreationCost {
+    fn default() -> Self {
+        Self {
+            sputnik_dao_deposit: NearToken::from_near(6),
+            social_db_deposit: NearToken::from_millinear(500),
+            instance_account_deposit: NearToken::from_millinear(2500),
+        }
+    }
+}
+
+impl CreationCost {
+    pub fn total(&self) -> NearToken {
+        self.sputnik_dao_deposit
+            .saturating_add(self.total_for_existing_dao())
+    }
+
+    pub fn total_for_existing_dao(&self) -> NearToken {
+        self.social_db_deposit
+            .saturating_add(self.instance_account_deposit)
+    }
+}
+
+#[near(serializers = [json])]
+pub struct CreationCostView {
+    pub sputnik_dao_deposit: NearToken,
+    pub social_db_deposit: NearToken,
+    pub instance_account_deposit: NearToken,
+    /// Charged on top of the deposits, see `get_fee_config`
+    pub creation_fee: NearToken,
+    /// The deposits and the fee to attach to `create_instance`
+    pub total: NearToken,
+    /// The total when attaching an instance to an existing DAO
+    pub total_for_existing_dao: NearToken,
+}
+
+impl Contract {
+    /// Panics unless at least `required_deposit` is attached and refunds the excess.
+    pub(crate) fn internal_take_deposit(&self, required_deposit: NearToken, action: &str) {
+        let attached_deposit = env::attached_deposit();
+        if attached_deposit < required_deposit {
+            env::panic_str(&format!(
+                "Must attach at least {} to {}",
+                required_deposit, action
+            ));
+        }
+        let overpayment = attached_deposit.saturating_sub(required_deposit);
+        if !overpayment.is_zero() {
+            Promise::new(env::predecessor_account_id()).transfer(overpayment);
+        }
+    }
+}
+
+#[near]
+impl Contract {
+    pub fn get_creation_cost(&self) -> CreationCostView {
+        CreationCostView {
+            sputnik_dao_deposit: self.creation_cost.sputnik_dao_deposit,
+            social_db_deposit: self.creation_cost.social_db_deposit,
+            instance_account_deposit: self.creation_cost.instance_account_deposit,
+            creation_fee: self.fee_config.creation_fee,
+            total: self
+                .creation_cost
+                .total()
+                .saturating_add(self.fee_config.creation_fee),
+            total_for_existing_dao: self
+                .creation_cost
+                .total_for_existing_dao()
+                .saturating_add(self.fee_config.creation_fee),
+        }
+    }
+
+    pub fn set_creation_cost(&mut self, creation_cost: CreationCost) {
+        self.assert_admin_action(AdminPermission::CreationCost, "set_creation_cost");
+        require!(
+            !creation_cost.instance_account_deposit.is_zero(),
+            "Instance account deposit must be greater than zero"
+        );
+        self.creation_cost = creation_cost;
+    }
+}
@@ -0,0 +1,166 @@
+use near_sdk::{env, near, Gas, PromiseResult};
+
+use crate::{
+    instance_contract, registry::DEFAULT_PAGE_LIMIT, AdminPermission, Contract, ContractExt,
+    FactoryEvent, InstanceOutcome, InstanceRecord,
+};
+
+/// Gas for an instance to switch to a global contract release.
+const PUSH_UPGRADE_GLOBAL_CONTRACT_GAS: Gas = Gas::from_tgas(50);
+/// Gas for an instance to fetch and deploy the wasm bytes of a release.
+const PUSH_UPGRADE_CONTRACT_BYTES_GAS: Gas = Gas::from_tgas(250);
+const PUSH_UPGRADE_CALLBACK_GAS: Gas = Gas::from_tgas(5);
+/// Gas kept for finishing the batch after the last scheduled upgrade.
+const PUSH_UPGRADE_RESERVED_GAS: Gas = Gas::from_tgas(20);
+/// Bounds the registry entries read by one batch, most of which may be skipped.
+const PUSH_UPGRADE_MAX_SCAN: usize = 100;
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub enum PushUpgradeStatus {
+    InProgress {
+        version: Option<String>,
+    },
+    Upgraded {
+        version: Option<String>,
+    },
+    /// The instance has not opted into auto-update
+    Refused {
+        version: Option<String>,
+    },
+    /// The upgrade call failed, for example because the instance runs a web4
+    /// build that predates push upgrades
+    Failed {
+        version: Option<String>,
+    },
+}
+
+#[near(serializers = [json])]
+pub struct PushUpgradeBatch {
+    /// Instances that were asked to upgrade in this batch
+    pub scheduled: Vec<String>,
+    /// Where to continue with the next batch, or `None` if all instances were visited
+    pub next_index: Option<u32>,
+}
+
+#[near]
+impl Contract {
+    /// Asks created instances, starting at `from_index`, to upgrade to the latest
+    /// web4 release. Schedules as many upgrades as the attached gas allows, and only
+    /// instances that opted into auto-update accept them. A release that is only
+    /// available as wasm bytes needs most of the gas of a transaction to deploy, so
+    /// one instance is upgraded per call, and `next_index` points to the next one.
+    pub fn push_instance_upgrades(&mut self, from_index: Option<u32>) -> PushUpgradeBatch {
+        self.assert_admin_action(AdminPermission::InstanceUpgrades, "push_instance_upgrades");
+        let version = self.latest_web4_version.clone();
+        let (upgrade_gas, max_scheduled) = if self.get_web4_global_contract_hash(None).is_some() {
+            (PUSH_UPGRADE_GLOBAL_CONTRACT_GAS, PUSH_UPGRADE_MAX_SCAN)
+        } else {
+            (PUSH_UPGRADE_CONTRACT_BYTES_GAS, 1)
+        };
+        let gas_per_instance = upgrade_gas.saturating_add(PUSH_UPGRADE_CALLBACK_GAS);
+
+        let from_index = from_index.unwrap_or(0);
+        let names: Vec<String> = self
+            .instances
+            .keys()
+            .skip(from_index as usize)
+            .take(PUSH_UPGRADE_MAX_SCAN)
+            .cloned()
+            .collect();
+        let scanned_until = from_index + names.len() as u32;
+
+        let mut scheduled = vec![];
+        let mut next_index = None;
+        for (offset, name) in names.into_iter().enumerate() {
+            let remaining_gas = env::prepaid_gas().saturating_sub(env::used_gas());
+            if scheduled.len() == max_scheduled
+                || remaining_gas < gas_per_instance.saturating_add(PUSH_UPGRADE_RESERVED_GAS)
+            {
+                next_index = Some(from_index + offset as u32);
+                break;
+            }
+            let Some(instance) = self.instances.get_mut(&name) else {
+                continue;
+            };
+            if instance.outcome != InstanceOutcome::Created || instance.web4_version == version {
+                continue;
+            }
+            instance.last_push_upgrade = Some(PushUpgradeStatus::InProgress {
+                version: version.clone(),
+            });
+            instance_contract::ext(instance.instance_account_id.clone())
+                .with_static_gas(upgrade_gas)
+                .with_unused_gas_weight(0)
+                .factory_upgrade(version.clone())
+                .then(
+                    Self::ext(env::current_account_id())
+                        .with_static_gas(PUSH_UPGRADE_CALLBACK_GAS)
+                        .with_unused_gas_weight(0)
+                        .push_instance_upgrade_callback(name.clone(), version.clone()),
+                );
+            scheduled.push(name);
+        }
+        if next_index.is_none() && scanned_until < self.instances.len() {
+            next_index = Some(scanned_until);
+        }
+        PushUpgradeBatch {
+            scheduled,
+            next_index,
+        }
+    }
+
+    #[private]
+    pub fn push_instance_upgrade_callback(&mut self, name: String, version: Option<String>) {
+        let Some(instance) = self.instances.get_mut(&name) else {
+            return;
+        };
+        let status = match env::promise_result(0) {
+            // The instance returns `false` if it refuses, or the result of its upgrade otherwise
+            PromiseResult::Successful(result) => {
+                if near_sdk::serde_json::from_slice::<bool>(&result).unwrap_or(true) {
+                    instance.web4_version = version.clone();
+                    FactoryEvent::InstanceUpgraded {
+                        name,
+                        instance_account_id: instance.instance_account_id.clone(),
+                        version: version.clone(),
+                    }
+                    .emit();
+                    PushUpgradeStatus::Upgraded { version }
+                } else {
+                    PushUpgradeStatus::Refused { version }
+                }
+            }
+            _ => {
+                env::log_str(&format!(
+                    "Failed upgrading treasury web4 account {}",
+                    instance.instance_account_id
+                ));
+                PushUpgradeStatus::Failed { version }
+            }
+        };
+        instance.last_push_upgrade = Some(status);
+    }
+
+    /// Instances whose latest pushed upgrade failed or was refused, among the `limit`
+    /// registry entries from `from_index`. Pages follow the registry like `get_instances`,
+    /// so a page can hold fewer failures than `limit`, or none.
+    pub fn get_push_upgrade_failures(
+        &self,
+        from_index: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<InstanceRecord> {
+        self.instances
+            .values()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(DEFAULT_PAGE_LIMIT) as usize)
+            .filter(|instance| {
+                matches!(
+                    instance.last_push_upgrade,
+                    Some(PushUpgradeStatus::Failed { .. } | PushUpgradeStatus::Refused { .. })
+                )
+            })
+            .cloned()
+            .collect()
+    }
+}
@@ -0,0 +1,199 @@
+use near_sdk::{env, near, require, AccountId, BlockHeight};
+
+use crate::{instance_name_of_account, Contract, ContractExt, FactoryEvent, PushUpgradeStatus};
+
+pub(crate) const DEFAULT_PAGE_LIMIT: u32 = 50;
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, PartialEq)]
+pub enum InstanceOutcome {
+    Pending,
+    Created,
+    AccountCreationFailed,
+    DaoCreationFailed,
+    DaoVerificationFailed,
+    StubDeleted,
+    /// The DAO decommissioned the instance and its account was deleted. The name
+    /// stays registered, so that it is not reused by accident.
+    Retired,
+}
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug)]
+pub struct InstanceRecord {
+    pub name: String,
+    pub instance_account_id: AccountId,
+    pub dao_account_id: AccountId,
+    pub creator_id: AccountId,
+    pub created_at_block: BlockHeight,
+    pub outcome: InstanceOutcome,
+    /// The web4 release the instance runs, or `None` for the build compiled into the factory
+    pub web4_version: Option<String>,
+    /// The result of the latest upgrade pushed by the factory
+    pub last_push_upgrade: Option<PushUpgradeStatus>,
+}
+
+impl Contract {
+    /// Whether `name` is taken by an earlier creation attempt. A name can only be
+    /// registered again if the instance account of the previous attempt was never
+    /// created or has been deleted, otherwise the earlier attempt can still be
+    /// resumed by its creator.
+    pub(crate) fn internal_is_name_registered(&self, name: &str) -> bool {
+        self.instances.get(name).is_some_and(|existing| {
+            !matches!(
+                existing.outcome,
+                InstanceOutcome::AccountCreationFailed
+                    | InstanceOutcome::DaoVerificationFailed
+                    | InstanceOutcome::StubDeleted
+            )
+        })
+    }
+
+    /// Records a new creation attempt, replacing a previous attempt whose name can
+    /// be reused.
+    pub(crate) fn internal_register_instance(&mut self, record: InstanceRecord) {
+        if self.internal_is_name_registered(&record.name) {
+            env::panic_str(&format!("Instance {} is already registered", record.name));
+        }
+        if let Some(existing) = self.instances.get(&record.name) {
+            let previous_creator_id = existing.creator_id.clone();
+            self.internal_remove_from_creator(&previous_creator_id, &record.name);
+        }
+
+        let mut names = self
+            .instances_by_creator
+            .get(&record.creator_id)
+            .cloned()
+            .unwrap_or_default();
+        names.push(record.name.clone());
+        self.instances_by_creator
+            .insert(record.creator_id.clone(), names);
+        self.instances.insert(record.name.clone(), record);
+    }
+
+    pub(crate) fn internal_set_instance_outcome(&mut self, name: &str, outcome: InstanceOutcome) {
+        let mut created = false;
+        if let Some(record) = self.instances.get_mut(name) {
+            if outcome == InstanceOutcome::Created && record.outcome != InstanceOutcome::Created {
+                FactoryEvent::InstanceCreated {
+                    name: name.to_string(),
+                    instance_account_id: record.instance_account_id.clone(),
+                    dao_account_id: record.dao_account_id.clone(),
+                    creator_id: record.creator_id.clone(),
+                }
+                .emit();
+                created = true;
+            }
+            record.outcome = outcome;
+        }
+        if created {
+            self.internal_record_created(name);
+            self.internal_collect_fee(name);
+        }
+    }
+
+    /// The name and record of the instance calling the factory.
+    fn internal_calling_instance(&self) -> (String, &InstanceRecord) {
+        let instance_account_id = env::predecessor_account_id();
+        let name = instance_name_of_account(&instance_account_id);
+        let record = self
+            .instances
+            .get(&name)
+            .filter(|record| record.instance_account_id == instance_account_id)
+            .unwrap_or_else(|| {
+                env::panic_str(&format!(
+                    "{} is not an instance of the factory",
+                    instance_account_id
+                ))
+            });
+        (name, record)
+    }
+
+    pub(crate) fn internal_remove_from_creator(&mut self, creator_id: &AccountId, name: &str) {
+        if let Some(names) = self.instances_by_creator.get_mut(creator_id) {
+            names.retain(|existing_name| existing_name != name);
+        }
+    }
+}
+
+#[near]
+impl Contract {
+    /// Called by an instance that is being decommissioned, right before its account
+    /// is deleted in favour of `beneficiary_id`.
+    pub fn on_instance_decommissioned(&mut self, beneficiary_id: AccountId) {
+        let (name, record) = self.internal_calling_instance();
+        require!(
+            record.outcome != InstanceOutcome::Retired,
+            "Instance is already retired"
+        );
+        let instance_account_id = record.instance_account_id.clone();
+
+        self.internal_set_instance_outcome(&name, InstanceOutcome::Retired);
+        // A retired instance cannot be resumed or started
+        self.creations.remove(&name);
+        FactoryEvent::InstanceRetired {
+            name,
+            instance_account_id,
+            beneficiary_id,
+        }
+        .emit();
+    }
+
+    /// Called by an instance after it upgraded itself to the web4 release `version`,
+    /// or to the latest release if `None`. Upgrades pushed by the factory are recorded
+    /// by `push_instance_upgrade_callback` instead.
+    pub fn on_instance_upgraded(&mut self, version: Option<String>) {
+        let (name, record) = self.internal_calling_instance();
+        let instance_account_id = record.instance_account_id.clone();
+        let version = version.or_else(|| self.latest_web4_version.clone());
+        if let Some(record) = self.instances.get_mut(&name) {
+            record.web4_version = version.clone();
+        }
+        FactoryEvent::InstanceUpgraded {
+            name,
+            instance_account_id,
+            version,
+        }
+        .emit();
+    }
+
+    pub fn get_instance(&self, name: String) -> Option<InstanceRecord> {
+        self.instances.get(&name).cloned()
+    }
+
+    pub fn get_instances_count(&self) -> u32 {
+        self.instances.len()
+    }
+
+    pub fn get_instances(
+        &self,
+        from_index: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<InstanceRecord> {
+        self.instances
+            .values()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(DEFAULT_PAGE_LIMIT) as usize)
+            .cloned()
+            .collect()
+    }
+
+    pub fn get_instances_by_creator(
+        &self,
+        creator_id: AccountId,
+        from_index: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<InstanceRecord> {
+        self.instances_by_creator
+            .get(&creator_id)
+            .map(|names| {
+                names
+                    .iter()
+                    .skip(from_index.unwrap_or(0) as usize)
+                    .take(limit.unwrap_or(DEFAULT_PAGE_LIMIT) as usize)
+                    .filter_map(|name| self.instances.get(name).cloned())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
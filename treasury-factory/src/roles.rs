@@ -0,0 +1,171 @@
+use near_sdk::{env, near, require, AccountId};
+
+use crate::{registry::DEFAULT_PAGE_LIMIT, Contract, ContractExt, FactoryEvent};
+
+/// Scopes of the factory administration that the owner can delegate to admins.
+/// The owner implicitly holds every permission.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AdminPermission {
+    /// `set_creation_cost`
+    CreationCost,
+    /// `add_trusted_account` and `remove_trusted_account`
+    TrustedAccounts,
+    /// `set_policy_template` and `remove_policy_template`
+    PolicyTemplates,
+    /// `set_admin_public_key`
+    AccessKeys,
+    /// Uploading, publishing, deprecating and selecting web4 releases
+    Web4Releases,
+    /// `push_instance_upgrades`
+    InstanceUpgrades,
+    /// Resuming instance creations started by other accounts
+    InstanceCreations,
+    /// `set_token_price` and `remove_token_price`
+    TokenPrices,
+    /// `set_fee_config`
+    Fees,
+}
+
+#[near(serializers = [json])]
+pub struct AdminView {
+    pub account_id: AccountId,
+    pub permissions: Vec<AdminPermission>,
+}
+
+impl Contract {
+    pub(crate) fn assert_owner(&self) {
+        require!(
+            env::predecessor_account_id() == self.owner_id,
+            "Only the factory owner can call this method"
+        );
+    }
+
+    pub(crate) fn internal_has_permission(
+        &self,
+        account_id: &AccountId,
+        permission: AdminPermission,
+    ) -> bool {
+        account_id == &self.owner_id
+            || self
+                .admins
+                .get(account_id)
+                .is_some_and(|permissions| permissions.contains(&permission))
+    }
+
+    /// Panics unless the caller is the owner or an admin with `permission`, and
+    /// emits an `admin_action` event for `method`.
+    pub(crate) fn assert_admin_action(&self, permission: AdminPermission, method: &str) {
+        let caller_id = env::predecessor_account_id();
+        require!(
+            self.internal_has_permission(&caller_id, permission),
+            format!("Requires the {:?} admin permission", permission)
+        );
+        FactoryEvent::AdminAction {
+            caller_id,
+            permission,
+            method: method.to_string(),
+        }
+        .emit();
+    }
+}
+
+#[near]
+impl Contract {
+    pub fn get_owner(&self) -> AccountId {
+        self.owner_id.clone()
+    }
+
+    pub fn get_pending_owner(&self) -> Option<AccountId> {
+        self.pending_owner_id.clone()
+    }
+
+    /// Starts transferring the factory to `new_owner_id`, which can be a Sputnik
+    /// DAO. The transfer completes when the new owner calls `accept_ownership`.
+    pub fn propose_owner(&mut self, new_owner_id: AccountId) {
+        self.assert_owner();
+        require!(
+            new_owner_id != self.owner_id,
+            "Account is already the factory owner"
+        );
+        self.pending_owner_id = Some(new_owner_id.clone());
+        FactoryEvent::OwnershipTransferStarted {
+            owner_id: self.owner_id.clone(),
+            pending_owner_id: new_owner_id,
+        }
+        .emit();
+    }
+
+    pub fn cancel_ownership_transfer(&mut self) {
+        self.assert_owner();
+        let pending_owner_id = self
+            .pending_owner_id
+            .take()
+            .unwrap_or_else(|| env::panic_str("No ownership transfer in progress"));
+        FactoryEvent::OwnershipTransferCancelled {
+            owner_id: self.owner_id.clone(),
+            pending_owner_id,
+        }
+        .emit();
+    }
+
+    pub fn accept_ownership(&mut self) {
+        require!(
+            self.pending_owner_id.as_ref() == Some(&env::predecessor_account_id()),
+            "Only the pending owner can accept the ownership"
+        );
+        let owner_id = self.pending_owner_id.take().unwrap();
+        let previous_owner_id = std::mem::replace(&mut self.owner_id, owner_id.clone());
+        FactoryEvent::OwnershipTransferred {
+            previous_owner_id,
+            owner_id,
+        }
+        .emit();
+    }
+
+    pub fn get_admins(&self, from_index: Option<u32>, limit: Option<u32>) -> Vec<AdminView> {
+        self.admins
+            .iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(DEFAULT_PAGE_LIMIT) as usize)
+            .map(|(account_id, permissions)| AdminView {
+                account_id: account_id.clone(),
+                permissions: permissions.clone(),
+            })
+            .collect()
+    }
+
+    pub fn get_admin_permissions(&self, account_id: AccountId) -> Vec<AdminPermission> {
+        self.admins.get(&account_id).cloned().unwrap_or_default()
+    }
+
+    /// Grants `account_id` exactly `permissions`, replacing the ones it had.
+    pub fn set_admin(&mut self, account_id: AccountId, permissions: Vec<AdminPermission>) {
+        self.assert_owner();
+        require!(
+            !permissions.is_empty(),
+            "An admin needs at least one permission"
+        );
+        let permissions = permissions
+            .iter()
+            .enumerate()
+            .filter(|(index, permission)| !permissions[..*index].contains(permission))
+            .map(|(_, permission)| *permission)
+            .collect::<Vec<_>>();
+        self.admins.insert(account_id.clone(), permissions.clone());
+        FactoryEvent::AdminSet {
+            account_id,
+            permissions,
+        }
+        .emit();
+    }
+
+    pub fn remove_admin(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        require!(
+            self.admins.remove(&account_id).is_some(),
+            format!("{} is not an admin", account_id)
+        );
+        FactoryEvent::AdminRemoved { account_id }.emit();
+    }
+}
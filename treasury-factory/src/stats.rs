@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+
+use near_sdk::{env, near, NearToken};
+
+use crate::{Contract, ContractExt};
+
+const NANOSECONDS_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+const DEFAULT_STATS_DAYS: u32 = 7;
+const MAX_STATS_DAYS: u32 = 90;
+
+/// Failed creation steps, counted again when a resumed step fails again.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FailuresByStage {
+    pub create_account: u64,
+    pub upgrade_instance: u64,
+    pub create_dao: u64,
+    /// Membership checks of `create_instance_for_existing_dao`
+    pub verify_dao: u64,
+    pub update_widgets: u64,
+}
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CreationCounters {
+    pub started: u64,
+    pub created: u64,
+    pub failed: FailuresByStage,
+    pub refunds: u64,
+    /// The NEAR value of the refunds, including those paid out in tokens
+    pub refunded: NearToken,
+}
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FactoryStats {
+    #[serde(flatten)]
+    pub counters: CreationCounters,
+    /// Created instances by the sputnik DAO factory of their DAO
+    pub instances_by_sputnik_factory: BTreeMap<String, u64>,
+    /// Created instances by the account their widgets are copied from
+    pub instances_by_widget_reference: BTreeMap<String, u64>,
+}
+
+#[near(serializers = [json])]
+pub struct DailyStats {
+    /// Days since the unix epoch, in UTC
+    pub day: u64,
+    #[serde(flatten)]
+    pub counters: CreationCounters,
+}
+
+#[near(serializers = [json])]
+pub struct FactoryStatsView {
+    pub totals: FactoryStats,
+    /// One entry per day, from the oldest to the current day
+    pub daily: Vec<DailyStats>,
+}
+
+fn current_day() -> u64 {
+    env::block_timestamp() / NANOSECONDS_PER_DAY
+}
+
+impl Contract {
+    /// Applies `update` to the totals and to the counters of the current day.
+    pub(crate) fn internal_update_stats(&mut self, update: impl Fn(&mut CreationCounters)) {
+        update(&mut self.stats.counters);
+        let day = current_day();
+        let mut counters = self.daily_stats.get(&day).cloned().unwrap_or_default();
+        update(&mut counters);
+        self.daily_stats.insert(day, counters);
+    }
+
+    pub(crate) fn internal_record_created(&mut self, name: &str) {
+        self.internal_update_stats(|counters| counters.created += 1);
+        if let Some(creation) = self.creations.get(name) {
+            *self
+                .stats
+                .instances_by_sputnik_factory
+                .entry(creation.sputnik_dao_factory_account_id.clone())
+                .or_default() += 1;
+            *self
+                .stats
+                .instances_by_widget_reference
+                .entry(creation.widget_reference_account_id.clone())
+                .or_default() += 1;
+        }
+    }
+}
+
+#[near]
+impl Contract {
+    /// Counters of every creation since the factory started keeping them, with the
+    /// counters of each of the last `days` days.
+    pub fn get_stats(&self, days: Option<u32>) -> FactoryStatsView {
+        let days = days.unwrap_or(DEFAULT_STATS_DAYS).min(MAX_STATS_DAYS) as u64;
+        let today = current_day();
+        FactoryStatsView {
+            totals: self.stats.clone(),
+            daily: (today.saturating_sub(days.saturating_sub(1))..=today)
+                .take(days as usize)
+                .map(|day| DailyStats {
+                    day,
+                    counters: self.daily_stats.get(&day).cloned().unwrap_or_default(),
+                })
+                .collect(),
+        }
+    }
+}
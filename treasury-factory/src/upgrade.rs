@@ -0,0 +1,80 @@
+use near_sdk::{
+    borsh, env, json_types::Base58CryptoHash, near, require, Gas, GasWeight, NearToken, Promise,
+};
+
+use crate::{Contract, ContractExt, FactoryEvent};
+
+/// Version of the `Contract` state layout. Bump it when the fields of a deployed
+/// layout change, keep a copy of that layout, and convert it in `migrate`. Fields
+/// added before the layout is first deployed keep the version it is released with.
+pub const STATE_VERSION: u32 = 1;
+
+/// The original factory, which stored an empty `Contract {}` state if any.
+const LEGACY_STATE_VERSION: u32 = 0;
+
+const MIGRATE_GAS: Gas = Gas::from_tgas(50);
+
+/// The layout version of the serialized state. `state_version` is the first field
+/// of every versioned layout, so it can be read before knowing the layout.
+fn stored_state_version(state: &[u8]) -> u32 {
+    match state.get(..4) {
+        Some(version) => u32::from_le_bytes(version.try_into().unwrap()),
+        None if state.is_empty() => LEGACY_STATE_VERSION,
+        None => env::panic_str("Unknown factory state layout"),
+    }
+}
+
+#[near]
+impl Contract {
+    pub fn get_state_version(&self) -> u32 {
+        self.state_version
+    }
+
+    /// Deploys the wasm code passed as raw input to the factory account and migrates
+    /// the state in the same receipt, so a failing migration also reverts the code.
+    /// Only the owner can upgrade the factory.
+    pub fn upgrade(&mut self) -> Promise {
+        self.assert_owner();
+        let code = env::input().unwrap_or_default();
+        require!(!code.is_empty(), "Missing the factory contract code");
+
+        FactoryEvent::FactoryUpgradeStarted {
+            caller_id: env::predecessor_account_id(),
+            code_hash: Base58CryptoHash::from(env::sha256_array(&code)),
+        }
+        .emit();
+        Promise::new(env::current_account_id())
+            .deploy_contract(code)
+            .function_call_weight(
+                "migrate".to_string(),
+                vec![],
+                NearToken::from_near(0),
+                MIGRATE_GAS,
+                GasWeight(1),
+            )
+    }
+
+    /// Converts the stored state to the current layout. Also called directly after
+    /// redeploying the factory from the legacy layout with a full access key.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let state = env::storage_read(b"STATE").unwrap_or_default();
+        let from_version = stored_state_version(&state);
+        let contract = match from_version {
+            LEGACY_STATE_VERSION => Self::default(),
+            STATE_VERSION => borsh::from_slice(&state)
+                .unwrap_or_else(|_| env::panic_str("Cannot deserialize the factory state")),
+            _ => env::panic_str(&format!(
+                "Cannot migrate from factory state version {}",
+                from_version
+            )),
+        };
+        FactoryEvent::StateMigrated {
+            from_version,
+            to_version: STATE_VERSION,
+        }
+        .emit();
+        contract
+    }
+}
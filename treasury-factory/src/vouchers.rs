@@ -0,0 +1,370 @@
+use near_sdk::{
+    env, json_types::Base58CryptoHash, near, require, AccountId, CryptoHash, NearToken, Promise,
+};
+
+use crate::{registry::DEFAULT_PAGE_LIMIT, Contract, ContractExt, FactoryEvent, InstanceOutcome};
+
+/// Pays for `create_instance` from a sponsorship instead of the attached deposit.
+#[near(serializers = [json])]
+#[derive(Clone, Debug)]
+pub enum Voucher {
+    /// A voucher code, whose sha256 hash was registered by the sponsor
+    Code(String),
+    /// A sponsorship that lists the caller as a beneficiary
+    Beneficiary { sponsorship_id: u64 },
+}
+
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug)]
+pub struct Redemption {
+    pub name: String,
+    pub beneficiary_id: AccountId,
+    /// Set if a voucher code was redeemed, `None` for a beneficiary of the sponsorship
+    pub voucher_hash: Option<Base58CryptoHash>,
+    pub redeemed_at_block: u64,
+}
+
+/// Creations pre-funded by a sponsor, for the holders of voucher codes or for
+/// a list of beneficiary accounts.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug)]
+pub struct Sponsorship {
+    pub id: u64,
+    pub sponsor_id: AccountId,
+    /// The creation cost and fee paid by the sponsor for each creation
+    pub cost_per_creation: NearToken,
+    /// Beneficiaries that have not created their instance yet
+    pub beneficiaries: Vec<AccountId>,
+    /// Hashes of the voucher codes that have not been redeemed yet
+    pub voucher_hashes: Vec<Base58CryptoHash>,
+    pub redemptions: Vec<Redemption>,
+    /// Refunds of failed sponsored creations, which pay for resuming them and are
+    /// returned to the sponsor when the sponsorship is closed
+    pub credit: NearToken,
+    /// Closed sponsorships returned the funds of their unused creations
+    pub closed: bool,
+}
+
+impl Sponsorship {
+    pub fn remaining_creations(&self) -> u32 {
+        (self.beneficiaries.len() + self.voucher_hashes.len()) as u32
+    }
+}
+
+#[near(serializers = [json])]
+pub struct RedemptionView {
+    #[serde(flatten)]
+    pub redemption: Redemption,
+    pub outcome: Option<InstanceOutcome>,
+}
+
+impl Contract {
+    fn internal_get_sponsorship_mut(&mut self, sponsorship_id: u64) -> &mut Sponsorship {
+        self.sponsorships
+            .get_mut(&sponsorship_id)
+            .unwrap_or_else(|| env::panic_str(&format!("Sponsorship {} not found", sponsorship_id)))
+    }
+
+    /// Uses `voucher` for the creation of `name` by `beneficiary_id`, and returns
+    /// the sponsorship, which is credited with the refunds of the creation, with the
+    /// fee the sponsor paid above the creation deposits.
+    pub(crate) fn internal_redeem_voucher(
+        &mut self,
+        voucher: Voucher,
+        beneficiary_id: &AccountId,
+        name: &str,
+    ) -> (u64, NearToken) {
+        let creation_cost = self.creation_cost.total();
+        let (sponsorship_id, voucher_hash) = match voucher {
+            Voucher::Code(code) => {
+                let voucher_hash: CryptoHash = env::sha256_array(code.as_bytes());
+                let sponsorship_id = self
+                    .voucher_sponsorships
+                    .remove(&voucher_hash)
+                    .unwrap_or_else(|| env::panic_str("Invalid or already redeemed voucher"));
+                (sponsorship_id, Some(Base58CryptoHash::from(voucher_hash)))
+            }
+            Voucher::Beneficiary { sponsorship_id } => (sponsorship_id, None),
+        };
+
+        let sponsorship = self.internal_get_sponsorship_mut(sponsorship_id);
+        require!(
+            sponsorship.cost_per_creation >= creation_cost,
+            "The sponsorship no longer covers the creation cost"
+        );
+        match voucher_hash {
+            Some(voucher_hash) => sponsorship
+                .voucher_hashes
+                .retain(|unused_hash| unused_hash != &voucher_hash),
+            None => {
+                let beneficiary_count = sponsorship.beneficiaries.len();
+                sponsorship
+                    .beneficiaries
+                    .retain(|unused_beneficiary_id| unused_beneficiary_id != beneficiary_id);
+                require!(
+                    sponsorship.beneficiaries.len() < beneficiary_count,
+                    format!(
+                        "{} is not a beneficiary of sponsorship {}",
+                        beneficiary_id, sponsorship_id
+                    )
+                );
+            }
+        }
+        sponsorship.redemptions.push(Redemption {
+            name: name.to_string(),
+            beneficiary_id: beneficiary_id.clone(),
+            voucher_hash,
+            redeemed_at_block: env::block_height(),
+        });
+
+        FactoryEvent::VoucherRedeemed {
+            sponsorship_id,
+            name: name.to_string(),
+            beneficiary_id: beneficiary_id.clone(),
+        }
+        .emit();
+        (
+            sponsorship_id,
+            sponsorship.cost_per_creation.saturating_sub(creation_cost),
+        )
+    }
+
+    /// The sponsorship that is credited with the refunds of `name`, unless it was closed.
+    fn internal_open_sponsorship_of(&mut self, name: &str) -> Option<&mut Sponsorship> {
+        let sponsorship_id = self.creations.get(name)?.sponsorship_id?;
+        self.sponsorships
+            .get_mut(&sponsorship_id)
+            .filter(|sponsorship| !sponsorship.closed)
+    }
+
+    /// Credits a refund of the creation of `name` to its sponsorship. Returns `false`
+    /// if the creation is not sponsored or the sponsorship was closed.
+    pub(crate) fn internal_credit_sponsorship(&mut self, name: &str, amount: NearToken) -> bool {
+        let Some(sponsorship) = self.internal_open_sponsorship_of(name) else {
+            return false;
+        };
+        sponsorship.credit = sponsorship.credit.saturating_add(amount);
+        FactoryEvent::SponsorshipCredited {
+            sponsorship_id: sponsorship.id,
+            name: name.to_string(),
+            amount,
+        }
+        .emit();
+        true
+    }
+
+    /// Pays `amount` for resuming the creation of `name` from the credit of its
+    /// sponsorship. Returns `false` if the credit does not cover it.
+    pub(crate) fn internal_spend_sponsorship_credit(
+        &mut self,
+        name: &str,
+        amount: NearToken,
+    ) -> bool {
+        match self.internal_open_sponsorship_of(name) {
+            Some(sponsorship) if sponsorship.credit >= amount => {
+                sponsorship.credit = sponsorship.credit.saturating_sub(amount);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Gives the voucher or beneficiary of `name` back to its sponsorship once the
+    /// creation failed before its account was created, and all its deposits and its
+    /// fee were credited back.
+    pub(crate) fn internal_restore_voucher(&mut self, name: &str) {
+        let Some(sponsorship) = self.internal_open_sponsorship_of(name) else {
+            return;
+        };
+        let Some(redemption) = sponsorship
+            .redemptions
+            .iter()
+            .rev()
+            .find(|redemption| redemption.name == name)
+            .cloned()
+        else {
+            return;
+        };
+        sponsorship.credit = sponsorship
+            .credit
+            .saturating_sub(sponsorship.cost_per_creation);
+        let sponsorship_id = sponsorship.id;
+        match redemption.voucher_hash {
+            Some(voucher_hash) => {
+                sponsorship.voucher_hashes.push(voucher_hash);
+                self.voucher_sponsorships
+                    .insert(CryptoHash::from(voucher_hash), sponsorship_id);
+            }
+            None => sponsorship
+                .beneficiaries
+                .push(redemption.beneficiary_id.clone()),
+        }
+        // A later resume of the failed creation is paid by the caller
+        self.internal_get_creation_mut(name).sponsorship_id = None;
+
+        FactoryEvent::VoucherRestored {
+            sponsorship_id,
+            name: name.to_string(),
+            beneficiary_id: redemption.beneficiary_id,
+        }
+        .emit();
+    }
+}
+
+#[near]
+impl Contract {
+    /// Pre-funds one creation, including its fee, for each beneficiary and each voucher
+    /// code, whose sha256 hashes are registered so that the codes stay secret until
+    /// redeemed.
+    /// Returns the id of the sponsorship.
+    #[payable]
+    pub fn create_sponsorship(
+        &mut self,
+        beneficiaries: Option<Vec<AccountId>>,
+        voucher_hashes: Option<Vec<Base58CryptoHash>>,
+    ) -> u64 {
+        let mut beneficiaries = beneficiaries.unwrap_or_default();
+        beneficiaries.sort();
+        beneficiaries.dedup();
+        let voucher_hashes = voucher_hashes.unwrap_or_default();
+
+        let sponsorship_id = self.next_sponsorship_id;
+        self.next_sponsorship_id += 1;
+        for voucher_hash in &voucher_hashes {
+            require!(
+                self.voucher_sponsorships
+                    .insert(CryptoHash::from(*voucher_hash), sponsorship_id)
+                    .is_none(),
+                "Voucher hashes must be unique"
+            );
+        }
+
+        let sponsor_id = env::predecessor_account_id();
+        let sponsorship = Sponsorship {
+            id: sponsorship_id,
+            sponsor_id: sponsor_id.clone(),
+            cost_per_creation: self
+                .creation_cost
+                .total()
+                .saturating_add(self.fee_config.creation_fee),
+            beneficiaries,
+            voucher_hashes,
+            redemptions: vec![],
+            credit: NearToken::from_near(0),
+            closed: false,
+        };
+        let creations = sponsorship.remaining_creations();
+        require!(
+            creations > 0,
+            "A sponsorship needs beneficiaries or vouchers"
+        );
+        self.internal_take_deposit(
+            sponsorship
+                .cost_per_creation
+                .saturating_mul(creations.into()),
+            "fund the sponsored treasury instances",
+        );
+
+        let mut sponsorship_ids = self
+            .sponsorships_by_sponsor
+            .get(&sponsor_id)
+            .cloned()
+            .unwrap_or_default();
+        sponsorship_ids.push(sponsorship_id);
+        self.sponsorships_by_sponsor
+            .insert(sponsor_id.clone(), sponsorship_ids);
+        self.sponsorships.insert(sponsorship_id, sponsorship);
+
+        FactoryEvent::SponsorshipCreated {
+            sponsorship_id,
+            sponsor_id,
+            creations,
+        }
+        .emit();
+        sponsorship_id
+    }
+
+    /// Invalidates the unused vouchers and beneficiaries of a sponsorship, and
+    /// returns their funds and the credit of the sponsorship to the sponsor.
+    pub fn close_sponsorship(&mut self, sponsorship_id: u64) -> Promise {
+        let sponsorship = self.internal_get_sponsorship_mut(sponsorship_id);
+        require!(
+            env::predecessor_account_id() == sponsorship.sponsor_id,
+            "Only the sponsor can close a sponsorship"
+        );
+        require!(!sponsorship.closed, "Sponsorship is already closed");
+
+        let refund = sponsorship
+            .cost_per_creation
+            .saturating_mul(sponsorship.remaining_creations().into())
+            .saturating_add(std::mem::replace(
+                &mut sponsorship.credit,
+                NearToken::from_near(0),
+            ));
+        let voucher_hashes = std::mem::take(&mut sponsorship.voucher_hashes);
+        sponsorship.beneficiaries.clear();
+        sponsorship.closed = true;
+        let sponsor_id = sponsorship.sponsor_id.clone();
+        for voucher_hash in voucher_hashes {
+            self.voucher_sponsorships
+                .remove(&CryptoHash::from(voucher_hash));
+        }
+
+        FactoryEvent::SponsorshipClosed {
+            sponsorship_id,
+            refund,
+        }
+        .emit();
+        Promise::new(sponsor_id).transfer(refund)
+    }
+
+    pub fn get_sponsorship(&self, sponsorship_id: u64) -> Option<Sponsorship> {
+        self.sponsorships.get(&sponsorship_id).cloned()
+    }
+
+    pub fn get_sponsorships_by_sponsor(
+        &self,
+        sponsor_id: AccountId,
+        from_index: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<Sponsorship> {
+        self.sponsorships_by_sponsor
+            .get(&sponsor_id)
+            .map(|sponsorship_ids| {
+                sponsorship_ids
+                    .iter()
+                    .skip(from_index.unwrap_or(0) as usize)
+                    .take(limit.unwrap_or(DEFAULT_PAGE_LIMIT) as usize)
+                    .filter_map(|sponsorship_id| self.sponsorships.get(sponsorship_id).cloned())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The creations paid by a sponsorship, with the current outcome of each instance.
+    pub fn get_sponsorship_redemptions(
+        &self,
+        sponsorship_id: u64,
+        from_index: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<RedemptionView> {
+        self.sponsorships
+            .get(&sponsorship_id)
+            .map(|sponsorship| {
+                sponsorship
+                    .redemptions
+                    .iter()
+                    .skip(from_index.unwrap_or(0) as usize)
+                    .take(limit.unwrap_or(DEFAULT_PAGE_LIMIT) as usize)
+                    .map(|redemption| RedemptionView {
+                        redemption: redemption.clone(),
+                        outcome: self
+                            .instances
+                            .get(&redemption.name)
+                            .map(|instance| instance.outcome.clone()),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,230 @@
+use near_sdk::{
+    env,
+    json_types::{Base58CryptoHash, Base64VecU8, U64},
+    near, require,
+    serde::Deserialize,
+    serde_json, CryptoHash, Gas, Promise, PromiseResult,
+};
+
+use crate::{
+    registry::DEFAULT_PAGE_LIMIT, AdminPermission, Contract, ContractExt, WEB4_CONTRACT_BYTES,
+};
+
+/// Metadata of a web4 contract build uploaded by the factory owner. The code is
+/// stored separately, so that listing releases does not read the wasm bytes.
+#[near(serializers = [borsh, json])]
+#[derive(Clone, Debug)]
+pub struct Web4Release {
+    pub version: String,
+    pub sha256: Base58CryptoHash,
+    pub size: u32,
+    pub notes: String,
+    /// Block timestamp of the upload, in nanoseconds
+    pub released_at: U64,
+    /// Deprecated releases can no longer be fetched or made the latest release
+    pub deprecated: bool,
+    /// Published as a global contract identified by `sha256`, which instances use
+    /// instead of deploying the code to their own account
+    pub global_contract: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct GetWeb4ContractBytesArgs {
+    version: Option<String>,
+}
+
+impl Contract {
+    fn internal_get_web4_release(&self, version: &str) -> &Web4Release {
+        self.web4_releases
+            .get(version)
+            .unwrap_or_else(|| env::panic_str(&format!("Web4 release {} not found", version)))
+    }
+
+    /// The code hash of `version`, or of the latest release if not set, when it
+    /// is published as a global contract.
+    fn internal_web4_global_contract_hash(
+        &self,
+        version: Option<&str>,
+    ) -> Option<Base58CryptoHash> {
+        let version = version.or(self.latest_web4_version.as_deref())?;
+        let release = self.internal_get_web4_release(version);
+        require!(
+            !release.deprecated,
+            format!("Web4 release {} is deprecated", version)
+        );
+        release.global_contract.then_some(release.sha256)
+    }
+
+    /// The method and arguments that switch an instance account running the minimum
+    /// self upgrade contract to the web4 contract.
+    pub(crate) fn internal_instance_upgrade_call(
+        &self,
+        version: Option<&str>,
+    ) -> (String, Vec<u8>) {
+        match self.internal_web4_global_contract_hash(version) {
+            Some(code_hash) => (
+                "use_global_contract".to_string(),
+                CryptoHash::from(code_hash).to_vec(),
+            ),
+            None => (
+                "upgrade".to_string(),
+                self.internal_web4_contract_bytes(version),
+            ),
+        }
+    }
+
+    /// The code of `version`, or of the latest release if not set. Falls back to
+    /// the web4 contract compiled into the factory while no release is uploaded.
+    pub(crate) fn internal_web4_contract_bytes(&self, version: Option<&str>) -> Vec<u8> {
+        let Some(version) = version.or(self.latest_web4_version.as_deref()) else {
+            return WEB4_CONTRACT_BYTES.to_vec();
+        };
+        require!(
+            !self.internal_get_web4_release(version).deprecated,
+            format!("Web4 release {} is deprecated", version)
+        );
+        self.web4_release_code
+            .get(version)
+            .cloned()
+            .unwrap_or_else(|| env::panic_str(&format!("Web4 release {} not found", version)))
+    }
+}
+
+#[near]
+impl Contract {
+    /// Returns the raw wasm bytes of a web4 release. Takes an optional `version`
+    /// argument, which is parsed by hand since instances call this without any.
+    pub fn get_web4_contract_bytes(&self) {
+        let version = env::input()
+            .filter(|input| !input.is_empty())
+            .and_then(|input| {
+                serde_json::from_slice::<GetWeb4ContractBytesArgs>(&input)
+                    .unwrap_or_else(|_| env::panic_str("Failed to deserialize input from JSON."))
+                    .version
+            });
+        env::value_return(&self.internal_web4_contract_bytes(version.as_deref()));
+    }
+
+    /// The global contract code hash of `version`, or of the latest release if not
+    /// set. Returns `None` if the release is only available as wasm bytes.
+    pub fn get_web4_global_contract_hash(
+        &self,
+        version: Option<String>,
+    ) -> Option<Base58CryptoHash> {
+        self.internal_web4_global_contract_hash(version.as_deref())
+    }
+
+    pub fn get_latest_web4_version(&self) -> Option<String> {
+        self.latest_web4_version.clone()
+    }
+
+    pub fn get_web4_release(&self, version: String) -> Option<Web4Release> {
+        self.web4_releases.get(&version).cloned()
+    }
+
+    pub fn get_web4_releases(
+        &self,
+        from_index: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<Web4Release> {
+        self.web4_releases
+            .values()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.unwrap_or(DEFAULT_PAGE_LIMIT) as usize)
+            .cloned()
+            .collect()
+    }
+
+    /// Stores a new web4 release, and makes it the latest release if `make_latest`.
+    pub fn upload_web4_release(
+        &mut self,
+        version: String,
+        code: Base64VecU8,
+        notes: String,
+        make_latest: bool,
+    ) -> Web4Release {
+        self.assert_admin_action(AdminPermission::Web4Releases, "upload_web4_release");
+        require!(!version.is_empty(), "Version cannot be empty");
+        require!(
+            !self.web4_releases.contains_key(&version),
+            format!("Web4 release {} already exists", version)
+        );
+
+        let code: Vec<u8> = code.into();
+        let release = Web4Release {
+            version: version.clone(),
+            sha256: env::sha256_array(&code).into(),
+            size: code.len() as u32,
+            notes,
+            released_at: env::block_timestamp().into(),
+            deprecated: false,
+            global_contract: false,
+        };
+        self.web4_releases.insert(version.clone(), release.clone());
+        self.web4_release_code.insert(version.clone(), code);
+        if make_latest {
+            self.latest_web4_version = Some(version);
+        }
+        release
+    }
+
+    /// Makes `version` the release served to new instances and `self_upgrade`,
+    /// which also rolls back to an older release.
+    pub fn set_latest_web4_version(&mut self, version: String) {
+        self.assert_admin_action(AdminPermission::Web4Releases, "set_latest_web4_version");
+        require!(
+            !self.internal_get_web4_release(&version).deprecated,
+            format!("Web4 release {} is deprecated", version)
+        );
+        self.latest_web4_version = Some(version);
+    }
+
+    pub fn deprecate_web4_release(&mut self, version: String) {
+        self.assert_admin_action(AdminPermission::Web4Releases, "deprecate_web4_release");
+        require!(
+            self.latest_web4_version.as_ref() != Some(&version),
+            "Cannot deprecate the latest web4 release"
+        );
+        self.web4_releases
+            .get_mut(&version)
+            .unwrap_or_else(|| env::panic_str(&format!("Web4 release {} not found", version)))
+            .deprecated = true;
+    }
+
+    /// Deploys the code of `version` as a global contract identified by its hash.
+    /// The storage cost is burned from the factory balance.
+    pub fn publish_web4_release_as_global_contract(&mut self, version: String) -> Promise {
+        self.assert_admin_action(
+            AdminPermission::Web4Releases,
+            "publish_web4_release_as_global_contract",
+        );
+        require!(
+            !self.internal_get_web4_release(&version).global_contract,
+            format!("Web4 release {} is already a global contract", version)
+        );
+        let code = self.internal_web4_contract_bytes(Some(&version));
+        Promise::new(env::current_account_id())
+            .deploy_global_contract(code)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(Gas::from_tgas(5))
+                    .with_unused_gas_weight(0)
+                    .publish_web4_release_callback(version),
+            )
+    }
+
+    #[private]
+    pub fn publish_web4_release_callback(&mut self, version: String) {
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            if let Some(release) = self.web4_releases.get_mut(&version) {
+                release.global_contract = true;
+            }
+        } else {
+            env::log_str(&format!(
+                "Failed publishing web4 release {} as a global contract",
+                version
+            ));
+        }
+    }
+}
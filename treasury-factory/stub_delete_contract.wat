@@ -0,0 +1,34 @@
+(module
+  (import "env" "current_account_id" (func $current_account_id (param i64)))
+  (import "env" "read_register" (func $read_register (param i64 i64)))
+  (import "env" "register_len" (func $register_len (param i64) (result i64)))
+  (import "env" "promise_batch_create" (func $promise_batch_create (param i64 i64) (result i64)))
+  (import "env" "promise_batch_action_delete_account" (func $promise_batch_action_delete_account (param i64 i64 i64)))
+  (import "env" "promise_return" (func $promise_return (param i64)))
+
+  ;; Callable by anyone, since the balance can only go to the embedded beneficiary
+  (func (export "delete")
+    (local $promise_id i64)
+
+    ;; Read current account id into addr 1024
+    (call $current_account_id (i64.const 0))
+    (call $read_register (i64.const 0) (i64.const 1024))
+
+    ;; Create a batch promise for deleting self
+    (call $promise_batch_create (call $register_len (i64.const 0)) (i64.const 1024))
+    (local.set $promise_id)
+
+    ;; Delete the account and send the remaining balance to the beneficiary
+    (call $promise_batch_action_delete_account
+      (local.get $promise_id)
+      (i64.load (i32.const 0)) ;; Beneficiary length stored at address 0
+      (i64.const 8) ;; Beneficiary account string starts at address 8
+    )
+
+    ;; Let the caller observe the outcome of the deletion
+    (call $promise_return (local.get $promise_id))
+  )
+  (memory 1)
+  ;; Reserve 64 bytes for the beneficiary account ID (pre-allocated empty space)
+  (data (i32.const 0) "\00\00\00\00\00\00\00\00XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX")
+)
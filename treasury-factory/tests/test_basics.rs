@@ -1,6 +1,7 @@
 use cargo_near_build::BuildOpts;
 use lazy_static::lazy_static;
 use near_sdk::base64::{engine::general_purpose::STANDARD as BASE64_STANDARD, Engine as _};
+use near_sdk::json_types::Base58CryptoHash;
 use near_sdk::serde::Deserialize;
 use near_sdk::{AccountId, NearToken};
 use near_workspaces::types::AccessKeyPermission;
@@ -217,6 +218,12 @@ async fn test_factory() -> Result<(), Box<dyn std::error::Error>> {
         .deploy(&treasury_factory_contract_wasm)
         .await?
         .result;
+    trust_account(
+        &treasury_factory_contract,
+        "WidgetReference",
+        WIDGET_REFERENCE_ACCOUNT_ID,
+    )
+    .await?;
 
     let init_sputnik_dao_factory_result =
         sputnik_dao_factory.call("new").max_gas().transact().await?;
@@ -366,6 +373,17 @@ async fn test_factory() -> Result<(), Box<dyn std::error::Error>> {
 
     assert!(create_treasury_instance_result.is_success());
 
+    for event in ["instance_upgraded", "instance_created", "widgets_updated"] {
+        assert!(
+            create_treasury_instance_result.logs().iter().any(|log| log
+                .starts_with(r#"EVENT_JSON:{"standard":"treasury-factory""#)
+                && log.contains(&format!(r#""event":"{}""#, event))),
+            "Missing {} event in {:?}",
+            event,
+            create_treasury_instance_result.logs()
+        );
+    }
+
     assert!(
         user_account_details_after.balance
             < (user_account_details_before
@@ -403,6 +421,61 @@ async fn test_factory() -> Result<(), Box<dyn std::error::Error>> {
         create_treasury_instance_result.total_gas_burnt.as_tgas()
     );
 
+    let instance_record: Value = treasury_factory_contract
+        .view("get_instance")
+        .args_json(json!({"name": instance_name}))
+        .await?
+        .json()?;
+    assert_eq!(instance_record["instance_account_id"], instance_account_id);
+    assert_eq!(
+        instance_record["dao_account_id"],
+        format!("{}.{}", instance_name, SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT)
+    );
+    assert_eq!(instance_record["creator_id"], user_account.id().as_str());
+    assert_eq!(instance_record["outcome"], "Created");
+
+    let instances_by_creator: Vec<Value> = treasury_factory_contract
+        .view("get_instances_by_creator")
+        .args_json(json!({"creator_id": user_account.id()}))
+        .await?
+        .json()?;
+    assert_eq!(instances_by_creator, vec![instance_record]);
+
+    let creation_status: Value = treasury_factory_contract
+        .view("get_creation_status")
+        .args_json(json!({"name": instance_name}))
+        .await?
+        .json()?;
+    for step in [
+        "create_account",
+        "upgrade_instance",
+        "create_dao",
+        "update_widgets",
+    ] {
+        assert_eq!(creation_status[step], "Succeeded", "step {}", step);
+    }
+
+    // The instance keeps the accounts it was created with, in place of the defaults it is built with
+    let instance_state = worker
+        .view_state(&instance_account_id.parse().unwrap())
+        .await?;
+    for (key, account_id) in [
+        ("factory_account_id", TREASURY_FACTORY_CONTRACT_ACCOUNT),
+        ("social_db_account_id", SOCIALDB_ACCOUNT),
+        ("widget_reference_account_id", WIDGET_REFERENCE_ACCOUNT_ID),
+        (
+            "sputnik_dao_factory_account_id",
+            SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT,
+        ),
+    ] {
+        assert_eq!(
+            instance_state.get(key.as_bytes()).map(Vec::as_slice),
+            Some(account_id.as_bytes()),
+            "stored {}",
+            key
+        );
+    }
+
     let result = treasury_factory_contract
         .as_account()
         .view(&instance_account_id.parse().unwrap(), "web4_get")
@@ -626,6 +699,12 @@ async fn test_factory_should_refund_if_failing_because_of_existing_account(
         .deploy(&treasury_factory_contract_wasm)
         .await?
         .result;
+    trust_account(
+        &treasury_factory_contract,
+        "WidgetReference",
+        WIDGET_REFERENCE_ACCOUNT_ID,
+    )
+    .await?;
 
     let init_sputnik_dao_factory_result =
         sputnik_dao_factory.call("new").max_gas().transact().await?;
@@ -733,14 +812,28 @@ async fn test_factory_should_refund_if_failing_because_of_existing_account(
     assert!(!failed_outcomes.is_empty());
     println!("{:?}", failed_outcomes);
 
-    assert_eq!(
-        "Failed creating treasury web4 account intellex.near",
-        create_treasury_instance_result
-            .logs()
-            .last()
-            .unwrap()
-            .to_owned()
-    );
+    assert!(create_treasury_instance_result.logs().contains(
+        &r#"EVENT_JSON:{"standard":"treasury-factory","version":"1.0.0","event":"account_creation_failed","data":{"name":"intellex","instance_account_id":"intellex.near"}}"#
+    ));
+    assert!(create_treasury_instance_result
+        .logs()
+        .iter()
+        .any(|log| log.contains(r#""event":"refund_issued""#)));
+
+    let instance_record: Value = treasury_factory_contract
+        .view("get_instance")
+        .args_json(json!({"name": instance_name}))
+        .await?
+        .json()?;
+    assert_eq!(instance_record["outcome"], "AccountCreationFailed");
+
+    let creation_status: Value = treasury_factory_contract
+        .view("get_creation_status")
+        .args_json(json!({"name": instance_name}))
+        .await?
+        .json()?;
+    assert_eq!(creation_status["create_account"], "Failed");
+    assert_eq!(creation_status["create_dao"], "NotStarted");
 
     let user_account_details_after = user_account.view_account().await?;
 
@@ -762,7 +855,7 @@ async fn test_factory_should_refund_if_failing_because_of_existing_account(
 }
 
 #[tokio::test]
-async fn test_factory_should_refund_if_failing_because_of_existing_dao_but_still_create_web4_and_set_social_metadata(
+async fn test_factory_should_refund_fully_without_creating_the_account_if_dao_exists(
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mainnet = near_workspaces::custom("https://rpc.mainnet.fastnear.com").await?;
     let sputnikdao_factory_contract_id: AccountId = SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT.parse()?;
@@ -854,6 +947,12 @@ async fn test_factory_should_refund_if_failing_because_of_existing_dao_but_still
         .deploy(&treasury_factory_contract_wasm)
         .await?
         .result;
+    trust_account(
+        &treasury_factory_contract,
+        "WidgetReference",
+        WIDGET_REFERENCE_ACCOUNT_ID,
+    )
+    .await?;
 
     let init_sputnik_dao_factory_result =
         sputnik_dao_factory.call("new").max_gas().transact().await?;
@@ -952,71 +1051,84 @@ async fn test_factory_should_refund_if_failing_because_of_existing_dao_but_still
         .await?;
 
     let treasury_factory_account_details_after = treasury_factory_contract.view_account().await?;
-    let failed_outcomes: Vec<_> = create_treasury_instance_result
-        .receipt_outcomes()
-        .iter()
-        .filter(|outcome| outcome.is_failure())
-        .collect();
+    println!("{:?}", create_treasury_instance_result.logs());
 
-    assert!(!failed_outcomes.is_empty());
-    println!("{:?}", failed_outcomes);
+    assert!(create_treasury_instance_result.logs().contains(
+        &r#"EVENT_JSON:{"standard":"treasury-factory","version":"1.0.0","event":"dao_creation_failed","data":{"name":"intellex","instance_account_id":"intellex.near","dao_account_id":"intellex.sputnik-dao.near"}}"#
+    ));
+    assert!(create_treasury_instance_result
+        .logs()
+        .contains(&"DAO intellex.sputnik-dao.near already exists"));
+    assert!(!create_treasury_instance_result
+        .logs()
+        .iter()
+        .any(|log| log.contains(r#""event":"instance_upgraded""#)));
 
-    println!("{:?}", create_treasury_instance_result.logs());
+    let instance_record: Value = treasury_factory_contract
+        .view("get_instance")
+        .args_json(json!({"name": instance_name}))
+        .await?
+        .json()?;
+    assert_eq!(instance_record["outcome"], "DaoCreationFailed");
 
+    let creation_status: Value = treasury_factory_contract
+        .view("get_creation_status")
+        .args_json(json!({"name": instance_name}))
+        .await?
+        .json()?;
+    assert_eq!(creation_status["create_account"], "NotStarted");
+    assert_eq!(creation_status["upgrade_instance"], "NotStarted");
+    assert_eq!(creation_status["create_dao"], "Failed");
+    assert_eq!(creation_status["update_widgets"], "NotStarted");
+
+    let stats: Value = treasury_factory_contract
+        .view("get_stats")
+        .args_json(json!({"days": 1}))
+        .await?
+        .json()?;
+    assert_eq!(stats["totals"]["started"], 1);
+    assert_eq!(stats["totals"]["created"], 0);
+    assert_eq!(stats["totals"]["failed"]["create_dao"], 1);
+    assert_eq!(stats["totals"]["refunds"], 1);
     assert_eq!(
-        "Succeeded creating and funding web4 account intellex.near, but failed creating treasury account intellex.sputnik-dao.near",
-        create_treasury_instance_result.logs().last().unwrap().to_owned()
+        stats["totals"]["refunded"],
+        NearToken::from_near(9).as_yoctonear().to_string()
     );
+    assert_eq!(stats["daily"][0]["failed"]["create_dao"], 1);
+
+    let resume_without_deposit_result = user_account
+        .call(treasury_factory_contract.id(), "resume_instance_creation")
+        .args_json(json!({"name": instance_name}))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(resume_without_deposit_result.is_failure());
     let user_account_details_after = user_account.view_account().await?;
 
-    let user_balance_diff = user_account_details_before.balance.as_millinear()
-        - user_account_details_after.balance.as_millinear();
     assert!(
-        user_balance_diff > 3000 && user_balance_diff < 3100,
-        "User balance after ( {} mNEAR ) should be 3 NEAR less than balance before ( {} mNEAR )",
+        user_account_details_before.balance.as_millinear()
+            - user_account_details_after.balance.as_millinear()
+            < 50,
+        "User balance after ( {} mNEAR ) should be almost the same as balance before ( {} mNEAR )",
         user_account_details_after.balance.as_millinear(),
         user_account_details_before.balance.as_millinear()
     );
 
-    assert!(
-        treasury_factory_account_details_after.balance
-            > treasury_factory_account_details_before.balance,
-        "Treasury factory balance after ( {} mNEAR ) should be slightly more than balance before ( {} mNEAR )",
-        treasury_factory_account_details_after.balance.as_millinear(),
-        treasury_factory_account_details_before.balance.as_millinear()
-    );
-
     assert!(
         treasury_factory_account_details_after
             .balance
             .as_millinear()
-            - treasury_factory_account_details_before
-                .balance
-                .as_millinear()
+            .abs_diff(treasury_factory_account_details_before.balance.as_millinear())
             < 100,
-        "Treasury factory balance after ( {} mNEAR ) should be slightly more than balance before ( {} mNEAR )",
-            treasury_factory_account_details_after.balance.as_millinear(),
-            treasury_factory_account_details_before.balance.as_millinear()
+        "Treasury factory balance after ( {} mNEAR ) should be about the same as balance before ( {} mNEAR )",
+        treasury_factory_account_details_after.balance.as_millinear(),
+        treasury_factory_account_details_before.balance.as_millinear()
     );
 
-    let instance_account_details = worker
-        .view_account(&format!("{}.near", instance_name).parse().unwrap())
-        .await?;
-    assert_eq!(instance_account_details.balance.as_millinear(), 2500);
-
-    let result = treasury_factory_contract
-        .as_account()
-        .view(&instance_account_id.parse().unwrap(), "web4_get")
-        .args_json(json!({"request": {"path": "/", "preloads": create_preload_result(instance_account_id.clone(), String::from("test treasury title"), String::from("test description"))}}))
-        .await?;
-
-    let response = result.json::<Web4Response>().unwrap();
-    assert_eq!("text/html; charset=UTF-8", response.content_type);
-
-    let body_string = String::from_utf8(BASE64_STANDARD.decode(response.body).unwrap()).unwrap();
-
-    assert!(body_string.contains("near-social-viewer"));
-    assert!(body_string.contains("\"test treasury title\""));
+    assert!(worker
+        .view_account(&instance_account_id.parse().unwrap())
+        .await
+        .is_err());
 
     let deployed_widgets = socialdb
         .call("get")
@@ -1025,45 +1137,9 @@ async fn test_factory_should_refund_if_failing_because_of_existing_dao_but_still
         }))
         .view()
         .await?;
-
     let deployed_widgets_json =
         Value::from_str(String::from_utf8(deployed_widgets.result).unwrap().as_str()).unwrap();
-
-    assert_eq!(
-        deployed_widgets_json[instance_account_id.clone()]["widget"]["app"][""],
-        reference_widget_data[reference_widget_contract.id().as_str()]["widget"]["app"]
-    );
-    assert_eq!(
-        deployed_widgets_json[instance_account_id.clone()]["widget"]["config"],
-        reference_widget_data[reference_widget_contract.id().as_str()]["widget"]["config"]
-    );
-
-    let social_metadata = socialdb
-        .call("get")
-        .args_json(json!({
-            "keys": [format!("{}/widget/app/metadata/**", instance_account_id)]
-        }))
-        .view()
-        .await?;
-    println!(
-        "{:?}",
-        String::from_utf8(social_metadata.result.clone())
-            .unwrap()
-            .as_str()
-    );
-
-    let social_metadata_json: Value =
-        Value::from_str(String::from_utf8(social_metadata.result).unwrap().as_str()).unwrap();
-    let metadata = &social_metadata_json[instance_account_id.clone()]["widget"]["app"]["metadata"];
-    assert_eq!(metadata["name"].as_str().unwrap(), "NEAR Treasury");
-    assert_eq!(
-        metadata["description"],
-        format!("NEAR Treasury / {}", instance_account_id)
-    );
-    assert_eq!(
-        metadata["image"]["ipfs_cid"],
-        "bafkreiefdkigadpkpccreqfnhut2li2nmf3alhz7c3wadveconelisnksu"
-    );
+    assert_eq!(deployed_widgets_json, json!({}));
 
     Ok(())
 }
@@ -1119,3 +1195,2022 @@ async fn test_html_contains_service_worker_registration() -> Result<(), Box<dyn
 
     Ok(())
 }
+
+struct FactorySandbox {
+    worker: near_workspaces::Worker<near_workspaces::network::Sandbox>,
+    mainnet: near_workspaces::Worker<near_workspaces::network::Custom>,
+    treasury_factory_contract: near_workspaces::Contract,
+}
+
+async fn setup_factory_sandbox() -> Result<FactorySandbox, Box<dyn std::error::Error>> {
+    let mainnet = near_workspaces::custom("https://rpc.mainnet.fastnear.com").await?;
+    let worker = near_workspaces::sandbox_with_version("2.7.0").await?;
+
+    let treasury_factory_contract: near_workspaces::Contract = worker
+        .import_contract(&TREASURY_FACTORY_CONTRACT_ACCOUNT.parse()?, &mainnet)
+        .initial_balance(NearToken::from_near(1000))
+        .transact()
+        .await?;
+    let sputnik_dao_factory = worker
+        .import_contract(&SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT.parse()?, &mainnet)
+        .initial_balance(NearToken::from_near(1000))
+        .transact()
+        .await?;
+    let socialdb = worker
+        .import_contract(&SOCIALDB_ACCOUNT.parse()?, &mainnet)
+        .initial_balance(NearToken::from_near(10000))
+        .transact()
+        .await?;
+    let reference_widget_contract = worker
+        .import_contract(&WIDGET_REFERENCE_ACCOUNT_ID.parse()?, &mainnet)
+        .initial_balance(NearToken::from_near(20))
+        .transact()
+        .await?;
+    let near_contract = worker
+        .import_contract(&"near".parse()?, &mainnet)
+        .initial_balance(NearToken::from_near(100_000_000))
+        .transact()
+        .await?;
+
+    assert!(near_contract
+        .call("new")
+        .max_gas()
+        .transact()
+        .await?
+        .is_success());
+    assert!(socialdb
+        .call("new")
+        .max_gas()
+        .transact()
+        .await?
+        .is_success());
+    assert!(socialdb
+        .call("set_status")
+        .args_json(json!({"status": "Live"}))
+        .max_gas()
+        .transact()
+        .await?
+        .is_success());
+    assert!(reference_widget_contract
+        .as_account()
+        .call(socialdb.id(), "set")
+        .args_json(json!({
+            "data": {
+                reference_widget_contract.id().as_str(): {
+                    "widget": {
+                        "app": "Hello",
+                        "config": "Goodbye"
+                    }
+                }
+            }
+        }))
+        .deposit(NearToken::from_near(2))
+        .transact()
+        .await?
+        .is_success());
+    assert!(sputnik_dao_factory
+        .call("new")
+        .max_gas()
+        .transact()
+        .await?
+        .is_success());
+
+    let treasury_factory_contract = treasury_factory_contract
+        .as_account()
+        .deploy(&build_project_once())
+        .await?
+        .result;
+    trust_account(
+        &treasury_factory_contract,
+        "WidgetReference",
+        WIDGET_REFERENCE_ACCOUNT_ID,
+    )
+    .await?;
+
+    Ok(FactorySandbox {
+        worker,
+        mainnet,
+        treasury_factory_contract,
+    })
+}
+
+async fn trust_account(
+    treasury_factory_contract: &near_workspaces::Contract,
+    kind: &str,
+    account_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let result = treasury_factory_contract
+        .call("add_trusted_account")
+        .args_json(json!({"kind": kind, "account_id": account_id}))
+        .transact()
+        .await?;
+    assert!(result.is_success(), "{:?}", result.failures());
+    Ok(())
+}
+
+fn simple_create_dao_args(instance_name: &str, member: &str) -> String {
+    let one_required_vote_policy = json!({
+        "weight_kind": "RoleWeight",
+        "quorum": "0",
+        "threshold": "1"
+    });
+    BASE64_STANDARD.encode(
+        json!({
+            "config": {
+                "name": instance_name,
+                "purpose": "creating dao treasury",
+                "metadata": ""
+            },
+            "policy": {
+                "roles": [
+                    {
+                        "kind": { "Group": [member] },
+                        "name": "Admin",
+                        "permissions": ["*:*"],
+                        "vote_policy": {
+                            "transfer": one_required_vote_policy,
+                            "call": one_required_vote_policy
+                        }
+                    }
+                ],
+                "default_vote_policy": one_required_vote_policy,
+                "proposal_bond": "100000000000000000000000",
+                "proposal_period": "604800000000000",
+                "bounty_bond": "100000000000000000000000",
+                "bounty_forgiveness_period": "604800000000000"
+            }
+        })
+        .to_string(),
+    )
+}
+
+#[tokio::test]
+async fn test_factory_should_complete_creation_resumed_after_dao_creation_fails(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let FactorySandbox {
+        worker,
+        treasury_factory_contract,
+        ..
+    } = setup_factory_sandbox().await?;
+
+    // Too little for the sputnik factory to deploy the DAO, so its creation fails
+    let set_creation_cost_result = treasury_factory_contract
+        .call("set_creation_cost")
+        .args_json(json!({
+            "creation_cost": {
+                "sputnik_dao_deposit": NearToken::from_near(1),
+                "social_db_deposit": NearToken::from_millinear(500),
+                "instance_account_deposit": NearToken::from_millinear(2500)
+            }
+        }))
+        .transact()
+        .await?;
+    assert!(set_creation_cost_result.is_success());
+
+    let instance_name = "test-resume";
+    let user_account = worker.dev_create_account().await?;
+    let user_account_details_before = user_account.view_account().await?;
+
+    let create_treasury_instance_result = user_account
+        .call(treasury_factory_contract.id(), "create_instance")
+        .args_json(json!({
+            "sputnik_dao_factory_account_id": SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT,
+            "social_db_account_id": SOCIALDB_ACCOUNT,
+            "widget_reference_account_id": WIDGET_REFERENCE_ACCOUNT_ID,
+            "name": instance_name,
+            "create_dao_args": simple_create_dao_args(instance_name, user_account.id().as_str())
+        }))
+        .max_gas()
+        .deposit(NearToken::from_near(4))
+        .transact()
+        .await?;
+    println!("logs: {:?}", create_treasury_instance_result.logs());
+
+    let creation_status: Value = treasury_factory_contract
+        .view("get_creation_status")
+        .args_json(json!({"name": instance_name}))
+        .await?
+        .json()?;
+    assert_eq!(creation_status["create_account"], "Succeeded");
+    assert_eq!(creation_status["upgrade_instance"], "Succeeded");
+    assert_eq!(creation_status["create_dao"], "Failed");
+    assert_eq!(creation_status["update_widgets"], "Succeeded");
+    assert_eq!(
+        creation_status["refunded"],
+        NearToken::from_near(1).as_yoctonear().to_string()
+    );
+
+    let set_creation_cost_result = treasury_factory_contract
+        .call("set_creation_cost")
+        .args_json(json!({
+            "creation_cost": {
+                "sputnik_dao_deposit": NearToken::from_near(6),
+                "social_db_deposit": NearToken::from_millinear(500),
+                "instance_account_deposit": NearToken::from_millinear(2500)
+            }
+        }))
+        .transact()
+        .await?;
+    assert!(set_creation_cost_result.is_success());
+
+    // Only the deposit of the failed DAO step is charged again
+    let resume_result = user_account
+        .call(treasury_factory_contract.id(), "resume_instance_creation")
+        .args_json(json!({"name": instance_name}))
+        .max_gas()
+        .deposit(NearToken::from_near(6))
+        .transact()
+        .await?;
+    assert!(resume_result.is_success(), "{:?}", resume_result.failures());
+
+    let creation_status: Value = treasury_factory_contract
+        .view("get_creation_status")
+        .args_json(json!({"name": instance_name}))
+        .await?
+        .json()?;
+    assert_eq!(creation_status["create_account"], "Succeeded");
+    assert_eq!(creation_status["upgrade_instance"], "Succeeded");
+    assert_eq!(creation_status["create_dao"], "Succeeded");
+    assert_eq!(creation_status["update_widgets"], "Succeeded");
+    assert_eq!(
+        creation_status["refunded"],
+        NearToken::from_near(1).as_yoctonear().to_string()
+    );
+
+    let instance_record: Value = treasury_factory_contract
+        .view("get_instance")
+        .args_json(json!({"name": instance_name}))
+        .await?
+        .json()?;
+    assert_eq!(instance_record["outcome"], "Created");
+
+    let policy: Value = worker
+        .view(
+            &format!("{}.{}", instance_name, SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT).parse()?,
+            "get_policy",
+        )
+        .await?
+        .json()?;
+    assert_eq!(
+        policy["roles"][0]["kind"]["Group"],
+        json!([user_account.id()])
+    );
+
+    let instance_account_details = worker
+        .view_account(&format!("{}.near", instance_name).parse()?)
+        .await?;
+    assert_eq!(instance_account_details.balance.as_millinear(), 2500);
+
+    // 4 NEAR for the first attempt, of which 1 NEAR was refunded, and 6 NEAR for the resume
+    let user_account_details_after = user_account.view_account().await?;
+    let user_balance_diff = user_account_details_before.balance.as_millinear()
+        - user_account_details_after.balance.as_millinear();
+    assert!(
+        user_balance_diff > 9000 && user_balance_diff < 9100,
+        "User balance after ( {} mNEAR ) should be 9 NEAR less than balance before ( {} mNEAR )",
+        user_account_details_after.balance.as_millinear(),
+        user_account_details_before.balance.as_millinear()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_factory_should_not_create_a_stub_to_delete_if_dao_exists(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let FactorySandbox {
+        worker,
+        mainnet,
+        treasury_factory_contract,
+    } = setup_factory_sandbox().await?;
+
+    let _ = worker
+        .import_contract(&"intellex.sputnik-dao.near".parse()?, &mainnet)
+        .initial_balance(NearToken::from_near(100))
+        .transact()
+        .await?;
+
+    let instance_name = "intellex";
+    let user_account = worker.dev_create_account().await?;
+    let user_account_details_before = user_account.view_account().await?;
+
+    let create_treasury_instance_result = user_account
+        .call(treasury_factory_contract.id(), "create_instance")
+        .args_json(json!({
+            "sputnik_dao_factory_account_id": SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT,
+            "social_db_account_id": SOCIALDB_ACCOUNT,
+            "widget_reference_account_id": WIDGET_REFERENCE_ACCOUNT_ID,
+            "name": instance_name,
+            "create_dao_args": simple_create_dao_args(instance_name, user_account.id().as_str()),
+            "options": {
+                "delete_stub_on_failure": true
+            }
+        }))
+        .max_gas()
+        .deposit(NearToken::from_near(9))
+        .transact()
+        .await?;
+    println!("logs: {:?}", create_treasury_instance_result.logs());
+
+    let creation_status: Value = treasury_factory_contract
+        .view("get_creation_status")
+        .args_json(json!({"name": instance_name}))
+        .await?
+        .json()?;
+    assert_eq!(creation_status["create_dao"], "Failed");
+    assert_eq!(creation_status["create_account"], "NotStarted");
+    assert_eq!(creation_status["upgrade_instance"], "NotStarted");
+    assert_eq!(
+        creation_status["refunded"],
+        NearToken::from_near(9).as_yoctonear().to_string()
+    );
+
+    let instance_record: Value = treasury_factory_contract
+        .view("get_instance")
+        .args_json(json!({"name": instance_name}))
+        .await?
+        .json()?;
+    assert_eq!(instance_record["outcome"], "DaoCreationFailed");
+
+    assert!(worker
+        .view_account(&format!("{}.near", instance_name).parse()?)
+        .await
+        .is_err());
+
+    let user_account_details_after = user_account.view_account().await?;
+    assert!(
+        user_account_details_before.balance.as_millinear()
+            - user_account_details_after.balance.as_millinear()
+            < 50,
+        "User balance after ( {} mNEAR ) should be almost the same as balance before ( {} mNEAR )",
+        user_account_details_after.balance.as_millinear(),
+        user_account_details_before.balance.as_millinear()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_factory_should_delete_stub_before_upgrading_it_if_dao_creation_fails(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let FactorySandbox {
+        worker,
+        treasury_factory_contract,
+        ..
+    } = setup_factory_sandbox().await?;
+
+    // Enough for the web4 upgrade, but too little for the sputnik factory to deploy the DAO
+    let set_creation_cost_result = treasury_factory_contract
+        .call("set_creation_cost")
+        .args_json(json!({
+            "creation_cost": {
+                "sputnik_dao_deposit": NearToken::from_near(1),
+                "social_db_deposit": NearToken::from_millinear(500),
+                "instance_account_deposit": NearToken::from_millinear(2500)
+            }
+        }))
+        .transact()
+        .await?;
+    assert!(set_creation_cost_result.is_success());
+
+    let instance_name = "test-delete-stub";
+    let user_account = worker.dev_create_account().await?;
+    let user_account_details_before = user_account.view_account().await?;
+
+    let create_treasury_instance_result = user_account
+        .call(treasury_factory_contract.id(), "create_instance")
+        .args_json(json!({
+            "sputnik_dao_factory_account_id": SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT,
+            "social_db_account_id": SOCIALDB_ACCOUNT,
+            "widget_reference_account_id": WIDGET_REFERENCE_ACCOUNT_ID,
+            "name": instance_name,
+            "create_dao_args": simple_create_dao_args(instance_name, user_account.id().as_str()),
+            "options": {
+                "delete_stub_on_failure": true
+            }
+        }))
+        .max_gas()
+        .deposit(NearToken::from_near(4))
+        .transact()
+        .await?;
+    println!("logs: {:?}", create_treasury_instance_result.logs());
+    assert!(!create_treasury_instance_result
+        .logs()
+        .iter()
+        .any(|log| log.contains(r#""event":"instance_upgraded""#)));
+
+    let creation_status: Value = treasury_factory_contract
+        .view("get_creation_status")
+        .args_json(json!({"name": instance_name}))
+        .await?
+        .json()?;
+    assert_eq!(creation_status["create_dao"], "Failed");
+    assert_eq!(creation_status["create_account"], "NotStarted");
+    assert_eq!(creation_status["upgrade_instance"], "NotStarted");
+    assert_eq!(creation_status["update_widgets"], "NotStarted");
+    assert_eq!(
+        creation_status["refunded"],
+        NearToken::from_millinear(1500).as_yoctonear().to_string()
+    );
+
+    let instance_record: Value = treasury_factory_contract
+        .view("get_instance")
+        .args_json(json!({"name": instance_name}))
+        .await?
+        .json()?;
+    assert_eq!(instance_record["outcome"], "StubDeleted");
+
+    assert!(worker
+        .view_account(&format!("{}.near", instance_name).parse()?)
+        .await
+        .is_err());
+
+    let user_account_details_after = user_account.view_account().await?;
+    assert!(
+        user_account_details_before.balance.as_millinear()
+            - user_account_details_after.balance.as_millinear()
+            < 50,
+        "User balance after ( {} mNEAR ) should be almost the same as balance before ( {} mNEAR )",
+        user_account_details_after.balance.as_millinear(),
+        user_account_details_before.balance.as_millinear()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_factory_should_refund_social_deposit_if_widgets_update_fails(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let FactorySandbox {
+        worker,
+        treasury_factory_contract,
+        ..
+    } = setup_factory_sandbox().await?;
+
+    trust_account(&treasury_factory_contract, "SocialDb", "no-social-db.near").await?;
+
+    let instance_name = "test-widgets-failure";
+    let user_account = worker.dev_create_account().await?;
+    let user_account_details_before = user_account.view_account().await?;
+
+    let create_treasury_instance_result = user_account
+        .call(treasury_factory_contract.id(), "create_instance")
+        .args_json(json!({
+            "sputnik_dao_factory_account_id": SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT,
+            "social_db_account_id": "no-social-db.near",
+            "widget_reference_account_id": WIDGET_REFERENCE_ACCOUNT_ID,
+            "name": instance_name,
+            "create_dao_args": simple_create_dao_args(instance_name, user_account.id().as_str())
+        }))
+        .max_gas()
+        .deposit(NearToken::from_near(9))
+        .transact()
+        .await?;
+    println!("logs: {:?}", create_treasury_instance_result.logs());
+
+    let creation_status: Value = treasury_factory_contract
+        .view("get_creation_status")
+        .args_json(json!({"name": instance_name}))
+        .await?
+        .json()?;
+    assert_eq!(creation_status["create_dao"], "Succeeded");
+    assert_eq!(creation_status["upgrade_instance"], "Succeeded");
+    assert_eq!(creation_status["update_widgets"], "Failed");
+    assert_eq!(
+        creation_status["refunded"],
+        NearToken::from_millinear(500).as_yoctonear().to_string()
+    );
+
+    // The social deposit that could not be spent on widgets is returned to the creator,
+    // who pays it again when resuming the step
+    let user_account_details_after = user_account.view_account().await?;
+    let user_balance_diff = user_account_details_before.balance.as_millinear()
+        - user_account_details_after.balance.as_millinear();
+    assert!(
+        user_balance_diff > 8500 && user_balance_diff < 8600,
+        "User balance after ( {} mNEAR ) should be 8.5 NEAR less than balance before ( {} mNEAR )",
+        user_account_details_after.balance.as_millinear(),
+        user_account_details_before.balance.as_millinear()
+    );
+
+    let resume_with_too_little_deposit_result = user_account
+        .call(treasury_factory_contract.id(), "resume_instance_creation")
+        .args_json(json!({"name": instance_name}))
+        .max_gas()
+        .deposit(NearToken::from_millinear(499))
+        .transact()
+        .await?;
+    assert!(resume_with_too_little_deposit_result.is_failure());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_factory_create_instance_for_existing_dao() -> Result<(), Box<dyn std::error::Error>> {
+    let FactorySandbox {
+        worker,
+        treasury_factory_contract,
+        ..
+    } = setup_factory_sandbox().await?;
+
+    let instance_name = "existing-dao-treasury";
+    let dao_member = worker.dev_create_account().await?;
+    let other_account = worker.dev_create_account().await?;
+
+    let create_dao_result = dao_member
+        .call(&SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT.parse()?, "create")
+        .args_json(json!({
+            "name": instance_name,
+            "args": simple_create_dao_args(instance_name, dao_member.id().as_str())
+        }))
+        .max_gas()
+        .deposit(NearToken::from_near(6))
+        .transact()
+        .await?;
+    assert!(create_dao_result.is_success());
+
+    let creation_cost: Value = treasury_factory_contract
+        .view("get_creation_cost")
+        .await?
+        .json()?;
+    assert_eq!(
+        creation_cost["total_for_existing_dao"],
+        NearToken::from_near(3).as_yoctonear().to_string()
+    );
+
+    let create_instance_args = json!({
+        "sputnik_dao_factory_account_id": SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT,
+        "social_db_account_id": SOCIALDB_ACCOUNT,
+        "widget_reference_account_id": WIDGET_REFERENCE_ACCOUNT_ID,
+        "name": instance_name
+    });
+
+    let other_account_details_before = other_account.view_account().await?;
+    let non_member_result = other_account
+        .call(
+            treasury_factory_contract.id(),
+            "create_instance_for_existing_dao",
+        )
+        .args_json(create_instance_args.clone())
+        .max_gas()
+        .deposit(NearToken::from_near(3))
+        .transact()
+        .await?;
+    let other_account_details_after = other_account.view_account().await?;
+    assert_eq!(
+        format!(
+            "{} is not an approver of treasury account {}.{}",
+            other_account.id(),
+            instance_name,
+            SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT
+        ),
+        non_member_result.logs().last().unwrap().to_owned()
+    );
+    assert!(
+        other_account_details_before.balance.as_millinear()
+            - other_account_details_after.balance.as_millinear()
+            < 10
+    );
+    assert!(worker
+        .view_account(&format!("{}.near", instance_name).parse()?)
+        .await
+        .is_err());
+
+    let member_result = dao_member
+        .call(
+            treasury_factory_contract.id(),
+            "create_instance_for_existing_dao",
+        )
+        .args_json(create_instance_args)
+        .max_gas()
+        .deposit(NearToken::from_near(3))
+        .transact()
+        .await?;
+    assert_eq!(
+        member_result.receipt_failures().len(),
+        0,
+        "{:?}",
+        member_result.receipt_failures()
+    );
+
+    let instance_record: Value = treasury_factory_contract
+        .view("get_instance")
+        .args_json(json!({"name": instance_name}))
+        .await?
+        .json()?;
+    assert_eq!(instance_record["outcome"], "Created");
+    assert_eq!(instance_record["creator_id"], dao_member.id().as_str());
+
+    let creation_status: Value = treasury_factory_contract
+        .view("get_creation_status")
+        .args_json(json!({"name": instance_name}))
+        .await?
+        .json()?;
+    assert_eq!(creation_status["update_widgets"], "Succeeded");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_factory_should_reject_untrusted_accounts() -> Result<(), Box<dyn std::error::Error>> {
+    let FactorySandbox {
+        worker,
+        treasury_factory_contract,
+        ..
+    } = setup_factory_sandbox().await?;
+
+    let trusted_accounts: Value = treasury_factory_contract
+        .view("get_trusted_accounts")
+        .await?
+        .json()?;
+    assert_eq!(
+        trusted_accounts["widget_reference_account_ids"],
+        json!([
+            "bootstrap.treasury-factory.near",
+            WIDGET_REFERENCE_ACCOUNT_ID
+        ])
+    );
+
+    let user_account = worker.dev_create_account().await?;
+    let create_treasury_instance_result = user_account
+        .call(treasury_factory_contract.id(), "create_instance")
+        .args_json(json!({
+            "sputnik_dao_factory_account_id": "evil-dao-factory.near",
+            "social_db_account_id": SOCIALDB_ACCOUNT,
+            "widget_reference_account_id": WIDGET_REFERENCE_ACCOUNT_ID,
+            "name": "untrusted",
+            "create_dao_args": simple_create_dao_args("untrusted", user_account.id().as_str())
+        }))
+        .max_gas()
+        .deposit(NearToken::from_near(9))
+        .transact()
+        .await?;
+    assert!(
+        format!("{:?}", create_treasury_instance_result.failures()).contains(
+            "sputnik_dao_factory_account_id evil-dao-factory.near is not accepted by the factory"
+        )
+    );
+
+    let non_owner_result = user_account
+        .call(treasury_factory_contract.id(), "add_trusted_account")
+        .args_json(json!({"kind": "SputnikDaoFactory", "account_id": "evil-dao-factory.near"}))
+        .transact()
+        .await?;
+    assert!(non_owner_result.is_failure());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_factory_create_instance_with_dao_config() -> Result<(), Box<dyn std::error::Error>> {
+    let FactorySandbox {
+        worker,
+        treasury_factory_contract,
+        ..
+    } = setup_factory_sandbox().await?;
+
+    let instance_name = "typedconfig";
+    let user_account = worker.dev_create_account().await?;
+    let dao_config = json!({
+        "purpose": "typed treasury",
+        "roles": [
+            {
+                "name": "Approver",
+                "members": [user_account.id()],
+                "permissions": ["transfer:AddProposal", "*:VoteApprove"],
+                "vote_threshold": { "Ratio": [1, 2] }
+            }
+        ],
+        "default_vote_threshold": { "Weight": "1" },
+        "proposal_bond": "0",
+        "voting_period": "604800000000000"
+    });
+
+    let mut invalid_dao_config = dao_config.clone();
+    invalid_dao_config["roles"][0]["permissions"] = json!(["transfers:AddProposal"]);
+    let invalid_result = user_account
+        .call(treasury_factory_contract.id(), "create_instance")
+        .args_json(json!({
+            "sputnik_dao_factory_account_id": SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT,
+            "social_db_account_id": SOCIALDB_ACCOUNT,
+            "widget_reference_account_id": WIDGET_REFERENCE_ACCOUNT_ID,
+            "name": instance_name,
+            "options": {
+                "dao_config": invalid_dao_config
+            }
+        }))
+        .max_gas()
+        .deposit(NearToken::from_near(9))
+        .transact()
+        .await?;
+    assert!(format!("{:?}", invalid_result.failures())
+        .contains("Invalid dao_config: Unknown proposal kind transfers"));
+    assert!(worker
+        .view_account(&format!("{}.near", instance_name).parse()?)
+        .await
+        .is_err());
+
+    let create_treasury_instance_result = user_account
+        .call(treasury_factory_contract.id(), "create_instance")
+        .args_json(json!({
+            "sputnik_dao_factory_account_id": SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT,
+            "social_db_account_id": SOCIALDB_ACCOUNT,
+            "widget_reference_account_id": WIDGET_REFERENCE_ACCOUNT_ID,
+            "name": instance_name,
+            "options": {
+                "dao_config": dao_config
+            }
+        }))
+        .max_gas()
+        .deposit(NearToken::from_near(9))
+        .transact()
+        .await?;
+    assert!(
+        create_treasury_instance_result.is_success(),
+        "{:?}",
+        create_treasury_instance_result.failures()
+    );
+
+    let policy: Value = worker
+        .view(
+            &format!("{}.{}", instance_name, SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT).parse()?,
+            "get_policy",
+        )
+        .await?
+        .json()?;
+    assert_eq!(
+        policy["roles"][0]["kind"]["Group"],
+        json!([user_account.id()])
+    );
+    assert_eq!(
+        policy["roles"][0]["vote_policy"]["transfer"]["threshold"],
+        json!([1, 2])
+    );
+    assert_eq!(policy["proposal_bond"], "0");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_factory_create_instance_with_branding() -> Result<(), Box<dyn std::error::Error>> {
+    let FactorySandbox {
+        worker,
+        treasury_factory_contract,
+        ..
+    } = setup_factory_sandbox().await?;
+
+    let instance_name = "brandedtreasury";
+    let user_account = worker.dev_create_account().await?;
+    let create_treasury_instance_result = user_account
+        .call(treasury_factory_contract.id(), "create_instance")
+        .args_json(json!({
+            "sputnik_dao_factory_account_id": SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT,
+            "social_db_account_id": SOCIALDB_ACCOUNT,
+            "widget_reference_account_id": WIDGET_REFERENCE_ACCOUNT_ID,
+            "name": instance_name,
+            "create_dao_args": simple_create_dao_args(instance_name, user_account.id().as_str()),
+            "options": {
+                "branding": {
+                    "name": "Typed Treasury",
+                    "tags": ["grants"]
+                }
+            }
+        }))
+        .max_gas()
+        .deposit(NearToken::from_near(9))
+        .transact()
+        .await?;
+    assert!(
+        create_treasury_instance_result.is_success(),
+        "{:?}",
+        create_treasury_instance_result.failures()
+    );
+
+    let instance_account_id = format!("{}.near", instance_name);
+    let social_metadata: Value = worker
+        .view(&SOCIALDB_ACCOUNT.parse()?, "get")
+        .args_json(json!({
+            "keys": [format!("{}/widget/app/metadata/**", instance_account_id)]
+        }))
+        .await?
+        .json()?;
+    let metadata = &social_metadata[&instance_account_id]["widget"]["app"]["metadata"];
+    assert_eq!(metadata["name"], "Typed Treasury");
+    assert_eq!(
+        metadata["description"],
+        format!("NEAR Treasury / {}", instance_account_id)
+    );
+    assert_eq!(
+        metadata["tags"],
+        json!({"app": "", "neartreasury": "", "grants": ""})
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_factory_create_instance_from_policy_template(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let FactorySandbox {
+        worker,
+        treasury_factory_contract,
+        ..
+    } = setup_factory_sandbox().await?;
+
+    let policy_templates: Value = treasury_factory_contract
+        .view("get_policy_templates")
+        .await?
+        .json()?;
+    let template_ids: Vec<&str> = policy_templates
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|template| template["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        template_ids,
+        vec!["small-multisig", "council-requestor", "foundation"]
+    );
+
+    let instance_name = "templated";
+    let user_account = worker.dev_create_account().await?;
+    let requestor_account = worker.dev_create_account().await?;
+    let create_treasury_instance_result = user_account
+        .call(treasury_factory_contract.id(), "create_instance")
+        .args_json(json!({
+            "sputnik_dao_factory_account_id": SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT,
+            "social_db_account_id": SOCIALDB_ACCOUNT,
+            "widget_reference_account_id": WIDGET_REFERENCE_ACCOUNT_ID,
+            "name": instance_name,
+            "options": {
+                "policy_template": {
+                    "template_id": "council-requestor",
+                    "members": {
+                        "Requestor": [requestor_account.id()],
+                        "Council": [user_account.id()]
+                    }
+                }
+            }
+        }))
+        .max_gas()
+        .deposit(NearToken::from_near(9))
+        .transact()
+        .await?;
+    assert!(
+        create_treasury_instance_result.is_success(),
+        "{:?}",
+        create_treasury_instance_result.failures()
+    );
+
+    let policy: Value = worker
+        .view(
+            &format!("{}.{}", instance_name, SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT).parse()?,
+            "get_policy",
+        )
+        .await?
+        .json()?;
+    assert_eq!(policy["roles"][0]["name"], "Requestor");
+    assert_eq!(
+        policy["roles"][0]["kind"]["Group"],
+        json!([requestor_account.id()])
+    );
+    assert_eq!(policy["roles"][1]["name"], "Council");
+    assert_eq!(
+        policy["roles"][1]["kind"]["Group"],
+        json!([user_account.id()])
+    );
+
+    let non_owner_result = user_account
+        .call(treasury_factory_contract.id(), "remove_policy_template")
+        .args_json(json!({"template_id": "foundation"}))
+        .transact()
+        .await?;
+    assert!(non_owner_result.is_failure());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_factory_create_instance_with_function_call_key_and_no_admin_key(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let FactorySandbox {
+        worker,
+        treasury_factory_contract,
+        ..
+    } = setup_factory_sandbox().await?;
+
+    let rotated_admin_public_key = "ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp";
+    let set_admin_public_key_result = treasury_factory_contract
+        .call("set_admin_public_key")
+        .args_json(json!({"admin_public_key": rotated_admin_public_key}))
+        .transact()
+        .await?;
+    assert!(set_admin_public_key_result.is_success());
+    let admin_public_key: Option<String> = treasury_factory_contract
+        .view("get_admin_public_key")
+        .await?
+        .json()?;
+    assert_eq!(admin_public_key.as_deref(), Some(rotated_admin_public_key));
+
+    let instance_name = "trustless";
+    let instance_account_id: AccountId = format!("{}.near", instance_name).parse()?;
+    let user_account = worker.dev_create_account().await?;
+    let create_treasury_instance_result = user_account
+        .call(treasury_factory_contract.id(), "create_instance")
+        .args_json(json!({
+            "sputnik_dao_factory_account_id": SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT,
+            "social_db_account_id": SOCIALDB_ACCOUNT,
+            "widget_reference_account_id": WIDGET_REFERENCE_ACCOUNT_ID,
+            "name": instance_name,
+            "create_dao_args": simple_create_dao_args(instance_name, user_account.id().as_str()),
+            "options": {
+                "access_keys": {
+                    "creator_key": "FunctionCall",
+                    "admin_key": "None"
+                }
+            }
+        }))
+        .max_gas()
+        .deposit(NearToken::from_near(9))
+        .transact()
+        .await?;
+    assert!(
+        create_treasury_instance_result.is_success(),
+        "{:?}",
+        create_treasury_instance_result.failures()
+    );
+
+    let access_keys = worker.view_access_keys(&instance_account_id).await?;
+    assert_eq!(access_keys.len(), 1);
+    assert_eq!(
+        access_keys[0].public_key,
+        user_account.secret_key().public_key()
+    );
+    match &access_keys[0].access_key.permission {
+        AccessKeyPermission::FunctionCall(function_call_permission) => {
+            assert_eq!(
+                function_call_permission.receiver_id,
+                instance_account_id.to_string()
+            );
+            assert_eq!(
+                function_call_permission.method_names,
+                vec!["update_widgets", "self_upgrade"]
+            );
+        }
+        _ => panic!("Expected FunctionCall permission"),
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_factory_init_with_network_config() -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let factory = worker.dev_deploy(&build_project_once()).await?;
+    let owner_account = worker.dev_create_account().await?;
+
+    let init_result = factory
+        .call("new")
+        .args_json(json!({
+            "owner_id": owner_account.id(),
+            "network_config": {"registrar_account_id": "testnet"},
+            "trusted_accounts": {
+                "sputnik_dao_factory_account_ids": ["sputnikv2.testnet"],
+                "social_db_account_ids": ["v1.social08.testnet"],
+                "widget_reference_account_ids": ["bootstrap.treasury-factory.testnet"]
+            }
+        }))
+        .transact()
+        .await?;
+    assert!(init_result.is_success(), "{:?}", init_result.failures());
+
+    let network_config: Value = factory.view("get_network_config").await?.json()?;
+    assert_eq!(network_config["registrar_account_id"], "testnet");
+    let owner_id: AccountId = factory.view("get_owner").await?.json()?;
+    assert_eq!(&owner_id, owner_account.id());
+    let trusted_accounts: Value = factory.view("get_trusted_accounts").await?.json()?;
+    assert_eq!(
+        trusted_accounts["sputnik_dao_factory_account_ids"],
+        json!(["sputnikv2.testnet"])
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_factory_create_instance_from_global_contract(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let FactorySandbox {
+        worker,
+        treasury_factory_contract,
+        ..
+    } = setup_factory_sandbox().await?;
+
+    let web4_contract_bytes =
+        include_bytes!("../../web4/treasury-web4/target/near/treasury_web4.wasm");
+    let upload_result = treasury_factory_contract
+        .call("upload_web4_release")
+        .args_json(json!({
+            "version": "1.0.0",
+            "code": BASE64_STANDARD.encode(web4_contract_bytes),
+            "notes": "global contract release",
+            "make_latest": true
+        }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(upload_result.is_success(), "{:?}", upload_result.failures());
+
+    let publish_result = treasury_factory_contract
+        .call("publish_web4_release_as_global_contract")
+        .args_json(json!({"version": "1.0.0"}))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(
+        publish_result.is_success(),
+        "{:?}",
+        publish_result.failures()
+    );
+    let release: Value = treasury_factory_contract
+        .view("get_web4_release")
+        .args_json(json!({"version": "1.0.0"}))
+        .await?
+        .json()?;
+    assert_eq!(release["global_contract"], true);
+    let global_contract_hash: Option<String> = treasury_factory_contract
+        .view("get_web4_global_contract_hash")
+        .args_json(json!({}))
+        .await?
+        .json()?;
+    assert_eq!(
+        global_contract_hash,
+        release["sha256"].as_str().map(String::from)
+    );
+
+    let instance_name = "globalinstance";
+    let user_account = worker.dev_create_account().await?;
+    let create_treasury_instance_result = user_account
+        .call(treasury_factory_contract.id(), "create_instance")
+        .args_json(json!({
+            "sputnik_dao_factory_account_id": SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT,
+            "social_db_account_id": SOCIALDB_ACCOUNT,
+            "widget_reference_account_id": WIDGET_REFERENCE_ACCOUNT_ID,
+            "name": instance_name,
+            "create_dao_args": simple_create_dao_args(instance_name, user_account.id().as_str())
+        }))
+        .max_gas()
+        .deposit(NearToken::from_near(9))
+        .transact()
+        .await?;
+    assert!(
+        create_treasury_instance_result.is_success(),
+        "{:?}",
+        create_treasury_instance_result.failures()
+    );
+
+    let creation_status: Value = treasury_factory_contract
+        .view("get_creation_status")
+        .args_json(json!({"name": instance_name}))
+        .await?
+        .json()?;
+    assert_eq!(creation_status["upgrade_instance"], "Succeeded");
+
+    // The web4 code is not stored on the instance account
+    let instance_account_id: AccountId = format!("{}.near", instance_name).parse()?;
+    let instance_account = worker.view_account(&instance_account_id).await?;
+    assert!(
+        (instance_account.storage_usage as usize) < web4_contract_bytes.len(),
+        "Storage usage {}",
+        instance_account.storage_usage
+    );
+
+    let web4_response: Value = worker
+        .view(&instance_account_id, "web4_get")
+        .args_json(json!({"request": {"path": "/"}}))
+        .await?
+        .json()?;
+    assert!(web4_response["preloadUrls"].is_array());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_factory_push_instance_upgrades() -> Result<(), Box<dyn std::error::Error>> {
+    let FactorySandbox {
+        worker,
+        treasury_factory_contract,
+        ..
+    } = setup_factory_sandbox().await?;
+
+    let user_account = worker.dev_create_account().await?;
+    for instance_name in ["autoupdate", "manualupdate"] {
+        let create_treasury_instance_result = user_account
+            .call(treasury_factory_contract.id(), "create_instance")
+            .args_json(json!({
+                "sputnik_dao_factory_account_id": SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT,
+                "social_db_account_id": SOCIALDB_ACCOUNT,
+                "widget_reference_account_id": WIDGET_REFERENCE_ACCOUNT_ID,
+                "name": instance_name,
+                "create_dao_args": simple_create_dao_args(instance_name, user_account.id().as_str())
+            }))
+            .max_gas()
+            .deposit(NearToken::from_near(9))
+            .transact()
+            .await?;
+        assert!(
+            create_treasury_instance_result.is_success(),
+            "{:?}",
+            create_treasury_instance_result.failures()
+        );
+    }
+
+    // The creator's full access key is on the instance account
+    let auto_update_instance = near_workspaces::Account::from_secret_key(
+        "autoupdate.near".parse()?,
+        user_account.secret_key().clone(),
+        &worker,
+    );
+    let set_auto_update_result = auto_update_instance
+        .call(auto_update_instance.id(), "set_auto_update")
+        .args_json(json!({"auto_update": true}))
+        .transact()
+        .await?;
+    assert!(
+        set_auto_update_result.is_success(),
+        "{:?}",
+        set_auto_update_result.failures()
+    );
+
+    let upload_result = treasury_factory_contract
+        .call("upload_web4_release")
+        .args_json(json!({
+            "version": "2.0.0",
+            "code": BASE64_STANDARD.encode(include_bytes!(
+                "../../web4/treasury-web4/target/near/treasury_web4.wasm"
+            )),
+            "notes": "pushed release",
+            "make_latest": true
+        }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(upload_result.is_success(), "{:?}", upload_result.failures());
+
+    let mut from_index: Option<u32> = Some(0);
+    while let Some(index) = from_index {
+        let push_result = treasury_factory_contract
+            .call("push_instance_upgrades")
+            .args_json(json!({"from_index": index}))
+            .max_gas()
+            .transact()
+            .await?;
+        assert!(push_result.is_success(), "{:?}", push_result.failures());
+        let batch: Value = push_result.json()?;
+        // The release is only available as wasm bytes, which one call deploys to one instance
+        assert!(batch["scheduled"].as_array().unwrap().len() <= 1);
+        from_index = batch["next_index"].as_u64().map(|index| index as u32);
+    }
+
+    let auto_update_record: Value = treasury_factory_contract
+        .view("get_instance")
+        .args_json(json!({"name": "autoupdate"}))
+        .await?
+        .json()?;
+    assert_eq!(auto_update_record["web4_version"], "2.0.0");
+    assert_eq!(
+        auto_update_record["last_push_upgrade"],
+        json!({"Upgraded": {"version": "2.0.0"}})
+    );
+
+    let failures: Value = treasury_factory_contract
+        .view("get_push_upgrade_failures")
+        .args_json(json!({}))
+        .await?
+        .json()?;
+    assert_eq!(failures.as_array().unwrap().len(), 1);
+    assert_eq!(failures[0]["name"], "manualupdate");
+    assert_eq!(
+        failures[0]["last_push_upgrade"],
+        json!({"Refused": {"version": "2.0.0"}})
+    );
+    assert_eq!(failures[0]["web4_version"], Value::Null);
+
+    // An instance upgrading itself reports its version to the factory
+    let manual_update_instance = near_workspaces::Account::from_secret_key(
+        "manualupdate.near".parse()?,
+        user_account.secret_key().clone(),
+        &worker,
+    );
+    let self_upgrade_result = manual_update_instance
+        .call(manual_update_instance.id(), "self_upgrade")
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(
+        self_upgrade_result.is_success(),
+        "{:?}",
+        self_upgrade_result.failures()
+    );
+    let manual_update_record: Value = treasury_factory_contract
+        .view("get_instance")
+        .args_json(json!({"name": "manualupdate"}))
+        .await?
+        .json()?;
+    assert_eq!(manual_update_record["web4_version"], "2.0.0");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_factory_rejects_invalid_names_before_taking_the_deposit(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let worker = near_workspaces::sandbox().await?;
+    let factory = worker.dev_deploy(&build_project_once()).await?;
+    let user_account = worker.dev_create_account().await?;
+
+    let name_check: Value = factory
+        .view("check_name")
+        .args_json(json!({"name": "My.Treasury"}))
+        .await?
+        .json()?;
+    assert_eq!(name_check["available"], false);
+    assert_eq!(
+        name_check["reasons"],
+        json!(["Name can only contain lowercase letters, digits, - and _"])
+    );
+
+    let user_balance_before = user_account.view_account().await?.balance;
+    let create_result = user_account
+        .call(factory.id(), "create_instance")
+        .args_json(json!({
+            "sputnik_dao_factory_account_id": SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT,
+            "social_db_account_id": SOCIALDB_ACCOUNT,
+            "widget_reference_account_id": WIDGET_REFERENCE_ACCOUNT_ID,
+            "name": "My.Treasury",
+            "create_dao_args": simple_create_dao_args("My.Treasury", user_account.id().as_str())
+        }))
+        .max_gas()
+        .deposit(NearToken::from_near(9))
+        .transact()
+        .await?;
+    assert!(create_result.is_failure());
+    assert!(format!("{:?}", create_result.failures()).contains(
+        "Invalid name My.Treasury: Name can only contain lowercase letters, digits, - and _"
+    ));
+    // The failed call returns the attached deposit, only gas is spent
+    let user_balance_after = user_account.view_account().await?.balance;
+    assert!(user_balance_before.saturating_sub(user_balance_after) < NearToken::from_millinear(50));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_factory_create_instance_paid_with_tokens() -> Result<(), Box<dyn std::error::Error>> {
+    let FactorySandbox {
+        worker,
+        mainnet,
+        treasury_factory_contract,
+    } = setup_factory_sandbox().await?;
+
+    let wrap_near_contract = worker
+        .import_contract(&"wrap.near".parse()?, &mainnet)
+        .initial_balance(NearToken::from_near(100))
+        .transact()
+        .await?;
+    assert!(wrap_near_contract
+        .call("new")
+        .max_gas()
+        .transact()
+        .await?
+        .is_success());
+
+    let user_account = worker.dev_create_account().await?;
+    for account_id in [user_account.id(), treasury_factory_contract.id()] {
+        assert!(user_account
+            .call(wrap_near_contract.id(), "storage_deposit")
+            .args_json(json!({"account_id": account_id}))
+            .deposit(NearToken::from_millinear(125))
+            .transact()
+            .await?
+            .is_success());
+    }
+    assert!(user_account
+        .call(wrap_near_contract.id(), "near_deposit")
+        .deposit(NearToken::from_near(20))
+        .transact()
+        .await?
+        .is_success());
+
+    let token_price = NearToken::from_near(10).as_yoctonear().to_string();
+    assert!(treasury_factory_contract
+        .call("set_token_price")
+        .args_json(json!({"token_account_id": wrap_near_contract.id(), "price": token_price}))
+        .transact()
+        .await?
+        .is_success());
+
+    let instance_name = "paidwithtokens";
+    let create_instance_args = json!({
+        "sputnik_dao_factory_account_id": SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT,
+        "social_db_account_id": SOCIALDB_ACCOUNT,
+        "widget_reference_account_id": WIDGET_REFERENCE_ACCOUNT_ID,
+        "name": instance_name,
+        "create_dao_args": simple_create_dao_args(instance_name, user_account.id().as_str())
+    });
+    let transfer_result = user_account
+        .call(wrap_near_contract.id(), "ft_transfer_call")
+        .args_json(json!({
+            "receiver_id": treasury_factory_contract.id(),
+            "amount": NearToken::from_near(12).as_yoctonear().to_string(),
+            "msg": create_instance_args.to_string()
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(
+        transfer_result.is_success(),
+        "{:?}",
+        transfer_result.failures()
+    );
+
+    let instance_record: Value = treasury_factory_contract
+        .view("get_instance")
+        .args_json(json!({"name": instance_name}))
+        .await?
+        .json()?;
+    assert_eq!(instance_record["outcome"], "Created");
+    assert_eq!(instance_record["creator_id"], user_account.id().as_str());
+
+    // Only the token price is kept, the rest of the transfer is returned
+    let factory_token_balance: String = wrap_near_contract
+        .view("ft_balance_of")
+        .args_json(json!({"account_id": treasury_factory_contract.id()}))
+        .await?
+        .json()?;
+    assert_eq!(factory_token_balance, token_price);
+    let user_token_balance: String = wrap_near_contract
+        .view("ft_balance_of")
+        .args_json(json!({"account_id": user_account.id()}))
+        .await?
+        .json()?;
+    assert_eq!(
+        user_token_balance,
+        NearToken::from_near(10).as_yoctonear().to_string()
+    );
+
+    // Transfers below the token price are returned
+    let underpaid_result = user_account
+        .call(wrap_near_contract.id(), "ft_transfer_call")
+        .args_json(json!({
+            "receiver_id": treasury_factory_contract.id(),
+            "amount": "1",
+            "msg": create_instance_args.to_string()
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(format!("{:?}", underpaid_result.failures()).contains("Must transfer at least"));
+    let user_token_balance_after: String = wrap_near_contract
+        .view("ft_balance_of")
+        .args_json(json!({"account_id": user_account.id()}))
+        .await?
+        .json()?;
+    assert_eq!(user_token_balance_after, user_token_balance);
+
+    // Enough for the stub account, but too little for the sputnik factory to deploy the DAO
+    assert!(treasury_factory_contract
+        .call("set_creation_cost")
+        .args_json(json!({
+            "creation_cost": {
+                "sputnik_dao_deposit": NearToken::from_near(1),
+                "social_db_deposit": NearToken::from_millinear(500),
+                "instance_account_deposit": NearToken::from_millinear(2500)
+            }
+        }))
+        .transact()
+        .await?
+        .is_success());
+    let failing_instance_name = "tokenstub";
+    let factory_balance_before = treasury_factory_contract.view_account().await?.balance;
+    let failing_transfer_result = user_account
+        .call(wrap_near_contract.id(), "ft_transfer_call")
+        .args_json(json!({
+            "receiver_id": treasury_factory_contract.id(),
+            "amount": token_price,
+            "msg": json!({
+                "sputnik_dao_factory_account_id": SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT,
+                "social_db_account_id": SOCIALDB_ACCOUNT,
+                "widget_reference_account_id": WIDGET_REFERENCE_ACCOUNT_ID,
+                "name": failing_instance_name,
+                "create_dao_args": simple_create_dao_args(
+                    failing_instance_name,
+                    user_account.id().as_str()
+                ),
+                "options": {
+                    "delete_stub_on_failure": true
+                }
+            })
+            .to_string()
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(
+        failing_transfer_result.is_success(),
+        "{:?}",
+        failing_transfer_result.failures()
+    );
+
+    let failing_instance_record: Value = treasury_factory_contract
+        .view("get_instance")
+        .args_json(json!({"name": failing_instance_name}))
+        .await?
+        .json()?;
+    assert_eq!(failing_instance_record["outcome"], "StubDeleted");
+    assert!(worker
+        .view_account(&format!("{}.near", failing_instance_name).parse()?)
+        .await
+        .is_err());
+    let failing_creation_status: Value = treasury_factory_contract
+        .view("get_creation_status")
+        .args_json(json!({"name": failing_instance_name}))
+        .await?
+        .json()?;
+    assert_eq!(
+        failing_creation_status["refunded"],
+        NearToken::from_near(4).as_yoctonear().to_string()
+    );
+
+    // The stub is deleted in favour of the factory, which refunds the whole token payment
+    let user_token_balance_after_failure: String = wrap_near_contract
+        .view("ft_balance_of")
+        .args_json(json!({"account_id": user_account.id()}))
+        .await?
+        .json()?;
+    assert_eq!(user_token_balance_after_failure, user_token_balance);
+    let factory_token_balance_after_failure: String = wrap_near_contract
+        .view("ft_balance_of")
+        .args_json(json!({"account_id": treasury_factory_contract.id()}))
+        .await?
+        .json()?;
+    assert_eq!(factory_token_balance_after_failure, token_price);
+    let factory_balance_after = treasury_factory_contract.view_account().await?.balance;
+    assert!(
+        factory_balance_before.saturating_sub(factory_balance_after)
+            < NearToken::from_millinear(100),
+        "Factory balance after ( {} mNEAR ) should be almost the same as balance before ( {} mNEAR )",
+        factory_balance_after.as_millinear(),
+        factory_balance_before.as_millinear()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_factory_create_instance_under_parent_account(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let FactorySandbox {
+        worker,
+        mainnet,
+        treasury_factory_contract,
+    } = setup_factory_sandbox().await?;
+
+    // The parent runs the same account creation contract as the `near` registrar
+    let parent_account = worker
+        .root_account()?
+        .create_subaccount("myorg")
+        .initial_balance(NearToken::from_near(50))
+        .transact()
+        .await?
+        .into_result()?;
+    let registrar_code = mainnet.view_code(&"near".parse()?).await?;
+    parent_account
+        .deploy(&registrar_code)
+        .await?
+        .into_result()?;
+    assert!(parent_account
+        .call(parent_account.id(), "new")
+        .max_gas()
+        .transact()
+        .await?
+        .is_success());
+
+    let user_account = worker.dev_create_account().await?;
+    let create_instance_args = json!({
+        "sputnik_dao_factory_account_id": SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT,
+        "social_db_account_id": SOCIALDB_ACCOUNT,
+        "widget_reference_account_id": WIDGET_REFERENCE_ACCOUNT_ID,
+        "name": "treasury",
+        "options": {
+            "parent_account_id": parent_account.id(),
+            "dao_config": {
+                "purpose": "org treasury",
+                "roles": [
+                    {
+                        "name": "Admin",
+                        "members": [user_account.id()],
+                        "permissions": ["*:*"],
+                        "vote_threshold": { "Weight": "1" }
+                    }
+                ],
+                "default_vote_threshold": { "Weight": "1" },
+                "proposal_bond": "0",
+                "voting_period": "604800000000000"
+            }
+        }
+    });
+
+    let unregistered_result = user_account
+        .call(treasury_factory_contract.id(), "create_instance")
+        .args_json(create_instance_args.clone())
+        .max_gas()
+        .deposit(NearToken::from_near(9))
+        .transact()
+        .await?;
+    assert!(
+        format!("{:?}", unregistered_result.failures()).contains(&format!(
+            "{} is not registered as a parent account",
+            parent_account.id()
+        ))
+    );
+
+    assert!(parent_account
+        .call(treasury_factory_contract.id(), "register_parent_account")
+        .args_json(json!({"creators": [user_account.id()]}))
+        .deposit(NearToken::from_millinear(100))
+        .transact()
+        .await?
+        .is_success());
+
+    let create_instance_result = user_account
+        .call(treasury_factory_contract.id(), "create_instance")
+        .args_json(create_instance_args)
+        .max_gas()
+        .deposit(NearToken::from_near(9))
+        .transact()
+        .await?;
+    assert_eq!(
+        create_instance_result.receipt_failures().len(),
+        0,
+        "{:?}",
+        create_instance_result.receipt_failures()
+    );
+
+    let instance_name = format!(
+        "treasury-{}",
+        parent_account
+            .id()
+            .as_str()
+            .trim_end_matches(".near")
+            .replace('.', "-")
+    );
+    let instance_record: Value = treasury_factory_contract
+        .view("get_instance")
+        .args_json(json!({"name": instance_name}))
+        .await?
+        .json()?;
+    assert_eq!(instance_record["outcome"], "Created");
+    assert_eq!(
+        instance_record["instance_account_id"],
+        format!("treasury.{}", parent_account.id())
+    );
+
+    assert!(worker
+        .view_account(&format!("treasury.{}", parent_account.id()).parse()?)
+        .await
+        .is_ok());
+    let policy: Value = worker
+        .view(
+            &format!("{}.{}", instance_name, SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT).parse()?,
+            "get_policy",
+        )
+        .await?
+        .json()?;
+    assert_eq!(
+        policy["roles"][0]["kind"]["Group"],
+        json!([user_account.id()])
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_factory_create_instances_batch() -> Result<(), Box<dyn std::error::Error>> {
+    let FactorySandbox {
+        worker,
+        treasury_factory_contract,
+        ..
+    } = setup_factory_sandbox().await?;
+
+    let user_account = worker.dev_create_account().await?;
+    let instance_spec = |name: &str| {
+        json!({
+            "sputnik_dao_factory_account_id": SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT,
+            "social_db_account_id": SOCIALDB_ACCOUNT,
+            "widget_reference_account_id": WIDGET_REFERENCE_ACCOUNT_ID,
+            "name": name,
+            "options": {
+                "dao_config": {
+                    "purpose": name,
+                    "roles": [
+                        {
+                            "name": "Admin",
+                            "members": [user_account.id()],
+                            "permissions": ["*:*"],
+                            "vote_threshold": { "Weight": "1" }
+                        }
+                    ],
+                    "default_vote_threshold": { "Weight": "1" },
+                    "proposal_bond": "0",
+                    "voting_period": "604800000000000"
+                }
+            }
+        })
+    };
+
+    let create_instances_result = user_account
+        .call(treasury_factory_contract.id(), "create_instances")
+        .args_json(json!({
+            "instances": [instance_spec("batchgrants"), instance_spec("batchops")]
+        }))
+        .max_gas()
+        .deposit(NearToken::from_near(18))
+        .transact()
+        .await?;
+    assert_eq!(
+        create_instances_result.receipt_failures().len(),
+        0,
+        "{:?}",
+        create_instances_result.receipt_failures()
+    );
+    let reports: Value = create_instances_result.json()?;
+    assert_eq!(reports[0]["name"], "batchgrants");
+    assert_eq!(reports[0]["outcome"], "Created");
+    assert_eq!(reports[0]["complete"], true);
+    assert_eq!(reports[1]["name"], "batchops");
+    assert_eq!(reports[1]["outcome"], "Pending");
+    assert_eq!(reports[1]["queued"], true);
+
+    // Queued creations are already paid for, so anyone can start them
+    let other_account = worker.dev_create_account().await?;
+    let start_result = other_account
+        .call(treasury_factory_contract.id(), "start_instance_creation")
+        .args_json(json!({"name": "batchops"}))
+        .max_gas()
+        .transact()
+        .await?;
+    assert_eq!(
+        start_result.receipt_failures().len(),
+        0,
+        "{:?}",
+        start_result.receipt_failures()
+    );
+
+    for name in ["batchgrants", "batchops"] {
+        let instance_record: Value = treasury_factory_contract
+            .view("get_instance")
+            .args_json(json!({"name": name}))
+            .await?
+            .json()?;
+        assert_eq!(instance_record["outcome"], "Created");
+        assert_eq!(instance_record["creator_id"], user_account.id().as_str());
+        assert!(worker
+            .view_account(&format!("{}.near", name).parse()?)
+            .await
+            .is_ok());
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_factory_instance_decommissioning() -> Result<(), Box<dyn std::error::Error>> {
+    let FactorySandbox {
+        worker,
+        treasury_factory_contract,
+        ..
+    } = setup_factory_sandbox().await?;
+
+    let instance_name = "retiring";
+    let user_account = worker.dev_create_account().await?;
+    let create_instance_args = json!({
+        "sputnik_dao_factory_account_id": SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT,
+        "social_db_account_id": SOCIALDB_ACCOUNT,
+        "widget_reference_account_id": WIDGET_REFERENCE_ACCOUNT_ID,
+        "name": instance_name,
+        "create_dao_args": simple_create_dao_args(instance_name, user_account.id().as_str())
+    });
+    let create_treasury_instance_result = user_account
+        .call(treasury_factory_contract.id(), "create_instance")
+        .args_json(create_instance_args.clone())
+        .max_gas()
+        .deposit(NearToken::from_near(9))
+        .transact()
+        .await?;
+    assert!(
+        create_treasury_instance_result.is_success(),
+        "{:?}",
+        create_treasury_instance_result.failures()
+    );
+
+    // The creator's full access key is on the instance account, which can call the
+    // method just like an approved DAO proposal
+    let instance_account = near_workspaces::Account::from_secret_key(
+        format!("{}.near", instance_name).parse()?,
+        user_account.secret_key().clone(),
+        &worker,
+    );
+    let beneficiary_balance_before = user_account.view_account().await?.balance;
+    let decommission_result = instance_account
+        .call(instance_account.id(), "decommission")
+        .args_json(json!({"beneficiary_id": user_account.id()}))
+        .max_gas()
+        .transact()
+        .await?;
+    assert_eq!(
+        decommission_result.receipt_failures().len(),
+        0,
+        "{:?}",
+        decommission_result.receipt_failures()
+    );
+    assert!(decommission_result
+        .logs()
+        .iter()
+        .any(|log| log.contains(r#""event":"instance_retired""#)));
+
+    assert!(worker.view_account(instance_account.id()).await.is_err());
+    assert!(user_account.view_account().await?.balance > beneficiary_balance_before);
+    let widgets: Value = worker
+        .view(&SOCIALDB_ACCOUNT.parse()?, "get")
+        .args_json(json!({
+            "keys": [format!("{}/widget/**", instance_account.id())]
+        }))
+        .await?
+        .json()?;
+    assert_eq!(widgets, json!({}));
+
+    let instance_record: Value = treasury_factory_contract
+        .view("get_instance")
+        .args_json(json!({"name": instance_name}))
+        .await?
+        .json()?;
+    assert_eq!(instance_record["outcome"], "Retired");
+
+    let recreate_result = user_account
+        .call(treasury_factory_contract.id(), "create_instance")
+        .args_json(create_instance_args)
+        .max_gas()
+        .deposit(NearToken::from_near(9))
+        .transact()
+        .await?;
+    assert!(format!("{:?}", recreate_result.failures())
+        .contains("Instance retiring was retired and its name cannot be reused"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_factory_create_instance_with_voucher() -> Result<(), Box<dyn std::error::Error>> {
+    let FactorySandbox {
+        worker,
+        mainnet,
+        treasury_factory_contract,
+    } = setup_factory_sandbox().await?;
+
+    // The DAO of intellex exists, so its creation fails before the instance account is created
+    let _ = worker
+        .import_contract(&"intellex.sputnik-dao.near".parse()?, &mainnet)
+        .initial_balance(NearToken::from_near(100))
+        .transact()
+        .await?;
+
+    let sponsor_account = worker.dev_create_account().await?;
+    let voucher_hash: Base58CryptoHash = near_sdk::env::sha256_array(b"grant-code").into();
+    let create_sponsorship_result = sponsor_account
+        .call(treasury_factory_contract.id(), "create_sponsorship")
+        .args_json(json!({"voucher_hashes": [voucher_hash]}))
+        .deposit(NearToken::from_near(9))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(
+        create_sponsorship_result.is_success(),
+        "{:?}",
+        create_sponsorship_result.failures()
+    );
+    let sponsorship_id: u64 = create_sponsorship_result.json()?;
+
+    let holder_account = worker.dev_create_account().await?;
+    let holder_account_details_before = holder_account.view_account().await?;
+
+    let failed_creation_result = holder_account
+        .call(treasury_factory_contract.id(), "create_instance")
+        .args_json(json!({
+            "sputnik_dao_factory_account_id": SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT,
+            "social_db_account_id": SOCIALDB_ACCOUNT,
+            "widget_reference_account_id": WIDGET_REFERENCE_ACCOUNT_ID,
+            "name": "intellex",
+            "create_dao_args": simple_create_dao_args("intellex", holder_account.id().as_str()),
+            "options": {
+                "voucher": {"Code": "grant-code"}
+            }
+        }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(failed_creation_result
+        .logs()
+        .iter()
+        .any(|log| log.contains(r#""event":"voucher_restored""#)));
+
+    let sponsorship: Value = treasury_factory_contract
+        .view("get_sponsorship")
+        .args_json(json!({"sponsorship_id": sponsorship_id}))
+        .await?
+        .json()?;
+    assert_eq!(sponsorship["voucher_hashes"], json!([voucher_hash]));
+    assert_eq!(sponsorship["credit"], "0");
+
+    let instance_name = "vouchertreasury";
+    let create_treasury_instance_result = holder_account
+        .call(treasury_factory_contract.id(), "create_instance")
+        .args_json(json!({
+            "sputnik_dao_factory_account_id": SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT,
+            "social_db_account_id": SOCIALDB_ACCOUNT,
+            "widget_reference_account_id": WIDGET_REFERENCE_ACCOUNT_ID,
+            "name": instance_name,
+            "create_dao_args": simple_create_dao_args(instance_name, holder_account.id().as_str()),
+            "options": {
+                "voucher": {"Code": "grant-code"}
+            }
+        }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert_eq!(
+        create_treasury_instance_result.receipt_failures().len(),
+        0,
+        "{:?}",
+        create_treasury_instance_result.receipt_failures()
+    );
+
+    let instance_record: Value = treasury_factory_contract
+        .view("get_instance")
+        .args_json(json!({"name": instance_name}))
+        .await?
+        .json()?;
+    assert_eq!(instance_record["outcome"], "Created");
+    assert_eq!(instance_record["creator_id"], holder_account.id().as_str());
+
+    let redemptions: Value = treasury_factory_contract
+        .view("get_sponsorship_redemptions")
+        .args_json(json!({"sponsorship_id": sponsorship_id}))
+        .await?
+        .json()?;
+    assert_eq!(redemptions.as_array().unwrap().len(), 2);
+    assert_eq!(redemptions[0]["name"], "intellex");
+    assert_eq!(redemptions[0]["outcome"], "DaoCreationFailed");
+    assert_eq!(redemptions[1]["name"], instance_name);
+    assert_eq!(redemptions[1]["outcome"], "Created");
+
+    let sponsorship: Value = treasury_factory_contract
+        .view("get_sponsorship")
+        .args_json(json!({"sponsorship_id": sponsorship_id}))
+        .await?
+        .json()?;
+    assert_eq!(sponsorship["voucher_hashes"], json!([]));
+
+    // The holder only paid for gas
+    let holder_account_details_after = holder_account.view_account().await?;
+    assert!(
+        holder_account_details_before.balance.as_millinear()
+            - holder_account_details_after.balance.as_millinear()
+            < 50
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_factory_create_instance_with_fee_and_referral(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let FactorySandbox {
+        worker,
+        treasury_factory_contract,
+        ..
+    } = setup_factory_sandbox().await?;
+
+    let set_fee_config_result = treasury_factory_contract
+        .call("set_fee_config")
+        .args_json(json!({
+            "fee_config": {
+                "creation_fee": NearToken::from_near(1),
+                "referral_share_bps": 2_000
+            }
+        }))
+        .transact()
+        .await?;
+    assert!(
+        set_fee_config_result.is_success(),
+        "{:?}",
+        set_fee_config_result.failures()
+    );
+    let creation_cost: Value = treasury_factory_contract
+        .view("get_creation_cost")
+        .await?
+        .json()?;
+    let total_cost: NearToken = serde_json::from_value(creation_cost["total"].clone())?;
+
+    let instance_name = "feetreasury";
+    let user_account = worker.dev_create_account().await?;
+    let referrer_account = worker.dev_create_account().await?;
+    let create_treasury_instance_result = user_account
+        .call(treasury_factory_contract.id(), "create_instance")
+        .args_json(json!({
+            "sputnik_dao_factory_account_id": SPUTNIKDAO_FACTORY_CONTRACT_ACCOUNT,
+            "social_db_account_id": SOCIALDB_ACCOUNT,
+            "widget_reference_account_id": WIDGET_REFERENCE_ACCOUNT_ID,
+            "name": instance_name,
+            "create_dao_args": simple_create_dao_args(instance_name, user_account.id().as_str()),
+            "options": {
+                "referrer_id": referrer_account.id()
+            }
+        }))
+        .max_gas()
+        .deposit(total_cost)
+        .transact()
+        .await?;
+    assert!(
+        create_treasury_instance_result.is_success(),
+        "{:?}",
+        create_treasury_instance_result.failures()
+    );
+
+    let fee_accounting: Value = treasury_factory_contract
+        .view("get_fee_accounting")
+        .await?
+        .json()?;
+    assert_eq!(fee_accounting["pending"], "0");
+    assert_eq!(
+        fee_accounting["collected"],
+        NearToken::from_near(1).as_yoctonear().to_string()
+    );
+    assert_eq!(
+        fee_accounting["referral_accrued"],
+        NearToken::from_millinear(200).as_yoctonear().to_string()
+    );
+    assert_eq!(
+        fee_accounting["withdrawable"],
+        NearToken::from_millinear(800).as_yoctonear().to_string()
+    );
+
+    let referrer_balance_before = referrer_account.view_account().await?.balance;
+    let claim_result = referrer_account
+        .call(treasury_factory_contract.id(), "claim")
+        .transact()
+        .await?;
+    assert!(claim_result.is_success(), "{:?}", claim_result.failures());
+    let referrer_balance_after = referrer_account.view_account().await?.balance;
+    assert!(
+        referrer_balance_after.saturating_sub(referrer_balance_before)
+            > NearToken::from_millinear(190)
+    );
+    assert!(!referrer_account
+        .call(treasury_factory_contract.id(), "claim")
+        .transact()
+        .await?
+        .is_success());
+
+    let fees_receiver = worker.dev_create_account().await?;
+    let receiver_balance_before = fees_receiver.view_account().await?.balance;
+    let withdraw_fees_result = treasury_factory_contract
+        .call("withdraw_fees")
+        .args_json(json!({"receiver_id": fees_receiver.id()}))
+        .transact()
+        .await?;
+    assert!(
+        withdraw_fees_result.is_success(),
+        "{:?}",
+        withdraw_fees_result.failures()
+    );
+    assert_eq!(
+        fees_receiver
+            .view_account()
+            .await?
+            .balance
+            .saturating_sub(receiver_balance_before),
+        NearToken::from_millinear(800)
+    );
+    let withdraw_again_result = treasury_factory_contract
+        .call("withdraw_fees")
+        .args_json(json!({}))
+        .transact()
+        .await?;
+    assert!(format!("{:?}", withdraw_again_result.failures()).contains("Nothing to withdraw"));
+
+    Ok(())
+}
@@ -51,6 +51,17 @@ async fn test_minimum_self_upgrade_wasm() -> Result<(), Box<dyn std::error::Erro
     .unwrap();
 
     let other_account = sandbox.dev_create_account().await?;
+    let use_global_contract_result = other_account
+        .call(contract.id(), "use_global_contract")
+        .args([0u8; 32].to_vec())
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(
+        use_global_contract_result.is_failure(),
+        "Another account should not be able to switch the contract to a global contract"
+    );
+
     let upgrade_result = other_account
         .call(contract.id(), "upgrade")
         .args(new_contract_wasm.clone())
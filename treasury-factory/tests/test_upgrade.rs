@@ -1,7 +1,9 @@
 use std::fs;
 
 use cargo_near_build::BuildOpts;
+use near_sdk::base64::{engine::general_purpose::STANDARD as BASE64_STANDARD, Engine as _};
 use near_workspaces;
+use serde_json::{json, Value};
 
 #[tokio::test]
 async fn test_get_web4_contract_bytes() -> Result<(), Box<dyn std::error::Error>> {
@@ -20,3 +22,204 @@ async fn test_get_web4_contract_bytes() -> Result<(), Box<dyn std::error::Error>
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_web4_releases() -> Result<(), Box<dyn std::error::Error>> {
+    let sandbox = near_workspaces::sandbox_with_version("2.7.0").await?;
+    let build_opts = BuildOpts::builder().build();
+    let build_artifact = cargo_near_build::build(build_opts).expect("Failed to build contract");
+
+    let contract_wasm = fs::read(build_artifact.path).expect("Unable to read contract wasm");
+    let contract = sandbox.dev_deploy(&contract_wasm).await?;
+
+    let latest_version: Option<String> = contract.view("get_latest_web4_version").await?.json()?;
+    assert_eq!(latest_version, None);
+
+    for (version, code) in [
+        ("1.0.0", b"release one".to_vec()),
+        ("1.1.0", b"release two".to_vec()),
+    ] {
+        let upload_result = contract
+            .call("upload_web4_release")
+            .args_json(json!({
+                "version": version,
+                "code": BASE64_STANDARD.encode(&code),
+                "notes": format!("notes for {}", version),
+                "make_latest": true
+            }))
+            .max_gas()
+            .transact()
+            .await?;
+        assert!(upload_result.is_success(), "{:?}", upload_result.failures());
+    }
+
+    let latest_version: Option<String> = contract.view("get_latest_web4_version").await?.json()?;
+    assert_eq!(latest_version.as_deref(), Some("1.1.0"));
+    assert_eq!(
+        contract.view("get_web4_contract_bytes").await?.result,
+        b"release two"
+    );
+    assert_eq!(
+        contract
+            .view("get_web4_contract_bytes")
+            .args_json(json!({"version": "1.0.0"}))
+            .await?
+            .result,
+        b"release one"
+    );
+
+    let releases: Value = contract.view("get_web4_releases").await?.json()?;
+    assert_eq!(releases[0]["version"], "1.0.0");
+    assert_eq!(releases[0]["notes"], "notes for 1.0.0");
+    assert_eq!(releases[0]["size"], 11);
+
+    // Roll back, then deprecate the newer release
+    assert!(contract
+        .call("set_latest_web4_version")
+        .args_json(json!({"version": "1.0.0"}))
+        .transact()
+        .await?
+        .is_success());
+    assert!(contract
+        .call("deprecate_web4_release")
+        .args_json(json!({"version": "1.1.0"}))
+        .transact()
+        .await?
+        .is_success());
+    assert_eq!(
+        contract.view("get_web4_contract_bytes").await?.result,
+        b"release one"
+    );
+    assert!(contract
+        .view("get_web4_contract_bytes")
+        .args_json(json!({"version": "1.1.0"}))
+        .await
+        .is_err());
+
+    let non_owner = sandbox.dev_create_account().await?;
+    let non_owner_upload_result = non_owner
+        .call(contract.id(), "upload_web4_release")
+        .args_json(json!({
+            "version": "2.0.0",
+            "code": BASE64_STANDARD.encode(b"evil"),
+            "notes": "",
+            "make_latest": true
+        }))
+        .transact()
+        .await?;
+    assert!(non_owner_upload_result.is_failure());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_migrate_from_legacy_factory() -> Result<(), Box<dyn std::error::Error>> {
+    let mainnet = near_workspaces::custom("https://rpc.mainnet.fastnear.com").await?;
+    let sandbox = near_workspaces::sandbox_with_version("2.7.0").await?;
+    let build_opts = BuildOpts::builder().build();
+    let build_artifact = cargo_near_build::build(build_opts).expect("Failed to build contract");
+    let contract_wasm = fs::read(build_artifact.path).expect("Unable to read contract wasm");
+
+    let legacy_contract = sandbox
+        .import_contract(&"treasury-factory.near".parse()?, &mainnet)
+        .initial_balance(near_workspaces::types::NearToken::from_near(100))
+        .transact()
+        .await?;
+    // The legacy factory stored an empty `Contract {}` after its first call
+    sandbox
+        .patch_state(legacy_contract.id(), b"STATE", &[])
+        .await?;
+
+    let contract = legacy_contract
+        .as_account()
+        .deploy(&contract_wasm)
+        .await?
+        .into_result()?;
+    assert!(contract.view("get_owner").await.is_err());
+
+    let non_owner = sandbox.dev_create_account().await?;
+    assert!(non_owner
+        .call(contract.id(), "migrate")
+        .transact()
+        .await?
+        .is_failure());
+
+    let migrate_result = contract.call("migrate").max_gas().transact().await?;
+    assert!(
+        migrate_result.is_success(),
+        "{:?}",
+        migrate_result.failures()
+    );
+    assert!(migrate_result
+        .logs()
+        .iter()
+        .any(|log| log
+            .contains(r#""event":"state_migrated","data":{"from_version":0,"to_version":1}"#)));
+
+    let state_version: u32 = contract.view("get_state_version").await?.json()?;
+    assert_eq!(state_version, 1);
+    let owner: String = contract.view("get_owner").await?.json()?;
+    assert_eq!(owner, "treasury-factory.near");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_factory_upgrade() -> Result<(), Box<dyn std::error::Error>> {
+    let sandbox = near_workspaces::sandbox_with_version("2.7.0").await?;
+    let build_opts = BuildOpts::builder().build();
+    let build_artifact = cargo_near_build::build(build_opts).expect("Failed to build contract");
+    let contract_wasm = fs::read(build_artifact.path).expect("Unable to read contract wasm");
+    let contract = sandbox.dev_deploy(&contract_wasm).await?;
+
+    let upload_result = contract
+        .call("upload_web4_release")
+        .args_json(json!({
+            "version": "1.0.0",
+            "code": BASE64_STANDARD.encode(b"release one"),
+            "notes": "",
+            "make_latest": true
+        }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(upload_result.is_success(), "{:?}", upload_result.failures());
+
+    let non_owner = sandbox.dev_create_account().await?;
+    let non_owner_upgrade_result = non_owner
+        .call(contract.id(), "upgrade")
+        .args(contract_wasm.clone())
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(non_owner_upgrade_result.is_failure());
+
+    let upgrade_result = contract
+        .call("upgrade")
+        .args(contract_wasm)
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(
+        upgrade_result.is_success(),
+        "{:?}",
+        upgrade_result.failures()
+    );
+    assert!(upgrade_result
+        .logs()
+        .iter()
+        .any(|log| log.contains(r#""event":"factory_upgrade_started""#)));
+    assert!(upgrade_result
+        .logs()
+        .iter()
+        .any(|log| log
+            .contains(r#""event":"state_migrated","data":{"from_version":1,"to_version":1}"#)));
+
+    // The state survives the upgrade
+    let latest_version: Option<String> = contract.view("get_latest_web4_version").await?.json()?;
+    assert_eq!(latest_version.as_deref(), Some("1.0.0"));
+    let state_version: u32 = contract.view("get_state_version").await?.json()?;
+    assert_eq!(state_version, 1);
+
+    Ok(())
+}
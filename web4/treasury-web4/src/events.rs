@@ -0,0 +1,23 @@
+use near_sdk::{json_types::Base58CryptoHash, near, AccountId};
+
+/// NEP-297 events emitted by treasury instances, logged as `EVENT_JSON:{...}`.
+#[near(event_json(standard = "treasury-web4"))]
+pub enum Web4Event {
+    /// The widgets of the reference account were written to the social db
+    #[event_version("1.0.0")]
+    WidgetsUpdated {
+        widget_reference_account_id: AccountId,
+        social_db_account_id: AccountId,
+    },
+    /// The instance switched to the web4 release `version` of the factory, or to its
+    /// latest release if `None`. Emitted by the contract it upgraded to.
+    #[event_version("1.0.0")]
+    InstanceUpgraded {
+        version: Option<String>,
+        code_hash: Base58CryptoHash,
+    },
+    /// The instance removed its widgets and is deleting its account in favour of
+    /// `beneficiary_id`
+    #[event_version("1.0.0")]
+    Decommissioned { beneficiary_id: AccountId },
+}
@@ -1,15 +1,108 @@
 // Find all our documentation at https://docs.near.org
+mod events;
 mod web4;
+use events::Web4Event;
 use near_sdk::base64::{engine::general_purpose::STANDARD as BASE64_STANDARD, Engine as _};
-use near_sdk::{env, near, serde_json, Gas, NearToken, Promise, PromiseResult};
+use near_sdk::{
+    env, json_types::Base58CryptoHash, near, serde_json, CryptoHash, Gas, NearToken, Promise,
+    PromiseOrValue, PromiseResult,
+};
 use web4::types::{Web4Request, Web4Response};
 
+#[cfg(not(feature = "testnet"))]
 const TREASURY_FACTORY_ACCOUNT_ID: &near_sdk::AccountIdRef =
     near_sdk::AccountIdRef::new_or_panic("treasury-factory.near");
+#[cfg(not(feature = "testnet"))]
 const NEAR_SOCIAL_ACCOUNT_ID: &near_sdk::AccountIdRef =
     near_sdk::AccountIdRef::new_or_panic("social.near");
+#[cfg(not(feature = "testnet"))]
 const WIDGET_REFERENCE_ACCOUNT_ID: &near_sdk::AccountIdRef =
     near_sdk::AccountIdRef::new_or_panic("bootstrap.treasury-factory.near");
+#[cfg(not(feature = "testnet"))]
+const SPUTNIK_DAO_FACTORY_ACCOUNT_ID: &near_sdk::AccountIdRef =
+    near_sdk::AccountIdRef::new_or_panic("sputnik-dao.near");
+
+#[cfg(feature = "testnet")]
+const TREASURY_FACTORY_ACCOUNT_ID: &near_sdk::AccountIdRef =
+    near_sdk::AccountIdRef::new_or_panic("treasury-factory.testnet");
+#[cfg(feature = "testnet")]
+const NEAR_SOCIAL_ACCOUNT_ID: &near_sdk::AccountIdRef =
+    near_sdk::AccountIdRef::new_or_panic("v1.social08.testnet");
+#[cfg(feature = "testnet")]
+const WIDGET_REFERENCE_ACCOUNT_ID: &near_sdk::AccountIdRef =
+    near_sdk::AccountIdRef::new_or_panic("bootstrap.treasury-factory.testnet");
+#[cfg(feature = "testnet")]
+const SPUTNIK_DAO_FACTORY_ACCOUNT_ID: &near_sdk::AccountIdRef =
+    near_sdk::AccountIdRef::new_or_panic("sputnikv2.testnet");
+
+// Kept outside of the contract struct, since existing instances have no state
+const AUTO_UPDATE_STORAGE_KEY: &[u8] = b"auto_update";
+// Written by the minimum self upgrade contract the instance account is created with
+const FACTORY_ACCOUNT_ID_STORAGE_KEY: &[u8] = b"factory_account_id";
+// Written when the factory updates the widgets of the instance
+const SOCIAL_DB_ACCOUNT_ID_STORAGE_KEY: &[u8] = b"social_db_account_id";
+const WIDGET_REFERENCE_ACCOUNT_ID_STORAGE_KEY: &[u8] = b"widget_reference_account_id";
+const SPUTNIK_DAO_FACTORY_ACCOUNT_ID_STORAGE_KEY: &[u8] = b"sputnik_dao_factory_account_id";
+
+/// The account id stored under `key`, or `default` for instances created before the
+/// factory passed it.
+fn stored_account_id(key: &[u8], default: &near_sdk::AccountIdRef) -> near_sdk::AccountId {
+    env::storage_read(key)
+        .and_then(|account_id| String::from_utf8(account_id).ok())
+        .and_then(|account_id| account_id.parse().ok())
+        .unwrap_or_else(|| default.into())
+}
+
+fn factory_account_id() -> near_sdk::AccountId {
+    stored_account_id(FACTORY_ACCOUNT_ID_STORAGE_KEY, TREASURY_FACTORY_ACCOUNT_ID)
+}
+
+fn social_db_account_id() -> near_sdk::AccountId {
+    stored_account_id(SOCIAL_DB_ACCOUNT_ID_STORAGE_KEY, NEAR_SOCIAL_ACCOUNT_ID)
+}
+
+fn widget_reference_account_id() -> near_sdk::AccountId {
+    stored_account_id(
+        WIDGET_REFERENCE_ACCOUNT_ID_STORAGE_KEY,
+        WIDGET_REFERENCE_ACCOUNT_ID,
+    )
+}
+
+fn sputnik_dao_factory_account_id() -> near_sdk::AccountId {
+    stored_account_id(
+        SPUTNIK_DAO_FACTORY_ACCOUNT_ID_STORAGE_KEY,
+        SPUTNIK_DAO_FACTORY_ACCOUNT_ID,
+    )
+}
+
+/// The name of the DAO of the instance `account_id`, which is the account id without its
+/// top-level account and with `-` in place of the dots: `treasury.near` is managed by
+/// `treasury.<sputnik factory>`, `treasury.myorg.near` by `treasury-myorg.<sputnik factory>`.
+fn dao_name(account_id: &str) -> String {
+    account_id
+        .rsplit_once('.')
+        .map_or(account_id, |(name, _)| name)
+        .replace('.', "-")
+}
+
+/// Branding of the instance in its social metadata, in place of the defaults.
+#[near(serializers = [json])]
+#[derive(Default)]
+pub struct SocialMetadata {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub ipfs_cid: Option<String>,
+    /// Added to the `app` and `neartreasury` tags
+    pub tags: Option<Vec<String>>,
+}
+
+/// Replaces every value of a social db tree with `null`, which deletes it when set.
+fn remove_values(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(children) => children.values_mut().for_each(remove_values),
+        _ => *value = serde_json::Value::Null,
+    }
+}
 
 // Define the contract structure
 #[near(contract_state)]
@@ -19,37 +112,191 @@ pub struct Contract {}
 // Implement the contract structure
 #[near]
 impl Contract {
-    pub fn self_upgrade(&mut self) {
-        const TREASURY_FACTORY_ACCOUNT_ID: &str = "treasury-factory.near";
-        Promise::new(TREASURY_FACTORY_ACCOUNT_ID.parse().unwrap())
-            .function_call(
-                "get_web4_contract_bytes".to_string(),
-                vec![],
-                NearToken::from_near(0),
-                Gas::from_tgas(200),
-            )
-            .then(Self::ext(env::current_account_id()).self_upgrade_callback());
+    pub fn self_upgrade(&mut self) -> Promise {
+        self.internal_self_upgrade(None)
+            .then(Self::internal_report_upgrade(None))
+    }
+
+    /**
+     * Upgrade to a specific web4 release of the factory, to pin or roll back a version.
+     * Only callable by the instance account itself, since it can downgrade the contract.
+     */
+    #[private]
+    pub fn self_upgrade_to_version(&mut self, version: String) -> Promise {
+        self.internal_self_upgrade(Some(version.clone()))
+            .then(Self::internal_report_upgrade(Some(version)))
+    }
+
+    /**
+     * Switches to the global contract of the release if the factory published one,
+     * and otherwise fetches and deploys the wasm bytes of the release
+     */
+    #[private]
+    pub fn self_upgrade_global_contract_callback(&mut self, version: Option<String>) -> Promise {
+        let code_hash = match env::promise_result(0) {
+            PromiseResult::Successful(result) => {
+                serde_json::from_slice::<Option<Base58CryptoHash>>(&result)
+                    .ok()
+                    .flatten()
+            }
+            _ => None,
+        };
+        match code_hash {
+            Some(code_hash) => Promise::new(env::current_account_id())
+                .use_global_contract(CryptoHash::from(code_hash).to_vec())
+                .then(Self::internal_upgraded_callback(version, code_hash)),
+            None => Promise::new(factory_account_id())
+                .function_call(
+                    "get_web4_contract_bytes".to_string(),
+                    match &version {
+                        Some(version) => serde_json::json!({ "version": version })
+                            .to_string()
+                            .into_bytes(),
+                        None => vec![],
+                    },
+                    NearToken::from_near(0),
+                    Gas::from_tgas(200),
+                )
+                .then(Self::ext(env::current_account_id()).self_upgrade_callback(version)),
+        }
+    }
+
+    /**
+     * Opt in or out of upgrades pushed by the factory, callable by the instance or its DAO
+     */
+    pub fn set_auto_update(&mut self, auto_update: bool) {
+        self.assert_self_or_dao();
+        if auto_update {
+            env::storage_write(AUTO_UPDATE_STORAGE_KEY, &[1]);
+        } else {
+            env::storage_remove(AUTO_UPDATE_STORAGE_KEY);
+        }
+    }
+
+    pub fn get_auto_update(&self) -> bool {
+        env::storage_has_key(AUTO_UPDATE_STORAGE_KEY)
+    }
+
+    /**
+     * Upgrade pushed by the factory, returns false if the instance has not opted into auto-update
+     */
+    pub fn factory_upgrade(&mut self, version: Option<String>) -> PromiseOrValue<bool> {
+        let factory_account_id = factory_account_id();
+        if env::predecessor_account_id() != factory_account_id {
+            env::panic_str(&format!("Should only be called by {}", factory_account_id));
+        }
+        if !self.get_auto_update() {
+            return PromiseOrValue::Value(false);
+        }
+        PromiseOrValue::Promise(self.internal_self_upgrade(version))
     }
 
     #[private]
-    pub fn self_upgrade_callback(&mut self) -> Promise {
+    pub fn self_upgrade_callback(&mut self, version: Option<String>) -> Promise {
         match env::promise_result(0) {
             PromiseResult::Successful(web4_contract_bytes) => {
-                Promise::new(env::current_account_id()).deploy_contract(web4_contract_bytes)
+                let code_hash = env::sha256_array(&web4_contract_bytes).into();
+                Promise::new(env::current_account_id())
+                    .deploy_contract(web4_contract_bytes)
+                    .then(Self::internal_upgraded_callback(version, code_hash))
             }
             _ => env::panic_str("No web4 contract bytes in promise result"),
         }
     }
 
+    /**
+     * Runs with the contract the instance upgraded to, once it is deployed
+     */
+    #[private]
+    pub fn upgraded_callback(&mut self, version: Option<String>, code_hash: Base58CryptoHash) {
+        match env::promise_result(0) {
+            PromiseResult::Successful(_) => {
+                Web4Event::InstanceUpgraded { version, code_hash }.emit()
+            }
+            _ => env::panic_str("Failed to deploy the web4 contract"),
+        }
+    }
+
+    /**
+     * Retire the instance, callable by the instance or its DAO through an approved proposal.
+     * Removes the widgets from the social db and withdraws its storage deposit, notifies the
+     * factory and deletes the instance account in favour of `beneficiary_id`.
+     */
+    pub fn decommission(&mut self, beneficiary_id: near_sdk::AccountId) -> Promise {
+        self.assert_self_or_dao();
+        let current_account_id = env::current_account_id();
+        Promise::new(social_db_account_id())
+            .function_call(
+                "get".to_string(),
+                serde_json::json!({
+                    "keys": [format!("{}/widget/**", current_account_id)]
+                })
+                .to_string()
+                .into_bytes(),
+                NearToken::from_near(0),
+                Gas::from_tgas(10),
+            )
+            .then(Self::ext(current_account_id).decommission_callback(beneficiary_id))
+    }
+
+    #[private]
+    pub fn decommission_callback(&mut self, beneficiary_id: near_sdk::AccountId) -> Promise {
+        let mut widgets = match env::promise_result(0) {
+            PromiseResult::Successful(result) => {
+                serde_json::from_slice::<serde_json::Value>(&result)
+                    .unwrap_or_else(|_| env::panic_str("Invalid widget data"))
+            }
+            _ => env::panic_str("Failed to get the widgets of the instance"),
+        };
+        remove_values(&mut widgets);
+        let current_account_id = env::current_account_id();
+        let social_db_account_id = social_db_account_id();
+
+        Web4Event::Decommissioned {
+            beneficiary_id: beneficiary_id.clone(),
+        }
+        .emit();
+
+        Promise::new(social_db_account_id.clone())
+            .function_call(
+                "set".to_string(),
+                serde_json::json!({ "data": widgets })
+                    .to_string()
+                    .into_bytes(),
+                NearToken::from_near(0),
+                Gas::from_tgas(20),
+            )
+            .then(Promise::new(social_db_account_id).function_call(
+                "storage_withdraw".to_string(),
+                b"{}".to_vec(),
+                NearToken::from_yoctonear(1),
+                Gas::from_tgas(10),
+            ))
+            .then(
+                Promise::new(factory_account_id()).function_call(
+                    "on_instance_decommissioned".to_string(),
+                    serde_json::json!({ "beneficiary_id": beneficiary_id })
+                        .to_string()
+                        .into_bytes(),
+                    NearToken::from_near(0),
+                    Gas::from_tgas(10),
+                ),
+            )
+            // Runs even if the factory does not know the instance
+            .then(Promise::new(current_account_id).delete_account(beneficiary_id))
+    }
+
     /**
      * Update app widget only, callable by anyone
      */
     #[payable]
     pub fn update_app_widget(&mut self) -> Promise {
         let current_account_id = env::current_account_id();
+        let widget_reference_account_id = widget_reference_account_id();
+        let social_db_account_id = social_db_account_id();
 
-        let key = format!("{}/widget/app", WIDGET_REFERENCE_ACCOUNT_ID);
-        let promise = Promise::new(NEAR_SOCIAL_ACCOUNT_ID.into())
+        let key = format!("{}/widget/app", widget_reference_account_id);
+        Promise::new(social_db_account_id.clone())
             .function_call(
                 "get".to_string(),
                 serde_json::json!({ "keys": [key] })
@@ -59,29 +306,50 @@ impl Contract {
                 Gas::from_tgas(10),
             )
             .then(Self::ext(current_account_id).update_widgets_callback(
-                WIDGET_REFERENCE_ACCOUNT_ID.into(),
-                NEAR_SOCIAL_ACCOUNT_ID.into(),
+                widget_reference_account_id,
+                social_db_account_id,
                 env::attached_deposit(),
-            ));
-        promise
+            ))
     }
 
+    /**
+     * Deploy the widgets of the reference account, callable by the factory or the instance.
+     * The accounts passed in are kept for the later calls of the instance, such as
+     * `update_app_widget`, `decommission` and the DAO check.
+     */
     #[payable]
     pub fn update_widgets(
         &mut self,
         widget_reference_account_id: near_sdk::AccountId,
         social_db_account_id: near_sdk::AccountId,
         set_social_metadata_defaults: Option<bool>,
+        social_metadata: Option<SocialMetadata>,
+        sputnik_dao_factory_account_id: Option<near_sdk::AccountId>,
     ) -> Promise {
         let current_account_id = env::current_account_id();
-        if !(env::predecessor_account_id() == TREASURY_FACTORY_ACCOUNT_ID
+        let factory_account_id = factory_account_id();
+        if !(env::predecessor_account_id() == factory_account_id
             || env::predecessor_account_id() == current_account_id)
         {
             env::panic_str(&format!(
                 "Should only be called by {} or {}",
-                TREASURY_FACTORY_ACCOUNT_ID, current_account_id
+                factory_account_id, current_account_id
             ));
         }
+        env::storage_write(
+            WIDGET_REFERENCE_ACCOUNT_ID_STORAGE_KEY,
+            widget_reference_account_id.as_bytes(),
+        );
+        env::storage_write(
+            SOCIAL_DB_ACCOUNT_ID_STORAGE_KEY,
+            social_db_account_id.as_bytes(),
+        );
+        if let Some(sputnik_dao_factory_account_id) = sputnik_dao_factory_account_id {
+            env::storage_write(
+                SPUTNIK_DAO_FACTORY_ACCOUNT_ID_STORAGE_KEY,
+                sputnik_dao_factory_account_id.as_bytes(),
+            );
+        }
         let mut promise = Promise::new(social_db_account_id.clone())
             .function_call(
                 "get".to_string(),
@@ -99,12 +367,20 @@ impl Contract {
                 env::attached_deposit(),
             ));
 
-        if set_social_metadata_defaults.unwrap_or(false) {
+        // Branding passed by the factory is written along with the defaults it overrides
+        if set_social_metadata_defaults.unwrap_or(false) || social_metadata.is_some() {
+            let SocialMetadata {
+                name,
+                description,
+                ipfs_cid,
+                tags,
+            } = social_metadata.unwrap_or_default();
             promise = promise.then(self.internal_set_social_metadata(
                 Some(social_db_account_id),
-                None,
-                None,
-                None,
+                name,
+                description,
+                ipfs_cid,
+                tags,
             ));
         }
         promise
@@ -126,17 +402,42 @@ impl Contract {
                 );
                 let args = "{\"data\": ".to_string() + new_widget.as_str() + "}";
 
-                Promise::new(social_db_account_id).function_call(
-                    "set".to_string(),
-                    args.into_bytes(),
-                    deposit_amount,
-                    Gas::from_tgas(10),
-                )
+                Promise::new(social_db_account_id.clone())
+                    .function_call(
+                        "set".to_string(),
+                        args.into_bytes(),
+                        deposit_amount,
+                        Gas::from_tgas(10),
+                    )
+                    .then(
+                        Self::ext(env::current_account_id())
+                            .with_static_gas(Gas::from_tgas(5))
+                            .widgets_set_callback(
+                                widget_reference_account_id,
+                                social_db_account_id,
+                            ),
+                    )
             }
             _ => env::panic_str("Failed to get reference widget data"),
         }
     }
 
+    #[private]
+    pub fn widgets_set_callback(
+        &mut self,
+        widget_reference_account_id: near_sdk::AccountId,
+        social_db_account_id: near_sdk::AccountId,
+    ) {
+        match env::promise_result(0) {
+            PromiseResult::Successful(_) => Web4Event::WidgetsUpdated {
+                widget_reference_account_id,
+                social_db_account_id,
+            }
+            .emit(),
+            _ => env::panic_str("Failed to set the widgets in the social db"),
+        }
+    }
+
     #[private]
     pub fn set_social_metadata(
         &mut self,
@@ -144,8 +445,61 @@ impl Contract {
         name: Option<String>,
         description: Option<String>,
         ipfs_cid: Option<String>,
+        tags: Option<Vec<String>>,
     ) -> Promise {
-        self.internal_set_social_metadata(social_db_account_id, name, description, ipfs_cid)
+        self.internal_set_social_metadata(social_db_account_id, name, description, ipfs_cid, tags)
+    }
+
+    fn assert_self_or_dao(&self) {
+        let current_account_id = env::current_account_id();
+        let dao_account_id = format!(
+            "{}.{}",
+            dao_name(current_account_id.as_str()),
+            sputnik_dao_factory_account_id()
+        );
+        let predecessor_account_id = env::predecessor_account_id();
+        if !(predecessor_account_id == current_account_id
+            || predecessor_account_id.as_str() == dao_account_id)
+        {
+            env::panic_str(&format!(
+                "Should only be called by {} or {}",
+                current_account_id, dao_account_id
+            ));
+        }
+    }
+
+    fn internal_self_upgrade(&mut self, version: Option<String>) -> Promise {
+        Promise::new(factory_account_id())
+            .function_call(
+                "get_web4_global_contract_hash".to_string(),
+                serde_json::json!({ "version": version })
+                    .to_string()
+                    .into_bytes(),
+                NearToken::from_near(0),
+                Gas::from_tgas(10),
+            )
+            .then(
+                Self::ext(env::current_account_id()).self_upgrade_global_contract_callback(version),
+            )
+    }
+
+    fn internal_upgraded_callback(version: Option<String>, code_hash: Base58CryptoHash) -> Promise {
+        Self::ext(env::current_account_id())
+            .with_static_gas(Gas::from_tgas(5))
+            .upgraded_callback(version, code_hash)
+    }
+
+    /// Tells the factory which release the instance upgraded itself to, since it only
+    /// keeps track of the upgrades it pushes.
+    fn internal_report_upgrade(version: Option<String>) -> Promise {
+        Promise::new(factory_account_id()).function_call(
+            "on_instance_upgraded".to_string(),
+            serde_json::json!({ "version": version })
+                .to_string()
+                .into_bytes(),
+            NearToken::from_near(0),
+            Gas::from_tgas(10),
+        )
     }
 
     fn internal_set_social_metadata(
@@ -154,17 +508,23 @@ impl Contract {
         name: Option<String>,
         description: Option<String>,
         ipfs_cid: Option<String>,
+        tags: Option<Vec<String>>,
     ) -> Promise {
         let current_account_id = env::current_account_id();
-        let social_db_account_id = social_db_account_id
-            .as_deref()
-            .unwrap_or(NEAR_SOCIAL_ACCOUNT_ID);
+        let social_db_account_id = social_db_account_id.unwrap_or_else(crate::social_db_account_id);
         let name = name.as_deref().unwrap_or("NEAR Treasury");
         let description =
             description.unwrap_or_else(|| format!("NEAR Treasury / {}", current_account_id));
         let ipfs_cid = ipfs_cid
             .as_deref()
             .unwrap_or("bafkreiefdkigadpkpccreqfnhut2li2nmf3alhz7c3wadveconelisnksu");
+        let mut tags_json = serde_json::json!({
+            "app": "",
+            "neartreasury": ""
+        });
+        for tag in tags.unwrap_or_default() {
+            tags_json[tag] = serde_json::json!("");
+        }
 
         let args = serde_json::json!({
             "data": {
@@ -177,10 +537,7 @@ impl Contract {
                                 "image": {
                                     "ipfs_cid": ipfs_cid
                                 },
-                                "tags": {
-                                    "app": "",
-                                    "neartreasury": ""
-                                }
+                                "tags": tags_json
                             }
                         }
                     }
@@ -188,7 +545,7 @@ impl Contract {
             }
         });
 
-        Promise::new(social_db_account_id.to_owned()).function_call(
+        Promise::new(social_db_account_id).function_call(
             "set".to_string(),
             args.to_string().into_bytes(),
             NearToken::from_near(0),
@@ -215,7 +572,8 @@ impl Contract {
 
         let current_account_id = env::current_account_id();
         let metadata_preload_url = format!(
-            "/web4/contract/social.near/get?keys.json=%5B%22{}/widget/app/metadata/**%22%5D",
+            "/web4/contract/{}/get?keys.json=%5B%22{}/widget/app/metadata/**%22%5D",
+            social_db_account_id(),
             current_account_id
         );
 
@@ -398,4 +756,155 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn auto_update_can_be_set_by_the_dao() {
+        let context = VMContextBuilder::new()
+            .current_account_id("not-only-devhub.near".parse().unwrap())
+            .predecessor_account_id("not-only-devhub.sputnik-dao.near".parse().unwrap())
+            .build();
+        testing_env!(context);
+        let mut contract = Contract::default();
+        assert!(!contract.get_auto_update());
+        contract.set_auto_update(true);
+        assert!(contract.get_auto_update());
+    }
+
+    #[test]
+    fn auto_update_can_be_set_by_the_dao_of_an_instance_under_a_parent_account() {
+        let context = VMContextBuilder::new()
+            .current_account_id("treasury.myorg.near".parse().unwrap())
+            .predecessor_account_id("treasury-myorg.sputnik-dao.near".parse().unwrap())
+            .build();
+        testing_env!(context);
+        let mut contract = Contract::default();
+        contract.set_auto_update(true);
+        assert!(contract.get_auto_update());
+    }
+
+    #[test]
+    #[should_panic(expected = "Should only be called by not-only-devhub.near")]
+    fn auto_update_cannot_be_set_by_others() {
+        let context = VMContextBuilder::new()
+            .current_account_id("not-only-devhub.near".parse().unwrap())
+            .predecessor_account_id("someone.near".parse().unwrap())
+            .build();
+        testing_env!(context);
+        Contract::default().set_auto_update(true);
+    }
+
+    #[test]
+    fn decommission_can_be_called_by_the_dao() {
+        let context = VMContextBuilder::new()
+            .current_account_id("not-only-devhub.near".parse().unwrap())
+            .predecessor_account_id("not-only-devhub.sputnik-dao.near".parse().unwrap())
+            .build();
+        testing_env!(context);
+        Contract::default().decommission("not-only-devhub.sputnik-dao.near".parse().unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "Should only be called by not-only-devhub.near")]
+    fn decommission_cannot_be_called_by_others() {
+        let context = VMContextBuilder::new()
+            .current_account_id("not-only-devhub.near".parse().unwrap())
+            .predecessor_account_id("someone.near".parse().unwrap())
+            .build();
+        testing_env!(context);
+        Contract::default().decommission("someone.near".parse().unwrap());
+    }
+
+    #[test]
+    fn remove_values_deletes_every_widget_key() {
+        let mut widgets = serde_json::json!({
+            "not-only-devhub.near": {
+                "widget": {
+                    "app": { "": "code", "metadata": { "name": "Treasury" } },
+                    "config": "config"
+                }
+            }
+        });
+        remove_values(&mut widgets);
+        assert_eq!(
+            widgets,
+            serde_json::json!({
+                "not-only-devhub.near": {
+                    "widget": {
+                        "app": { "": null, "metadata": { "name": null } },
+                        "config": null
+                    }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn factory_upgrade_is_refused_without_auto_update() {
+        let context = VMContextBuilder::new()
+            .current_account_id("not-only-devhub.near".parse().unwrap())
+            .predecessor_account_id(TREASURY_FACTORY_ACCOUNT_ID.into())
+            .build();
+        testing_env!(context);
+        assert!(matches!(
+            Contract::default().factory_upgrade(None),
+            PromiseOrValue::Value(false)
+        ));
+    }
+
+    #[test]
+    fn accounts_passed_at_creation_are_used_in_place_of_the_defaults() {
+        let context = VMContextBuilder::new()
+            .current_account_id("not-only-devhub.custom".parse().unwrap())
+            .predecessor_account_id("treasury-factory.custom".parse().unwrap())
+            .build();
+        testing_env!(context);
+        env::storage_write(FACTORY_ACCOUNT_ID_STORAGE_KEY, b"treasury-factory.custom");
+        let mut contract = Contract::default();
+        contract.update_widgets(
+            "bootstrap.treasury-factory.custom".parse().unwrap(),
+            "social.custom".parse().unwrap(),
+            Some(true),
+            None,
+            Some("sputnik-dao.custom".parse().unwrap()),
+        );
+        assert_eq!(social_db_account_id().as_str(), "social.custom");
+        assert_eq!(
+            widget_reference_account_id().as_str(),
+            "bootstrap.treasury-factory.custom"
+        );
+
+        let response =
+            contract.web4_get(serde_json::from_value(serde_json::json!({ "path": "/" })).unwrap());
+        match response {
+            Web4Response::PreloadUrls { preload_urls } => {
+                assert_eq!(
+                    preload_urls,
+                    vec!["/web4/contract/social.custom/get?keys.json=%5B%22not-only-devhub.custom/widget/app/metadata/**%22%5D"]
+                );
+            }
+            _ => {
+                panic!("Should return Web4Response::PreloadUrls");
+            }
+        }
+
+        let context = VMContextBuilder::new()
+            .current_account_id("not-only-devhub.custom".parse().unwrap())
+            .predecessor_account_id("not-only-devhub.sputnik-dao.custom".parse().unwrap())
+            .build();
+        testing_env!(context);
+        contract.set_auto_update(true);
+        assert!(contract.get_auto_update());
+    }
+
+    #[test]
+    #[should_panic(expected = "Should only be called by treasury-factory.custom")]
+    fn factory_upgrade_is_only_accepted_from_the_stored_factory() {
+        let context = VMContextBuilder::new()
+            .current_account_id("not-only-devhub.custom".parse().unwrap())
+            .predecessor_account_id(TREASURY_FACTORY_ACCOUNT_ID.into())
+            .build();
+        testing_env!(context);
+        env::storage_write(FACTORY_ACCOUNT_ID_STORAGE_KEY, b"treasury-factory.custom");
+        let _ = Contract::default().factory_upgrade(None);
+    }
 }
@@ -107,6 +107,40 @@ async fn test_social_metadata() -> Result<(), Box<dyn std::error::Error>> {
         metadata["image"]["ipfs_cid"],
         "bafkreiefdkigadpkpccreqfnhut2li2nmf3alhz7c3wadveconelisnksu"
     );
+    assert_eq!(metadata["tags"], json!({"app": "", "neartreasury": ""}));
+
+    let set_branding_result = contract
+        .call("set_social_metadata")
+        .args_json(json!({
+            "name": "Org Treasury",
+            "description": "Funds of the org",
+            "ipfs_cid": "bafkreib3c4mlrkeyfjvg4fspb5k3w6eeo2f2wp6qtfadfz3i2qqbqkyxeq",
+            "tags": ["grants"]
+        }))
+        .max_gas()
+        .transact()
+        .await?;
+    assert!(set_branding_result.is_success());
+    let social_metadata = socialdb
+        .call("get")
+        .args_json(json!({
+            "keys": [format!("{}/widget/app/metadata/**", contract.id().as_str())]
+        }))
+        .view()
+        .await?;
+    let social_metadata_json: Value =
+        Value::from_str(String::from_utf8(social_metadata.result).unwrap().as_str()).unwrap();
+    let metadata = &social_metadata_json[contract.id().as_str()]["widget"]["app"]["metadata"];
+    assert_eq!(metadata["name"], "Org Treasury");
+    assert_eq!(metadata["description"], "Funds of the org");
+    assert_eq!(
+        metadata["image"]["ipfs_cid"],
+        "bafkreib3c4mlrkeyfjvg4fspb5k3w6eeo2f2wp6qtfadfz3i2qqbqkyxeq"
+    );
+    assert_eq!(
+        metadata["tags"],
+        json!({"app": "", "neartreasury": "", "grants": ""})
+    );
 
     Ok(())
 }
@@ -179,7 +213,7 @@ async fn test_update_widgets() -> Result<(), Box<dyn std::error::Error>> {
         .await?;
     let reference_widgets_json_string = String::from_utf8(reference_widgets.result).unwrap();
 
-    instance_account
+    let update_widgets_result = instance_account
         .call(instance_account.id(), "update_widgets")
         .args_json(json!({
             "widget_reference_account_id": WIDGET_REFERENCE_ACCOUNT_ID,
@@ -191,6 +225,10 @@ async fn test_update_widgets() -> Result<(), Box<dyn std::error::Error>> {
         .await?
         .into_result()
         .unwrap();
+    assert!(update_widgets_result.logs().contains(&format!(
+        r#"EVENT_JSON:{{"standard":"treasury-web4","version":"1.0.0","event":"widgets_updated","data":{{"widget_reference_account_id":"{}","social_db_account_id":"{}"}}}}"#,
+        WIDGET_REFERENCE_ACCOUNT_ID, SOCIALDB_ACCOUNT
+    ).as_str()));
 
     let deployed_widgets = socialdb
         .call("get")
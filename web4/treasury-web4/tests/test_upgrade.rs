@@ -55,6 +55,9 @@ async fn test_upgrade() -> Result<(), Box<dyn std::error::Error>> {
         self_upgrade_result.total_gas_burnt.as_tgas(),
         self_upgrade_result.receipt_failures()
     );
+    assert!(self_upgrade_result.logs().iter().any(|log| log.starts_with(
+        r#"EVENT_JSON:{"standard":"treasury-web4","version":"1.0.0","event":"instance_upgraded","data":{"version":null,"code_hash":"#
+    )));
 
     let body_string = &call_web4_get_with_preload_result(contract).await.unwrap()[..15];
     assert_eq!(body_string, "<!UPGRADE html>");